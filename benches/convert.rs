@@ -0,0 +1,115 @@
+//! Compares the slice conversions in `symphonium::convert` against converting
+//! one sample at a time.
+//!
+//! Run with `cargo bench --bench convert`. Build with
+//! `--no-default-features --features wav,pcm,resampler` to compare against
+//! the scalar fallbacks.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use symphonium::convert;
+
+const LEN: usize = 48_000;
+const ITERS: u32 = 500;
+
+fn time(mut f: impl FnMut()) -> Duration {
+    // Warm up.
+    for _ in 0..10 {
+        f();
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERS {
+        f();
+    }
+    start.elapsed() / ITERS
+}
+
+fn bench<T: Copy>(name: &str, src: &[T], per_sample: fn(T) -> f32, slice: fn(&[T], &mut [f32])) {
+    let mut dst = vec![0.0; src.len()];
+
+    let scalar = time(|| {
+        for (out_s, &s) in dst.iter_mut().zip(black_box(src)) {
+            *out_s = per_sample(s);
+        }
+        black_box(&mut dst);
+    });
+
+    let batched = time(|| {
+        slice(black_box(src), &mut dst);
+        black_box(&mut dst);
+    });
+
+    println!(
+        "{:<8} per-sample: {:>10.2?}  slice: {:>10.2?}  ({:.2}x)",
+        name,
+        scalar,
+        batched,
+        scalar.as_secs_f64() / batched.as_secs_f64()
+    );
+}
+
+fn main() {
+    let u8s: Vec<u8> = (0..LEN).map(|i| i as u8).collect();
+    let u16s: Vec<u16> = (0..LEN).map(|i| (i * 7) as u16).collect();
+    let i8s: Vec<i8> = (0..LEN).map(|i| i as i8).collect();
+    let i16s: Vec<i16> = (0..LEN).map(|i| (i * 7) as i16).collect();
+    let i32s: Vec<i32> = (0..LEN).map(|i| (i as i32).wrapping_mul(89_477)).collect();
+    let u32s: Vec<u32> = i32s.iter().map(|&s| s as u32).collect();
+    let b24s: Vec<[u8; 3]> = (0..LEN)
+        .map(|i| {
+            let b = (i as u32 * 349).to_ne_bytes();
+            [b[0], b[1], b[2]]
+        })
+        .collect();
+
+    bench(
+        "u8",
+        &u8s,
+        convert::pcm_u8_to_f32,
+        convert::pcm_u8_slice_to_f32,
+    );
+    bench(
+        "u16",
+        &u16s,
+        convert::pcm_u16_to_f32,
+        convert::pcm_u16_slice_to_f32,
+    );
+    bench(
+        "u24",
+        &b24s,
+        convert::pcm_u24_to_f32_ne,
+        convert::pcm_u24_slice_to_f32_ne,
+    );
+    bench(
+        "u32",
+        &u32s,
+        convert::pcm_u32_to_f32,
+        convert::pcm_u32_slice_to_f32,
+    );
+    bench(
+        "i8",
+        &i8s,
+        convert::pcm_i8_to_f32,
+        convert::pcm_i8_slice_to_f32,
+    );
+    bench(
+        "i16",
+        &i16s,
+        convert::pcm_i16_to_f32,
+        convert::pcm_i16_slice_to_f32,
+    );
+    bench(
+        "i24",
+        &b24s,
+        convert::pcm_i24_to_f32_ne,
+        convert::pcm_i24_slice_to_f32_ne,
+    );
+    bench(
+        "i32",
+        &i32s,
+        convert::pcm_i32_to_f32,
+        convert::pcm_i32_slice_to_f32,
+    );
+}
@@ -0,0 +1,645 @@
+use symphonia::core::audio::Channels;
+
+/// The gain of a channel that is split equally between two speakers
+/// (-3 dB), as used by the ITU-R BS.775 downmix equations.
+const MINUS_3_DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// The speaker position of a single channel.
+///
+/// The variants are listed in the same order as Microsoft's
+/// WAVEFORMATEXTENSIBLE channel mask (which is also the order that
+/// Symphonia uses).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChannelPosition {
+    FrontLeft,
+    FrontRight,
+    /// The front center channel. This is also used for the single channel of
+    /// a mono resource.
+    FrontCenter,
+    LowFrequency,
+    RearLeft,
+    RearRight,
+    FrontLeftOfCenter,
+    FrontRightOfCenter,
+    RearCenter,
+    SideLeft,
+    SideRight,
+    TopCenter,
+    TopFrontLeft,
+    TopFrontCenter,
+    TopFrontRight,
+    TopRearLeft,
+    TopRearCenter,
+    TopRearRight,
+    RearLeftOfCenter,
+    RearRightOfCenter,
+    FrontLeftWide,
+    FrontRightWide,
+    FrontLeftHigh,
+    FrontCenterHigh,
+    FrontRightHigh,
+    LowFrequency2,
+    /// A channel with no known speaker position.
+    Unknown,
+}
+
+impl ChannelPosition {
+    /// Convert a single channel from a Symphonia [`Channels`] bitmask.
+    ///
+    /// Returns [`ChannelPosition::Unknown`] if `channel` does not contain
+    /// exactly one known channel.
+    pub fn from_symphonia(channel: Channels) -> Self {
+        use ChannelPosition::*;
+
+        match channel {
+            Channels::FRONT_LEFT => FrontLeft,
+            Channels::FRONT_RIGHT => FrontRight,
+            Channels::FRONT_CENTRE => FrontCenter,
+            Channels::LFE1 => LowFrequency,
+            Channels::REAR_LEFT => RearLeft,
+            Channels::REAR_RIGHT => RearRight,
+            Channels::FRONT_LEFT_CENTRE => FrontLeftOfCenter,
+            Channels::FRONT_RIGHT_CENTRE => FrontRightOfCenter,
+            Channels::REAR_CENTRE => RearCenter,
+            Channels::SIDE_LEFT => SideLeft,
+            Channels::SIDE_RIGHT => SideRight,
+            Channels::TOP_CENTRE => TopCenter,
+            Channels::TOP_FRONT_LEFT => TopFrontLeft,
+            Channels::TOP_FRONT_CENTRE => TopFrontCenter,
+            Channels::TOP_FRONT_RIGHT => TopFrontRight,
+            Channels::TOP_REAR_LEFT => TopRearLeft,
+            Channels::TOP_REAR_CENTRE => TopRearCenter,
+            Channels::TOP_REAR_RIGHT => TopRearRight,
+            Channels::REAR_LEFT_CENTRE => RearLeftOfCenter,
+            Channels::REAR_RIGHT_CENTRE => RearRightOfCenter,
+            Channels::FRONT_LEFT_WIDE => FrontLeftWide,
+            Channels::FRONT_RIGHT_WIDE => FrontRightWide,
+            Channels::FRONT_LEFT_HIGH => FrontLeftHigh,
+            Channels::FRONT_CENTRE_HIGH => FrontCenterHigh,
+            Channels::FRONT_RIGHT_HIGH => FrontRightHigh,
+            Channels::LFE2 => LowFrequency2,
+            _ => Unknown,
+        }
+    }
+
+    /// The surround channel on the same side as this one, if this is a
+    /// surround channel.
+    fn surround_pair(&self) -> Option<ChannelPosition> {
+        use ChannelPosition::*;
+
+        match self {
+            SideLeft => Some(RearLeft),
+            SideRight => Some(RearRight),
+            RearLeft => Some(SideLeft),
+            RearRight => Some(SideRight),
+            _ => None,
+        }
+    }
+
+    /// Whether or not this is a low-frequency effects channel.
+    pub fn is_lfe(&self) -> bool {
+        matches!(self, Self::LowFrequency | Self::LowFrequency2)
+    }
+
+    /// The positions (and gains) this channel is folded into when it does
+    /// not exist in the target layout of a downmix.
+    ///
+    /// Each position folds one step closer to the front center so that
+    /// repeatedly folding always ends up in a channel of a mono or stereo
+    /// layout.
+    fn fold(&self) -> &'static [(ChannelPosition, f32)] {
+        use ChannelPosition::*;
+
+        match self {
+            SideLeft | RearLeft => &[(FrontLeft, MINUS_3_DB)],
+            SideRight | RearRight => &[(FrontRight, MINUS_3_DB)],
+            FrontLeft => &[(FrontCenter, MINUS_3_DB)],
+            FrontRight => &[(FrontCenter, MINUS_3_DB)],
+            FrontCenter => &[(FrontLeft, MINUS_3_DB), (FrontRight, MINUS_3_DB)],
+            FrontLeftOfCenter | FrontLeftWide | FrontLeftHigh | TopFrontLeft => &[(FrontLeft, 1.0)],
+            FrontRightOfCenter | FrontRightWide | FrontRightHigh | TopFrontRight => {
+                &[(FrontRight, 1.0)]
+            }
+            FrontCenterHigh | TopFrontCenter | TopCenter => &[(FrontCenter, 1.0)],
+            RearLeftOfCenter | TopRearLeft => &[(RearLeft, 1.0)],
+            RearRightOfCenter | TopRearRight => &[(RearRight, 1.0)],
+            RearCenter | TopRearCenter => &[(RearLeft, MINUS_3_DB), (RearRight, MINUS_3_DB)],
+            LowFrequency2 => &[(LowFrequency, 1.0)],
+            // ITU-R BS.775 discards the LFE channel when downmixing.
+            LowFrequency | Unknown => &[],
+        }
+    }
+}
+
+/// The speaker positions of the channels in a resource, in the order that
+/// the channels are stored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChannelLayout {
+    positions: Vec<ChannelPosition>,
+}
+
+impl ChannelLayout {
+    /// Construct a layout from a list of speaker positions.
+    pub fn new(positions: Vec<ChannelPosition>) -> Self {
+        Self { positions }
+    }
+
+    /// A single mono channel.
+    pub fn mono() -> Self {
+        Self::new(vec![ChannelPosition::FrontCenter])
+    }
+
+    /// Left and right channels.
+    pub fn stereo() -> Self {
+        Self::new(vec![
+            ChannelPosition::FrontLeft,
+            ChannelPosition::FrontRight,
+        ])
+    }
+
+    /// 5.1 surround in WAV/SMPTE order (L, R, C, LFE, Ls, Rs).
+    pub fn surround_5_1() -> Self {
+        use ChannelPosition::*;
+
+        Self::new(vec![
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            LowFrequency,
+            SideLeft,
+            SideRight,
+        ])
+    }
+
+    /// 7.1 surround in WAV/SMPTE order (L, R, C, LFE, Lb, Rb, Ls, Rs).
+    pub fn surround_7_1() -> Self {
+        use ChannelPosition::*;
+
+        Self::new(vec![
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            LowFrequency,
+            RearLeft,
+            RearRight,
+            SideLeft,
+            SideRight,
+        ])
+    }
+
+    /// The layout that is assumed for a resource with the given number of
+    /// channels when no other information is available.
+    pub fn default_for_channels(channels: usize) -> Self {
+        match channels {
+            1 => Self::mono(),
+            2 => Self::stereo(),
+            6 => Self::surround_5_1(),
+            8 => Self::surround_7_1(),
+            _ => Self::new(vec![ChannelPosition::Unknown; channels]),
+        }
+    }
+
+    /// Construct a layout from a Symphonia [`Channels`] bitmask.
+    ///
+    /// Symphonia uses [`Channels::FRONT_LEFT`] for the channel of a mono
+    /// stream, so a single channel is always treated as
+    /// [`ChannelPosition::FrontCenter`].
+    pub fn from_symphonia(channels: Channels) -> Self {
+        if channels.count() == 1 {
+            return Self::mono();
+        }
+
+        Self::new(
+            channels
+                .iter()
+                .map(ChannelPosition::from_symphonia)
+                .collect(),
+        )
+    }
+
+    /// The number of channels in this layout.
+    pub fn channels(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// The speaker positions of the channels, in the order they are stored.
+    pub fn positions(&self) -> &[ChannelPosition] {
+        &self.positions
+    }
+
+    /// The index of the channel with the given speaker position.
+    pub fn index_of(&self, position: ChannelPosition) -> Option<usize> {
+        self.positions.iter().position(|p| *p == position)
+    }
+
+    /// Whether or not this layout consists of a single mono channel.
+    pub fn is_mono(&self) -> bool {
+        self.positions.len() == 1
+    }
+
+    /// For each channel in the canonical (WAV/SMPTE) order, the index of
+    /// that channel in this layout.
+    ///
+    /// Channels with an unknown position are placed last in their original
+    /// order.
+    pub fn canonical_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.positions.len()).collect();
+        order.sort_by_key(|&i| self.positions[i]);
+        order
+    }
+
+    /// The same channels sorted into the canonical (WAV/SMPTE) order.
+    pub fn to_canonical(&self) -> Self {
+        Self::new(
+            self.canonical_order()
+                .into_iter()
+                .map(|i| self.positions[i])
+                .collect(),
+        )
+    }
+
+    /// Whether or not the channels in this layout are in the canonical
+    /// (WAV/SMPTE) order.
+    pub fn is_canonical(&self) -> bool {
+        self.positions.windows(2).all(|w| w[0] <= w[1])
+    }
+}
+
+/// The convention a format uses to order the channels of common layouts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelOrder {
+    /// The order of the WAVEFORMATEXTENSIBLE channel mask, which is the same
+    /// as the SMPTE order used by FLAC (L, R, C, LFE, Ls, Rs for 5.1).
+    ///
+    /// This is the canonical order. Symphonia's decoders output the channels
+    /// of every format other than AIFF in this order (reordering Vorbis and
+    /// ALAC while decoding).
+    #[default]
+    Wav,
+    /// The order used by AIFF and AIFF-C (L, R, C for 3 channels and
+    /// L, Lc, C, R, Rc, S for 6 channels).
+    Aiff,
+}
+
+impl ChannelOrder {
+    /// The order used by the container of a file that starts with `header`.
+    ///
+    /// AIFF and AIFF-C files are recognized by their `FORM` chunk, regardless
+    /// of the codec of the samples. Every other container is assumed to use
+    /// the WAV order.
+    pub fn from_header(header: &[u8]) -> Self {
+        match header {
+            [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', b'F' | b'C', ..] => Self::Aiff,
+            _ => Self::Wav,
+        }
+    }
+
+    /// The layout of the channels as they are stored by a source with this
+    /// order, where `channels` is the channel mask reported by Symphonia.
+    ///
+    /// If this order does not define a layout for the number of channels,
+    /// then the order of the channel mask is used.
+    pub fn layout(&self, channels: Channels) -> ChannelLayout {
+        use ChannelPosition::*;
+
+        let positions: Option<&[ChannelPosition]> = match (self, channels.count()) {
+            (Self::Wav, _) => None,
+            (Self::Aiff, 3) => Some(&[FrontLeft, FrontRight, FrontCenter]),
+            (Self::Aiff, 4) => Some(&[FrontLeft, FrontRight, RearLeft, RearRight]),
+            (Self::Aiff, 6) => Some(&[
+                FrontLeft,
+                FrontLeftOfCenter,
+                FrontCenter,
+                FrontRight,
+                FrontRightOfCenter,
+                RearCenter,
+            ]),
+            _ => None,
+        };
+
+        match positions {
+            Some(positions) => ChannelLayout::new(positions.to_vec()),
+            None => ChannelLayout::from_symphonia(channels),
+        }
+    }
+}
+
+/// A matrix of gains used to mix a resource from one channel layout into
+/// another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixMatrix {
+    in_channels: usize,
+    out_channels: usize,
+    /// The gains stored row by row, with one row per output channel.
+    coefficients: Vec<f32>,
+}
+
+impl MixMatrix {
+    /// Construct a matrix from a list of gains, stored row by row with one
+    /// row of `in_channels` gains for each output channel.
+    ///
+    /// This will panic if the length of `coefficients` is not
+    /// `in_channels * out_channels`.
+    pub fn from_coefficients(
+        in_channels: usize,
+        out_channels: usize,
+        coefficients: Vec<f32>,
+    ) -> Self {
+        assert_eq!(coefficients.len(), in_channels * out_channels);
+
+        Self {
+            in_channels,
+            out_channels,
+            coefficients,
+        }
+    }
+
+    /// Construct the standard matrix to mix from the layout `from` into the
+    /// layout `to`.
+    ///
+    /// * Channels that exist in both layouts are copied unchanged.
+    /// * When downmixing, the equations from ITU-R BS.775 are used. The
+    ///   center channel is mixed into the left and right channels at -3 dB,
+    ///   the surround channels are mixed into their front channel at -3 dB,
+    ///   and the LFE channel is discarded. A mono target is the stereo
+    ///   downmix with each side at -3 dB.
+    /// * When the target has only one pair of surround channels (i.e. 7.1 to
+    ///   5.1), the side and rear surround channels of each side are mixed
+    ///   into it at -3 dB.
+    /// * When upmixing, a mono channel is copied to the front center channel
+    ///   if there is one, or else to both the left and right channels. No
+    ///   other channels are synthesized, so for example stereo to 5.1 only
+    ///   fills the front left and right channels.
+    pub fn new(from: &ChannelLayout, to: &ChannelLayout) -> Self {
+        let in_channels = from.channels();
+        let out_channels = to.channels();
+        let mut coefficients = vec![0.0; in_channels * out_channels];
+
+        if from.is_mono() && in_channels > 0 {
+            let targets: &[ChannelPosition] = if to.index_of(ChannelPosition::FrontCenter).is_some()
+            {
+                &[ChannelPosition::FrontCenter]
+            } else {
+                &[ChannelPosition::FrontLeft, ChannelPosition::FrontRight]
+            };
+
+            let mut found = false;
+            for pos in targets {
+                if let Some(out_ch) = to.index_of(*pos) {
+                    coefficients[out_ch * in_channels] = 1.0;
+                    found = true;
+                }
+            }
+            if !found && out_channels > 0 {
+                coefficients[0] = 1.0;
+            }
+
+            return Self::from_coefficients(in_channels, out_channels, coefficients);
+        }
+
+        for (in_ch, pos) in from.positions().iter().enumerate() {
+            if *pos == ChannelPosition::Unknown {
+                // Fall back to mapping unknown channels by their index.
+                if in_ch < out_channels && to.positions()[in_ch] == ChannelPosition::Unknown {
+                    coefficients[in_ch * in_channels + in_ch] = 1.0;
+                }
+                continue;
+            }
+
+            // When only one of a pair of surround channels exists in the
+            // target (i.e. 7.1 to 5.1), both are folded into it at -3 dB.
+            let gain = match pos.surround_pair() {
+                Some(pair)
+                    if from.index_of(pair).is_some()
+                        && to.index_of(*pos).is_some() != to.index_of(pair).is_some() =>
+                {
+                    MINUS_3_DB
+                }
+                _ => 1.0,
+            };
+
+            fold_into(to, *pos, gain, 0, &mut |out_ch, gain| {
+                coefficients[out_ch * in_channels + in_ch] += gain;
+            });
+        }
+
+        Self::from_coefficients(in_channels, out_channels, coefficients)
+    }
+
+    /// Construct a matrix that sums `in_channels` channels into one mono
+    /// channel, scaling each channel by the gain of `downmix`.
+    pub fn mono_downmix(in_channels: usize, downmix: MonoDownmix) -> Self {
+        Self::from_coefficients(in_channels, 1, vec![downmix.gain(in_channels); in_channels])
+    }
+
+    /// Construct a matrix that copies each channel unchanged.
+    pub fn identity(channels: usize) -> Self {
+        let mut coefficients = vec![0.0; channels * channels];
+        for ch in 0..channels {
+            coefficients[ch * channels + ch] = 1.0;
+        }
+
+        Self::from_coefficients(channels, channels, coefficients)
+    }
+
+    /// The number of input channels.
+    pub fn in_channels(&self) -> usize {
+        self.in_channels
+    }
+
+    /// The number of output channels.
+    pub fn out_channels(&self) -> usize {
+        self.out_channels
+    }
+
+    /// The gain applied to input channel `in_ch` when mixing into output
+    /// channel `out_ch`.
+    pub fn coefficient(&self, out_ch: usize, in_ch: usize) -> f32 {
+        self.coefficients[out_ch * self.in_channels + in_ch]
+    }
+
+    /// The gains applied to each input channel when mixing into output
+    /// channel `out_ch`.
+    pub fn row(&self, out_ch: usize) -> &[f32] {
+        &self.coefficients[out_ch * self.in_channels..(out_ch + 1) * self.in_channels]
+    }
+
+    /// Whether or not this matrix copies each channel unchanged.
+    pub fn is_identity(&self) -> bool {
+        self.in_channels == self.out_channels && *self == Self::identity(self.in_channels)
+    }
+}
+
+/// The gain that each channel is scaled by when summing channels into mono.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MonoDownmix {
+    /// Each channel is scaled by `1 / channels`, so the sum never clips.
+    #[default]
+    Average,
+    /// Each channel is attenuated by 3 dB, which keeps the loudness of
+    /// uncorrelated channels.
+    Minus3dB,
+    /// Each channel is attenuated by 6 dB, which keeps the level of a
+    /// centered (fully correlated) stereo signal.
+    Minus6dB,
+}
+
+impl MonoDownmix {
+    /// The gain that each of the given number of channels is scaled by.
+    pub fn gain(&self, channels: usize) -> f32 {
+        match self {
+            MonoDownmix::Average => 1.0 / channels.max(1) as f32,
+            MonoDownmix::Minus3dB => MINUS_3_DB,
+            MonoDownmix::Minus6dB => 0.5,
+        }
+    }
+}
+
+fn fold_into(
+    to: &ChannelLayout,
+    pos: ChannelPosition,
+    gain: f32,
+    depth: usize,
+    add: &mut impl FnMut(usize, f32),
+) {
+    // Each fold moves one step closer to the front, so a handful of steps is
+    // enough to reach any layout that has a front channel.
+    const MAX_DEPTH: usize = 6;
+
+    if let Some(out_ch) = to.index_of(pos) {
+        add(out_ch, gain);
+        return;
+    }
+
+    // Prefer the other pair of surround channels if the target has them
+    // (i.e. 7.1 to 5.1).
+    if let Some(out_ch) = pos.surround_pair().and_then(|p| to.index_of(p)) {
+        add(out_ch, gain);
+        return;
+    }
+
+    if depth >= MAX_DEPTH {
+        return;
+    }
+
+    for (next_pos, next_gain) in pos.fold() {
+        fold_into(to, *next_pos, gain * next_gain, depth + 1, add);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_row(m: &MixMatrix, out_ch: usize, expected: &[f32]) {
+        for (a, b) in m.row(out_ch).iter().zip(expected) {
+            assert!(
+                (a - b).abs() < 1e-6,
+                "{:?} != {:?}",
+                m.row(out_ch),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn bs775_downmix() {
+        let m = MixMatrix::new(&ChannelLayout::surround_5_1(), &ChannelLayout::stereo());
+
+        // L, R, C, LFE, Ls, Rs
+        assert_row(&m, 0, &[1.0, 0.0, MINUS_3_DB, 0.0, MINUS_3_DB, 0.0]);
+        assert_row(&m, 1, &[0.0, 1.0, MINUS_3_DB, 0.0, 0.0, MINUS_3_DB]);
+
+        let m = MixMatrix::new(&ChannelLayout::surround_5_1(), &ChannelLayout::mono());
+        assert_row(&m, 0, &[MINUS_3_DB, MINUS_3_DB, 1.0, 0.0, 0.5, 0.5]);
+
+        let m = MixMatrix::new(
+            &ChannelLayout::surround_7_1(),
+            &ChannelLayout::surround_5_1(),
+        );
+        assert_row(
+            &m,
+            4,
+            &[0.0, 0.0, 0.0, 0.0, MINUS_3_DB, 0.0, MINUS_3_DB, 0.0],
+        );
+        assert_row(
+            &m,
+            5,
+            &[0.0, 0.0, 0.0, 0.0, 0.0, MINUS_3_DB, 0.0, MINUS_3_DB],
+        );
+        assert_row(&m, 0, &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn mono_downmix() {
+        let m = MixMatrix::mono_downmix(4, MonoDownmix::Average);
+        assert_eq!(m.out_channels(), 1);
+        assert_row(&m, 0, &[0.25; 4]);
+
+        let m = MixMatrix::mono_downmix(2, MonoDownmix::Minus3dB);
+        assert_row(&m, 0, &[MINUS_3_DB; 2]);
+
+        let m = MixMatrix::mono_downmix(2, MonoDownmix::Minus6dB);
+        assert_row(&m, 0, &[0.5; 2]);
+    }
+
+    #[test]
+    fn upmix() {
+        let m = MixMatrix::new(&ChannelLayout::mono(), &ChannelLayout::stereo());
+        assert_row(&m, 0, &[1.0]);
+        assert_row(&m, 1, &[1.0]);
+
+        let m = MixMatrix::new(&ChannelLayout::stereo(), &ChannelLayout::surround_5_1());
+        assert_row(&m, 0, &[1.0, 0.0]);
+        assert_row(&m, 1, &[0.0, 1.0]);
+        assert_row(&m, 2, &[0.0, 0.0]);
+
+        assert!(MixMatrix::new(&ChannelLayout::stereo(), &ChannelLayout::stereo()).is_identity());
+    }
+
+    #[test]
+    fn canonical_order() {
+        let mask = Channels::FRONT_LEFT
+            | Channels::FRONT_RIGHT
+            | Channels::FRONT_CENTRE
+            | Channels::LFE1
+            | Channels::REAR_LEFT
+            | Channels::REAR_RIGHT;
+
+        let wav = ChannelOrder::Wav.layout(mask);
+        let aiff = ChannelOrder::Aiff.layout(mask);
+
+        assert!(wav.is_canonical());
+        assert!(!aiff.is_canonical());
+        assert_eq!(aiff.canonical_order(), vec![0, 3, 2, 1, 4, 5]);
+        assert_eq!(
+            aiff.to_canonical().positions(),
+            [
+                ChannelPosition::FrontLeft,
+                ChannelPosition::FrontRight,
+                ChannelPosition::FrontCenter,
+                ChannelPosition::FrontLeftOfCenter,
+                ChannelPosition::FrontRightOfCenter,
+                ChannelPosition::RearCenter,
+            ]
+        );
+    }
+
+    #[test]
+    fn order_from_header() {
+        assert_eq!(
+            ChannelOrder::from_header(b"FORM\0\0\x03\x2eAIFFCOMM"),
+            ChannelOrder::Aiff
+        );
+        assert_eq!(
+            ChannelOrder::from_header(b"FORM\0\0\x03\x2eAIFC"),
+            ChannelOrder::Aiff
+        );
+        assert_eq!(
+            ChannelOrder::from_header(b"RIFF\0\0\x03\x2eWAVE"),
+            ChannelOrder::Wav
+        );
+        assert_eq!(ChannelOrder::from_header(b"fLaC"), ChannelOrder::Wav);
+        assert_eq!(ChannelOrder::from_header(b""), ChannelOrder::Wav);
+    }
+}
@@ -1,15 +1,17 @@
+mod simd;
+
 /// Convert a PCM sample in `u8` format to `f32` format in the
 /// range `[-1.0, 1.0]`.
 #[inline]
 pub fn pcm_u8_to_f32(s: u8) -> f32 {
-    ((f32::from(s)) * (2.0 / std::u8::MAX as f32)) - 1.0
+    ((f32::from(s)) * (2.0 / u8::MAX as f32)) - 1.0
 }
 
 /// Convert a PCM sample in `u16` format to `f32` format in the
 /// range `[-1.0, 1.0]`.
 #[inline]
 pub fn pcm_u16_to_f32(s: u16) -> f32 {
-    ((f32::from(s)) * (2.0 / std::u16::MAX as f32)) - 1.0
+    ((f32::from(s)) * (2.0 / u16::MAX as f32)) - 1.0
 }
 
 /// Convert a PCM sample in `u24` format to `f32` format in the
@@ -54,21 +56,21 @@ pub fn pcm_u24_to_f32_be(s: [u8; 3]) -> f32 {
 /// range `[-1.0, 1.0]`.
 #[inline]
 pub fn pcm_u32_to_f32(s: u32) -> f32 {
-    ((f64::from(s) * (2.0 / std::u32::MAX as f64)) - 1.0) as f32
+    ((f64::from(s) * (2.0 / u32::MAX as f64)) - 1.0) as f32
 }
 
 /// Convert a PCM sample in `i8` format to `f32` format in the
 /// range `[-1.0, 1.0]`.
 #[inline]
 pub fn pcm_i8_to_f32(s: i8) -> f32 {
-    f32::from(s) / std::i8::MAX as f32
+    f32::from(s) / i8::MAX as f32
 }
 
 /// Convert a PCM sample in `i16` format to `f32` format in the
 /// range `[-1.0, 1.0]`.
 #[inline]
 pub fn pcm_i16_to_f32(s: i16) -> f32 {
-    f32::from(s) / std::i16::MAX as f32
+    f32::from(s) / i16::MAX as f32
 }
 
 /// Convert a PCM sample in `i24` format to `f32` format in the
@@ -88,10 +90,11 @@ pub fn pcm_i24_to_f32_ne(s: [u8; 3]) -> f32 {
 /// bytes in little endian.
 #[inline]
 pub fn pcm_i24_to_f32_le(s: [u8; 3]) -> f32 {
-    // In little-endian the MSB is the last byte.
-    let bytes = [s[0], s[1], s[2], 0];
+    // In little-endian the MSB is the last byte. Place the sample in the upper
+    // three bytes and shift it back down to sign-extend it.
+    let bytes = [0, s[0], s[1], s[2]];
 
-    let val = i32::from_le_bytes(bytes);
+    let val = i32::from_le_bytes(bytes) >> 8;
 
     (f64::from(val) / 8_388_607.0) as f32
 }
@@ -101,10 +104,11 @@ pub fn pcm_i24_to_f32_le(s: [u8; 3]) -> f32 {
 /// bytes in big endian.
 #[inline]
 pub fn pcm_i24_to_f32_be(s: [u8; 3]) -> f32 {
-    // In big-endian the MSB is the first byte.
-    let bytes = [0, s[0], s[1], s[2]];
+    // In big-endian the MSB is the first byte. Place the sample in the upper
+    // three bytes and shift it back down to sign-extend it.
+    let bytes = [s[0], s[1], s[2], 0];
 
-    let val = i32::from_be_bytes(bytes);
+    let val = i32::from_be_bytes(bytes) >> 8;
 
     (f64::from(val) / 8_388_607.0) as f32
 }
@@ -113,5 +117,441 @@ pub fn pcm_i24_to_f32_be(s: [u8; 3]) -> f32 {
 /// range `[-1.0, 1.0]`.
 #[inline]
 pub fn pcm_i32_to_f32(s: i32) -> f32 {
-    (f64::from(s) / std::i32::MAX as f64) as f32
+    (f64::from(s) / i32::MAX as f64) as f32
+}
+
+/// Convert a slice of PCM samples in `u8` format to `f32` format in the
+/// range `[-1.0, 1.0]`.
+///
+/// Only the first `src.len().min(dst.len())` samples are converted.
+pub fn pcm_u8_slice_to_f32(src: &[u8], dst: &mut [f32]) {
+    convert_slice(src, dst, simd::u8_to_f32, pcm_u8_to_f32);
+}
+
+/// Convert a slice of PCM samples in `u16` format to `f32` format in the
+/// range `[-1.0, 1.0]`.
+///
+/// Only the first `src.len().min(dst.len())` samples are converted.
+pub fn pcm_u16_slice_to_f32(src: &[u16], dst: &mut [f32]) {
+    convert_slice(src, dst, simd::u16_to_f32, pcm_u16_to_f32);
+}
+
+/// Convert a slice of PCM samples in `u24` format to `f32` format in the
+/// range `[-1.0, 1.0]`, where each `u24` is represented as three
+/// bytes in native endian.
+///
+/// Only the first `src.len().min(dst.len())` samples are converted.
+pub fn pcm_u24_slice_to_f32_ne(src: &[[u8; 3]], dst: &mut [f32]) {
+    convert_slice(src, dst, no_simd, pcm_u24_to_f32_ne);
+}
+
+/// Convert a slice of PCM samples in `u32` format to `f32` format in the
+/// range `[-1.0, 1.0]`.
+///
+/// Only the first `src.len().min(dst.len())` samples are converted.
+pub fn pcm_u32_slice_to_f32(src: &[u32], dst: &mut [f32]) {
+    convert_slice(src, dst, no_simd, pcm_u32_to_f32);
+}
+
+/// Convert a slice of PCM samples in `i8` format to `f32` format in the
+/// range `[-1.0, 1.0]`.
+///
+/// Only the first `src.len().min(dst.len())` samples are converted.
+pub fn pcm_i8_slice_to_f32(src: &[i8], dst: &mut [f32]) {
+    convert_slice(src, dst, simd::i8_to_f32, pcm_i8_to_f32);
+}
+
+/// Convert a slice of PCM samples in `i16` format to `f32` format in the
+/// range `[-1.0, 1.0]`.
+///
+/// Only the first `src.len().min(dst.len())` samples are converted.
+pub fn pcm_i16_slice_to_f32(src: &[i16], dst: &mut [f32]) {
+    convert_slice(src, dst, simd::i16_to_f32, pcm_i16_to_f32);
+}
+
+/// Convert a slice of PCM samples in `i24` format to `f32` format in the
+/// range `[-1.0, 1.0]`, where each `i24` is represented as three
+/// bytes in native endian.
+///
+/// Only the first `src.len().min(dst.len())` samples are converted.
+pub fn pcm_i24_slice_to_f32_ne(src: &[[u8; 3]], dst: &mut [f32]) {
+    convert_slice(src, dst, no_simd, pcm_i24_to_f32_ne);
+}
+
+/// Convert a slice of PCM samples in `i32` format to `f32` format in the
+/// range `[-1.0, 1.0]`.
+///
+/// Only the first `src.len().min(dst.len())` samples are converted.
+pub fn pcm_i32_slice_to_f32(src: &[i32], dst: &mut [f32]) {
+    convert_slice(src, dst, simd::i32_to_f32, pcm_i32_to_f32);
+}
+
+/// Convert as much of `src` as possible with the SIMD function `fast`, and
+/// the rest with the scalar function `to_f32`.
+#[inline]
+fn convert_slice<T: Copy>(
+    src: &[T],
+    dst: &mut [f32],
+    fast: fn(&[T], &mut [f32]) -> usize,
+    to_f32: fn(T) -> f32,
+) {
+    let len = src.len().min(dst.len());
+    let src = &src[..len];
+    let dst = &mut dst[..len];
+
+    let n = fast(src, dst);
+
+    for (out_s, &s) in dst[n..].iter_mut().zip(&src[n..]) {
+        *out_s = to_f32(s);
+    }
+}
+
+/// Used for formats that have no SIMD fast path.
+fn no_simd<T>(_src: &[T], _dst: &mut [f32]) -> usize {
+    0
+}
+
+/// What happens to `f32` samples outside of the range `[-1.0, 1.0]` when
+/// converting them to an integer format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverflowMode {
+    /// Clip the sample to the minimum or maximum value of the format.
+    #[default]
+    Saturate,
+    /// Wrap the sample around, keeping only the lowest bits (the same as an
+    /// `as` cast between integer types).
+    ///
+    /// Infinite samples have no lowest bits, so they become silence.
+    Wrap,
+}
+
+/// Round `v` to the nearest integer and fit it into the range `[min, max]`
+/// using the given mode. NaN becomes silence (the middle of the range).
+#[inline]
+fn fit(v: f64, min: i64, max: i64, mode: OverflowMode) -> i64 {
+    let silence = (min + max + 1) / 2;
+    if v.is_nan() {
+        return silence;
+    }
+
+    match mode {
+        OverflowMode::Saturate => v.round().clamp(min as f64, max as f64) as i64,
+        OverflowMode::Wrap if v.is_infinite() => silence,
+        OverflowMode::Wrap => {
+            // Wrap in `f64`, since a large sample doesn't fit into an `i64`.
+            let range = max - min + 1;
+            let v = v.round().rem_euclid(range as f64) as i64;
+            if v > max {
+                v - range
+            } else {
+                v
+            }
+        }
+    }
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `u8`
+/// format. This is the inverse of [`pcm_u8_to_f32`].
+#[inline]
+pub fn f32_to_pcm_u8(s: f32, mode: OverflowMode) -> u8 {
+    let v = (f64::from(s) + 1.0) * (u8::MAX as f64 / 2.0);
+    fit(v, 0, u8::MAX.into(), mode) as u8
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `u16`
+/// format. This is the inverse of [`pcm_u16_to_f32`].
+#[inline]
+pub fn f32_to_pcm_u16(s: f32, mode: OverflowMode) -> u16 {
+    let v = (f64::from(s) + 1.0) * (u16::MAX as f64 / 2.0);
+    fit(v, 0, u16::MAX.into(), mode) as u16
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `u24`
+/// format, where the `u24` is represented as three bytes in native endian.
+/// This is the inverse of [`pcm_u24_to_f32_ne`].
+#[inline]
+pub fn f32_to_pcm_u24_ne(s: f32, mode: OverflowMode) -> [u8; 3] {
+    #[cfg(target_endian = "little")]
+    return f32_to_pcm_u24_le(s, mode);
+
+    #[cfg(target_endian = "big")]
+    return f32_to_pcm_u24_be(s, mode);
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `u24`
+/// format, where the `u24` is represented as three bytes in little endian.
+/// This is the inverse of [`pcm_u24_to_f32_le`].
+#[inline]
+pub fn f32_to_pcm_u24_le(s: f32, mode: OverflowMode) -> [u8; 3] {
+    let v = (f64::from(s) + 1.0) * (16_777_215.0 / 2.0);
+    let b = (fit(v, 0, 16_777_215, mode) as u32).to_le_bytes();
+
+    [b[0], b[1], b[2]]
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `u24`
+/// format, where the `u24` is represented as three bytes in big endian.
+/// This is the inverse of [`pcm_u24_to_f32_be`].
+#[inline]
+pub fn f32_to_pcm_u24_be(s: f32, mode: OverflowMode) -> [u8; 3] {
+    let v = (f64::from(s) + 1.0) * (16_777_215.0 / 2.0);
+    let b = (fit(v, 0, 16_777_215, mode) as u32).to_be_bytes();
+
+    [b[1], b[2], b[3]]
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `i8`
+/// format. This is the inverse of [`pcm_i8_to_f32`].
+#[inline]
+pub fn f32_to_pcm_i8(s: f32, mode: OverflowMode) -> i8 {
+    let v = f64::from(s) * i8::MAX as f64;
+    fit(v, i8::MIN.into(), i8::MAX.into(), mode) as i8
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `i16`
+/// format. This is the inverse of [`pcm_i16_to_f32`].
+#[inline]
+pub fn f32_to_pcm_i16(s: f32, mode: OverflowMode) -> i16 {
+    f32_to_pcm_i16_dithered(s, 0.0, mode)
+}
+
+/// The same as [`f32_to_pcm_i16`], but adds `dither` (in units of the least
+/// significant bit) before rounding.
+#[inline]
+pub(crate) fn f32_to_pcm_i16_dithered(s: f32, dither: f32, mode: OverflowMode) -> i16 {
+    let v = f64::from(s) * i16::MAX as f64 + f64::from(dither);
+    fit(v, i16::MIN.into(), i16::MAX.into(), mode) as i16
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `i24`
+/// format, where the `i24` is represented as three bytes in native endian.
+/// This is the inverse of [`pcm_i24_to_f32_ne`].
+#[inline]
+pub fn f32_to_pcm_i24_ne(s: f32, mode: OverflowMode) -> [u8; 3] {
+    #[cfg(target_endian = "little")]
+    return f32_to_pcm_i24_le(s, mode);
+
+    #[cfg(target_endian = "big")]
+    return f32_to_pcm_i24_be(s, mode);
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `i24`
+/// format, where the `i24` is represented as three bytes in little endian.
+/// This is the inverse of [`pcm_i24_to_f32_le`].
+#[inline]
+pub fn f32_to_pcm_i24_le(s: f32, mode: OverflowMode) -> [u8; 3] {
+    let v = f64::from(s) * 8_388_607.0;
+    let b = (fit(v, -8_388_608, 8_388_607, mode) as i32).to_le_bytes();
+
+    [b[0], b[1], b[2]]
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `i24`
+/// format, where the `i24` is represented as three bytes in big endian.
+/// This is the inverse of [`pcm_i24_to_f32_be`].
+#[inline]
+pub fn f32_to_pcm_i24_be(s: f32, mode: OverflowMode) -> [u8; 3] {
+    let v = f64::from(s) * 8_388_607.0;
+    let b = (fit(v, -8_388_608, 8_388_607, mode) as i32).to_be_bytes();
+
+    [b[1], b[2], b[3]]
+}
+
+/// Convert an `f32` sample in the range `[-1.0, 1.0]` to a PCM sample in `i32`
+/// format. This is the inverse of [`pcm_i32_to_f32`].
+///
+/// Note that an `f32` can only hold 24 bits of precision, so converting an
+/// `i32` sample to `f32` and back is not lossless.
+#[inline]
+pub fn f32_to_pcm_i32(s: f32, mode: OverflowMode) -> i32 {
+    f32_to_pcm_i32_dithered(s, 0.0, mode)
+}
+
+/// The same as [`f32_to_pcm_i32`], but adds `dither` (in units of the least
+/// significant bit) before rounding.
+#[inline]
+pub(crate) fn f32_to_pcm_i32_dithered(s: f32, dither: f32, mode: OverflowMode) -> i32 {
+    let v = f64::from(s) * i32::MAX as f64 + f64::from(dither);
+    fit(v, i32::MIN.into(), i32::MAX.into(), mode) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const SAT: OverflowMode = OverflowMode::Saturate;
+
+    proptest! {
+        #[test]
+        fn u8_round_trip(s: u8) {
+            prop_assert_eq!(f32_to_pcm_u8(pcm_u8_to_f32(s), SAT), s);
+        }
+
+        #[test]
+        fn u16_round_trip(s: u16) {
+            prop_assert_eq!(f32_to_pcm_u16(pcm_u16_to_f32(s), SAT), s);
+        }
+
+        #[test]
+        fn u24_round_trip(s in 0u32..(1 << 24)) {
+            let le = s.to_le_bytes();
+            let le = [le[0], le[1], le[2]];
+            let be = [le[2], le[1], le[0]];
+            let ne = if cfg!(target_endian = "little") { le } else { be };
+
+            prop_assert_eq!(f32_to_pcm_u24_le(pcm_u24_to_f32_le(le), SAT), le);
+            prop_assert_eq!(f32_to_pcm_u24_be(pcm_u24_to_f32_be(be), SAT), be);
+            prop_assert_eq!(f32_to_pcm_u24_ne(pcm_u24_to_f32_ne(ne), SAT), ne);
+        }
+
+        #[test]
+        fn i8_round_trip(s: i8) {
+            prop_assert_eq!(f32_to_pcm_i8(pcm_i8_to_f32(s), SAT), s);
+        }
+
+        #[test]
+        fn i16_round_trip(s: i16) {
+            prop_assert_eq!(f32_to_pcm_i16(pcm_i16_to_f32(s), SAT), s);
+        }
+
+        #[test]
+        fn i24_round_trip(s in -(1i32 << 23)..(1 << 23)) {
+            let le = s.to_le_bytes();
+            let le = [le[0], le[1], le[2]];
+            let be = [le[2], le[1], le[0]];
+            let ne = if cfg!(target_endian = "little") { le } else { be };
+
+            prop_assert_eq!(f32_to_pcm_i24_le(pcm_i24_to_f32_le(le), SAT), le);
+            prop_assert_eq!(f32_to_pcm_i24_be(pcm_i24_to_f32_be(be), SAT), be);
+            prop_assert_eq!(f32_to_pcm_i24_ne(pcm_i24_to_f32_ne(ne), SAT), ne);
+        }
+
+        #[test]
+        fn i32_round_trip(s in -1.0f32..=1.0) {
+            // `f32` can't hold every `i32`, so round-trip in the other direction.
+            // Small samples have more precision than an `i32` step, so allow an
+            // error of one step.
+            let back = pcm_i32_to_f32(f32_to_pcm_i32(s, SAT));
+            prop_assert!((f64::from(back) - f64::from(s)).abs() <= 1.0 / i32::MAX as f64);
+        }
+
+        #[test]
+        fn wrap_matches_integer_cast(s in -3.0f32..3.0) {
+            let v = (f64::from(s) * i16::MAX as f64).round() as i64;
+            prop_assert_eq!(f32_to_pcm_i16(s, OverflowMode::Wrap), v as i16);
+
+            let v = ((f64::from(s) + 1.0) * (u8::MAX as f64 / 2.0)).round() as i64;
+            prop_assert_eq!(f32_to_pcm_u8(s, OverflowMode::Wrap), v as u8);
+        }
+
+        #[test]
+        fn wrap_large_samples(s in -1e28f32..1e28) {
+            // These don't fit into an `i64`, but do fit into an `i128`.
+            let v = (f64::from(s) * i32::MAX as f64).round() as i128;
+            prop_assert_eq!(f32_to_pcm_i32(s, OverflowMode::Wrap), v as i32);
+
+            let v = (f64::from(s) * i16::MAX as f64).round() as i128;
+            prop_assert_eq!(f32_to_pcm_i16(s, OverflowMode::Wrap), v as i16);
+        }
+    }
+
+    #[test]
+    fn non_finite() {
+        for mode in [OverflowMode::Saturate, OverflowMode::Wrap] {
+            assert_eq!(f32_to_pcm_i32(f32::NAN, mode), 0);
+            assert_eq!(f32_to_pcm_u8(f32::NAN, mode), 128);
+        }
+
+        assert_eq!(f32_to_pcm_i32(f32::INFINITY, SAT), i32::MAX);
+        assert_eq!(f32_to_pcm_i32(f32::NEG_INFINITY, SAT), i32::MIN);
+        assert_eq!(f32_to_pcm_i32(f32::INFINITY, OverflowMode::Wrap), 0);
+        // Large finite samples have no low bits left at this resolution.
+        assert_eq!(f32_to_pcm_i32(1e30, OverflowMode::Wrap), 0);
+        assert_eq!(f32_to_pcm_u16(f32::NEG_INFINITY, OverflowMode::Wrap), 32768);
+    }
+
+    #[test]
+    fn i24_sign_extension() {
+        // Negative samples have the top bit of the most significant byte set,
+        // and must not be read as large positive values.
+        assert_eq!(pcm_i24_to_f32_le([0xff, 0xff, 0xff]), -1.0 / 8_388_607.0);
+        assert_eq!(pcm_i24_to_f32_be([0xff, 0xff, 0xff]), -1.0 / 8_388_607.0);
+        assert_eq!(
+            pcm_i24_to_f32_le([0x00, 0x00, 0x80]),
+            -8_388_608.0 / 8_388_607.0
+        );
+        assert_eq!(
+            pcm_i24_to_f32_be([0x80, 0x00, 0x00]),
+            -8_388_608.0 / 8_388_607.0
+        );
+        assert_eq!(
+            pcm_i24_to_f32_le([0x01, 0x00, 0xc0]),
+            -4_194_303.0 / 8_388_607.0
+        );
+        assert_eq!(
+            pcm_i24_to_f32_be([0xc0, 0x00, 0x01]),
+            -4_194_303.0 / 8_388_607.0
+        );
+
+        // Positive samples are unchanged.
+        assert_eq!(pcm_i24_to_f32_le([0xff, 0xff, 0x7f]), 1.0);
+        assert_eq!(pcm_i24_to_f32_be([0x7f, 0xff, 0xff]), 1.0);
+    }
+
+    #[test]
+    fn slices_match_scalar() {
+        // Odd lengths exercise the scalar tail after the SIMD part.
+        fn check<T: Copy>(src: &[T], slice: fn(&[T], &mut [f32]), scalar: fn(T) -> f32) {
+            for len in [0, 1, 7, 8, 9, 31, src.len()] {
+                let len = len.min(src.len());
+                let mut dst = vec![0.0; len];
+                slice(&src[..len], &mut dst);
+
+                for (&d, &s) in dst.iter().zip(&src[..len]) {
+                    assert_eq!(d.to_bits(), scalar(s).to_bits());
+                }
+            }
+        }
+
+        let u8s: Vec<u8> = (0..=u8::MAX).collect();
+        let u16s: Vec<u16> = (0..=u16::MAX).collect();
+        let i8s: Vec<i8> = (i8::MIN..=i8::MAX).collect();
+        let i16s: Vec<i16> = (i16::MIN..=i16::MAX).collect();
+        let i32s: Vec<i32> = (0..100_003)
+            .map(|i: i64| (i * 42_949 + i32::MIN as i64) as i32)
+            .chain([i32::MIN, i32::MAX, 0, -1, 1])
+            .collect();
+        let u32s: Vec<u32> = i32s.iter().map(|&s| s as u32).collect();
+        let b24s: Vec<[u8; 3]> = (0..1 << 24)
+            .step_by(97)
+            .map(|i: u32| {
+                let b = i.to_ne_bytes();
+                [b[0], b[1], b[2]]
+            })
+            .collect();
+
+        check(&u8s, pcm_u8_slice_to_f32, pcm_u8_to_f32);
+        check(&u16s, pcm_u16_slice_to_f32, pcm_u16_to_f32);
+        check(&b24s, pcm_u24_slice_to_f32_ne, pcm_u24_to_f32_ne);
+        check(&u32s, pcm_u32_slice_to_f32, pcm_u32_to_f32);
+        check(&i8s, pcm_i8_slice_to_f32, pcm_i8_to_f32);
+        check(&i16s, pcm_i16_slice_to_f32, pcm_i16_to_f32);
+        check(&b24s, pcm_i24_slice_to_f32_ne, pcm_i24_to_f32_ne);
+        check(&i32s, pcm_i32_slice_to_f32, pcm_i32_to_f32);
+
+        // Only the overlapping part is converted.
+        let mut dst = [9.0; 4];
+        pcm_i16_slice_to_f32(&[i16::MAX, 0], &mut dst);
+        assert_eq!(dst, [1.0, 0.0, 9.0, 9.0]);
+    }
+
+    #[test]
+    fn saturate() {
+        assert_eq!(f32_to_pcm_u8(2.0, SAT), u8::MAX);
+        assert_eq!(f32_to_pcm_u8(-2.0, SAT), 0);
+        assert_eq!(f32_to_pcm_i16(-2.0, SAT), i16::MIN);
+        assert_eq!(f32_to_pcm_i24_le(2.0, SAT), [0xFF, 0xFF, 0x7F]);
+        assert_eq!(f32_to_pcm_i32(f32::NAN, SAT), 0);
+    }
 }
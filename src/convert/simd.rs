@@ -0,0 +1,369 @@
+//! SIMD fast paths for the slice conversions in `convert`.
+//!
+//! Each function converts as many samples from the start of `src` as it can
+//! and returns how many it converted, leaving the rest to the scalar
+//! fallback. The results are identical to the scalar conversions, since the
+//! same operations are performed in the same order.
+//!
+//! `src` and `dst` must have the same length.
+
+#![allow(unreachable_code)]
+
+#[cfg(all(
+    any(feature = "opt-simd-sse", feature = "opt-simd-avx"),
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    pub(super) use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    pub(super) use std::arch::x86_64::*;
+}
+
+/// Dispatch to the fastest implementation supported by the enabled features
+/// and the CPU.
+macro_rules! dispatch {
+    ($name:ident, $src:ident, $dst:ident) => {{
+        debug_assert_eq!($src.len(), $dst.len());
+
+        #[cfg(all(
+            feature = "opt-simd-avx",
+            any(target_arch = "x86", target_arch = "x86_64")
+        ))]
+        if std::is_x86_feature_detected!("avx2") {
+            // Safety: the CPU supports AVX2.
+            return unsafe { avx2::$name($src, $dst) };
+        }
+
+        #[cfg(all(
+            feature = "opt-simd-sse",
+            any(target_arch = "x86", target_arch = "x86_64")
+        ))]
+        if std::is_x86_feature_detected!("sse2") {
+            // Safety: the CPU supports SSE2.
+            return unsafe { sse2::$name($src, $dst) };
+        }
+
+        #[cfg(all(feature = "opt-simd-neon", target_arch = "aarch64"))]
+        {
+            // Safety: NEON is always available on aarch64.
+            return unsafe { neon::$name($src, $dst) };
+        }
+
+        0
+    }};
+}
+
+pub(super) fn u8_to_f32(src: &[u8], dst: &mut [f32]) -> usize {
+    dispatch!(u8_to_f32, src, dst)
+}
+
+pub(super) fn u16_to_f32(src: &[u16], dst: &mut [f32]) -> usize {
+    dispatch!(u16_to_f32, src, dst)
+}
+
+pub(super) fn i8_to_f32(src: &[i8], dst: &mut [f32]) -> usize {
+    dispatch!(i8_to_f32, src, dst)
+}
+
+pub(super) fn i16_to_f32(src: &[i16], dst: &mut [f32]) -> usize {
+    dispatch!(i16_to_f32, src, dst)
+}
+
+pub(super) fn i32_to_f32(src: &[i32], dst: &mut [f32]) -> usize {
+    dispatch!(i32_to_f32, src, dst)
+}
+
+#[cfg(all(
+    feature = "opt-simd-sse",
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+mod sse2 {
+    use super::x86::*;
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn u8_to_f32(src: &[u8], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let zero = _mm_setzero_si128();
+        let scale = _mm_set1_ps(2.0 / u8::MAX as f32);
+        let one = _mm_set1_ps(1.0);
+
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadl_epi64(src.as_ptr().add(i) as *const __m128i);
+            let v = _mm_unpacklo_epi8(v, zero);
+
+            let lo = _mm_cvtepi32_ps(_mm_unpacklo_epi16(v, zero));
+            let hi = _mm_cvtepi32_ps(_mm_unpackhi_epi16(v, zero));
+
+            let out = dst.as_mut_ptr().add(i);
+            _mm_storeu_ps(out, _mm_sub_ps(_mm_mul_ps(lo, scale), one));
+            _mm_storeu_ps(out.add(4), _mm_sub_ps(_mm_mul_ps(hi, scale), one));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn u16_to_f32(src: &[u16], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let zero = _mm_setzero_si128();
+        let scale = _mm_set1_ps(2.0 / u16::MAX as f32);
+        let one = _mm_set1_ps(1.0);
+
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+
+            let lo = _mm_cvtepi32_ps(_mm_unpacklo_epi16(v, zero));
+            let hi = _mm_cvtepi32_ps(_mm_unpackhi_epi16(v, zero));
+
+            let out = dst.as_mut_ptr().add(i);
+            _mm_storeu_ps(out, _mm_sub_ps(_mm_mul_ps(lo, scale), one));
+            _mm_storeu_ps(out.add(4), _mm_sub_ps(_mm_mul_ps(hi, scale), one));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn i8_to_f32(src: &[i8], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let scale = _mm_set1_ps(i8::MAX as f32);
+
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadl_epi64(src.as_ptr().add(i) as *const __m128i);
+            // Sign-extend to 16 bits, and then to 32 bits.
+            let v = _mm_srai_epi16(_mm_unpacklo_epi8(v, v), 8);
+
+            let lo = _mm_cvtepi32_ps(_mm_srai_epi32(_mm_unpacklo_epi16(v, v), 16));
+            let hi = _mm_cvtepi32_ps(_mm_srai_epi32(_mm_unpackhi_epi16(v, v), 16));
+
+            let out = dst.as_mut_ptr().add(i);
+            _mm_storeu_ps(out, _mm_div_ps(lo, scale));
+            _mm_storeu_ps(out.add(4), _mm_div_ps(hi, scale));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn i16_to_f32(src: &[i16], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let scale = _mm_set1_ps(i16::MAX as f32);
+
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+
+            // Sign-extend to 32 bits.
+            let lo = _mm_cvtepi32_ps(_mm_srai_epi32(_mm_unpacklo_epi16(v, v), 16));
+            let hi = _mm_cvtepi32_ps(_mm_srai_epi32(_mm_unpackhi_epi16(v, v), 16));
+
+            let out = dst.as_mut_ptr().add(i);
+            _mm_storeu_ps(out, _mm_div_ps(lo, scale));
+            _mm_storeu_ps(out.add(4), _mm_div_ps(hi, scale));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn i32_to_f32(src: &[i32], dst: &mut [f32]) -> usize {
+        let n = src.len() / 4 * 4;
+        let scale = _mm_set1_pd(i32::MAX as f64);
+
+        for i in (0..n).step_by(4) {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+
+            // Divide in `f64` like the scalar conversion does.
+            let lo = _mm_div_pd(_mm_cvtepi32_pd(v), scale);
+            let hi = _mm_div_pd(_mm_cvtepi32_pd(_mm_shuffle_epi32(v, 0b1110)), scale);
+
+            let out = _mm_movelh_ps(_mm_cvtpd_ps(lo), _mm_cvtpd_ps(hi));
+            _mm_storeu_ps(dst.as_mut_ptr().add(i), out);
+        }
+
+        n
+    }
+}
+
+#[cfg(all(
+    feature = "opt-simd-avx",
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+mod avx2 {
+    use super::x86::*;
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn u8_to_f32(src: &[u8], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let scale = _mm256_set1_ps(2.0 / u8::MAX as f32);
+        let one = _mm256_set1_ps(1.0);
+
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadl_epi64(src.as_ptr().add(i) as *const __m128i);
+            let v = _mm256_cvtepi32_ps(_mm256_cvtepu8_epi32(v));
+
+            _mm256_storeu_ps(
+                dst.as_mut_ptr().add(i),
+                _mm256_sub_ps(_mm256_mul_ps(v, scale), one),
+            );
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn u16_to_f32(src: &[u16], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let scale = _mm256_set1_ps(2.0 / u16::MAX as f32);
+        let one = _mm256_set1_ps(1.0);
+
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            let v = _mm256_cvtepi32_ps(_mm256_cvtepu16_epi32(v));
+
+            _mm256_storeu_ps(
+                dst.as_mut_ptr().add(i),
+                _mm256_sub_ps(_mm256_mul_ps(v, scale), one),
+            );
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn i8_to_f32(src: &[i8], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let scale = _mm256_set1_ps(i8::MAX as f32);
+
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadl_epi64(src.as_ptr().add(i) as *const __m128i);
+            let v = _mm256_cvtepi32_ps(_mm256_cvtepi8_epi32(v));
+
+            _mm256_storeu_ps(dst.as_mut_ptr().add(i), _mm256_div_ps(v, scale));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn i16_to_f32(src: &[i16], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let scale = _mm256_set1_ps(i16::MAX as f32);
+
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            let v = _mm256_cvtepi32_ps(_mm256_cvtepi16_epi32(v));
+
+            _mm256_storeu_ps(dst.as_mut_ptr().add(i), _mm256_div_ps(v, scale));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn i32_to_f32(src: &[i32], dst: &mut [f32]) -> usize {
+        let n = src.len() / 4 * 4;
+        let scale = _mm256_set1_pd(i32::MAX as f64);
+
+        for i in (0..n).step_by(4) {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+
+            // Divide in `f64` like the scalar conversion does.
+            let v = _mm256_div_pd(_mm256_cvtepi32_pd(v), scale);
+
+            _mm_storeu_ps(dst.as_mut_ptr().add(i), _mm256_cvtpd_ps(v));
+        }
+
+        n
+    }
+}
+
+#[cfg(all(feature = "opt-simd-neon", target_arch = "aarch64"))]
+mod neon {
+    use std::arch::aarch64::*;
+
+    #[target_feature(enable = "neon")]
+    unsafe fn store_u16x8(v: uint16x8_t, out: *mut f32) {
+        let scale = vdupq_n_f32(2.0 / u16::MAX as f32);
+        let one = vdupq_n_f32(1.0);
+
+        let lo = vcvtq_f32_u32(vmovl_u16(vget_low_u16(v)));
+        let hi = vcvtq_f32_u32(vmovl_u16(vget_high_u16(v)));
+
+        vst1q_f32(out, vsubq_f32(vmulq_f32(lo, scale), one));
+        vst1q_f32(out.add(4), vsubq_f32(vmulq_f32(hi, scale), one));
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn store_i16x8(v: int16x8_t, scale: f32, out: *mut f32) {
+        let scale = vdupq_n_f32(scale);
+
+        let lo = vcvtq_f32_s32(vmovl_s16(vget_low_s16(v)));
+        let hi = vcvtq_f32_s32(vmovl_s16(vget_high_s16(v)));
+
+        vst1q_f32(out, vdivq_f32(lo, scale));
+        vst1q_f32(out.add(4), vdivq_f32(hi, scale));
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn u8_to_f32(src: &[u8], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let scale = vdupq_n_f32(2.0 / u8::MAX as f32);
+        let one = vdupq_n_f32(1.0);
+
+        for i in (0..n).step_by(8) {
+            let v = vmovl_u8(vld1_u8(src.as_ptr().add(i)));
+
+            let lo = vcvtq_f32_u32(vmovl_u16(vget_low_u16(v)));
+            let hi = vcvtq_f32_u32(vmovl_u16(vget_high_u16(v)));
+
+            let out = dst.as_mut_ptr().add(i);
+            vst1q_f32(out, vsubq_f32(vmulq_f32(lo, scale), one));
+            vst1q_f32(out.add(4), vsubq_f32(vmulq_f32(hi, scale), one));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn u16_to_f32(src: &[u16], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+
+        for i in (0..n).step_by(8) {
+            store_u16x8(vld1q_u16(src.as_ptr().add(i)), dst.as_mut_ptr().add(i));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn i8_to_f32(src: &[i8], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+
+        for i in (0..n).step_by(8) {
+            let v = vmovl_s8(vld1_s8(src.as_ptr().add(i)));
+            store_i16x8(v, i8::MAX as f32, dst.as_mut_ptr().add(i));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn i16_to_f32(src: &[i16], dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+
+        for i in (0..n).step_by(8) {
+            let v = vld1q_s16(src.as_ptr().add(i));
+            store_i16x8(v, i16::MAX as f32, dst.as_mut_ptr().add(i));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn i32_to_f32(_src: &[i32], _dst: &mut [f32]) -> usize {
+        // There is no fast way to divide in `f64` like the scalar conversion
+        // does, so leave this to the scalar fallback.
+        0
+    }
+}
@@ -2,30 +2,163 @@ use std::borrow::Cow;
 
 use symphonia::core::audio::AudioBufferRef;
 use symphonia::core::audio::{AudioBuffer, Signal};
-use symphonia::core::codecs::{CodecRegistry, DecoderOptions};
+use symphonia::core::codecs::{CodecRegistry, Decoder, DecoderOptions};
+use symphonia::core::formats::FormatReader;
 use symphonia::core::probe::ProbeResult;
-use symphonia::core::sample::{i24, u24};
+use symphonia::core::sample::{i24, u24, Sample as SymphoniaSample};
 
+use crate::channel::{ChannelLayout, MixMatrix};
+use crate::dither::ConversionOptions;
+#[cfg(feature = "resampler")]
+use crate::resample::{ChunkedResampler, ResampledSink};
+use crate::sample::{Sample, I24, U24};
 use crate::DecodedAudioF32;
 
-use super::resource::{DecodedAudio, DecodedAudioType};
+use super::resource::{
+    DecodedAudio, DecodedAudioType, FormatConverter, SampleFormat, StorageFormat,
+};
 use super::{convert, LoadError};
 
 const SHRINK_THRESHOLD: usize = 4096;
 
+/// The parameters for decoding a resource.
+pub(crate) struct DecodeParams<'a> {
+    /// The number of channels to decode (after `channel_map` is applied).
+    pub n_channels: usize,
+    /// The planes of the source to decode, in order, if not all of them.
+    pub channel_map: Option<&'a [usize]>,
+    /// The sample rate of the source.
+    pub sample_rate: u32,
+    /// The matrix to mix the channels with, if any.
+    pub mix: Option<&'a MixMatrix>,
+    /// The channel layout of the resulting resource.
+    pub layout: ChannelLayout,
+    pub max_bytes: usize,
+    /// Whether to store floating point samples as `f16`. (Only used when
+    /// decoding in the native sample format.)
+    #[cfg(feature = "f16")]
+    pub float_as_f16: bool,
+    /// The format to store the samples in. (Only used when decoding in the
+    /// native sample format.)
+    pub storage: StorageFormat,
+    /// How to dither when `storage` narrows the samples.
+    pub conversion: ConversionOptions,
+}
+
 #[cfg(feature = "resampler")]
 pub(crate) fn decode_resampled(
     probed: &mut ProbeResult,
     codec_registry: &CodecRegistry,
-    pcm_sample_rate: u32,
+    params: DecodeParams,
     target_sample_rate: u32,
-    n_channels: usize,
-    mut resampler: crate::ResamplerRefMut,
-    max_bytes: usize,
+    resampler: crate::ResamplerRefMut,
 ) -> Result<DecodedAudioF32, LoadError> {
+    let layout = params.layout.clone();
+    let out_channels = layout.channels();
+
+    let mut final_buf = resample_packets(
+        probed,
+        codec_registry,
+        params,
+        target_sample_rate,
+        resampler,
+        |_, capacity| {
+            (0..out_channels)
+                .map(|_| {
+                    let mut m = Vec::new();
+                    m.reserve_exact(capacity);
+                    m
+                })
+                .collect::<Vec<Vec<f32>>>()
+        },
+    )?;
+
+    shrink_buffer(&mut final_buf);
+
+    Ok(DecodedAudioF32::new_with_layout(
+        final_buf,
+        target_sample_rate,
+        layout,
+    ))
+}
+
+/// Same as [`decode_resampled`], but the resampled samples are requantized to
+/// the format the file would be stored in if it was not resampled, one chunk at
+/// a time.
+#[cfg(feature = "resampler")]
+pub(crate) fn decode_resampled_native(
+    probed: &mut ProbeResult,
+    codec_registry: &CodecRegistry,
+    params: DecodeParams,
+    target_sample_rate: u32,
+    resampler: crate::ResamplerRefMut,
+) -> Result<DecodedAudio, LoadError> {
+    let layout = params.layout.clone();
+    let storage = params.storage;
+    let conversion = params.conversion;
+    #[cfg(feature = "f16")]
+    let float_as_f16 = params.float_as_f16;
+
+    let converter = resample_packets(
+        probed,
+        codec_registry,
+        params,
+        target_sample_rate,
+        resampler,
+        |decoded, capacity| {
+            let native = native_format(
+                decoded,
+                #[cfg(feature = "f16")]
+                float_as_f16,
+            );
+
+            FormatConverter::new(
+                SampleFormat::F32,
+                storage.target(native).unwrap_or(native),
+                &conversion,
+                layout.channels(),
+                capacity,
+            )
+        },
+    )?;
+
+    Ok(DecodedAudio::new_with_layout(
+        converter.finish(),
+        target_sample_rate,
+        layout,
+    ))
+}
+
+/// Decode, mix and resample every packet into the sink returned by
+/// `make_sink`, which is called with the first decoded packet (or the
+/// decoder's empty buffer if there is none) and the number of frames to
+/// allocate.
+#[cfg(feature = "resampler")]
+fn resample_packets<K: ResampledSink>(
+    probed: &mut ProbeResult,
+    codec_registry: &CodecRegistry,
+    params: DecodeParams,
+    target_sample_rate: u32,
+    resampler: crate::ResamplerRefMut,
+    mut make_sink: impl FnMut(&AudioBufferRef, usize) -> K,
+) -> Result<K, LoadError> {
+    let DecodeParams {
+        n_channels,
+        channel_map,
+        sample_rate: pcm_sample_rate,
+        mix,
+        layout,
+        max_bytes,
+        ..
+    } = params;
+
     assert_ne!(n_channels, 0);
 
-    resampler.reset();
+    // The number of channels after mixing.
+    let out_channels = layout.channels();
+
+    let mut resampler =
+        ChunkedResampler::new(resampler, out_channels, pcm_sample_rate, target_sample_rate)?;
 
     // Get the default track in the audio stream.
     let track = probed
@@ -34,70 +167,20 @@ pub(crate) fn decode_resampled(
         .ok_or_else(|| LoadError::NoTrackFound)?;
 
     let file_frames = track.codec_params.n_frames;
-    let max_frames = max_bytes / (4 * n_channels);
-
-    if let Some(frames) = file_frames {
-        if frames > max_frames as u64 {
-            return Err(LoadError::FileTooLarge(max_bytes));
-        }
-    }
 
     let decode_opts: DecoderOptions = Default::default();
 
     // Create a decoder for the track.
     let mut decoder = codec_registry
         .make(&track.codec_params, &decode_opts)
-        .map_err(|e| LoadError::CouldNotCreateDecoder(e))?;
+        .map_err(LoadError::CouldNotCreateDecoder)?;
 
     let mut tmp_conversion_buf: Option<AudioBuffer<f32>> = None;
-    let mut tmp_resampler_in_buf = vec![vec![0.0; resampler.input_frames_max()]; n_channels];
-    let mut tmp_resampler_out_buf = vec![vec![0.0; resampler.output_frames_max()]; n_channels];
-    let mut tmp_resampler_in_len = 0;
-
-    let estimated_final_frames = (file_frames.unwrap_or(44100) as f64
-        * (target_sample_rate as f64 / pcm_sample_rate as f64))
-        .ceil() as usize
-        + resampler.output_frames_max();
-    let mut final_buf: Vec<Vec<f32>> = (0..n_channels)
-        .map(|_| {
-            let mut m = Vec::new();
-            m.reserve_exact(estimated_final_frames);
-            m
-        })
-        .collect();
-
-    let mut total_in_frames: usize = 0;
+    let mut sink: Option<K> = None;
+    let mut max_frames = 0;
 
     let track_id = track.id;
 
-    let mut desired_tmp_in_frames = resampler.input_frames_next();
-    let mut delay_frames_left = resampler.output_delay();
-
-    let mut resample = |tmp_resampler_in_buf: &Vec<Vec<f32>>,
-                        tmp_resampler_out_buf: &mut Vec<Vec<f32>>,
-                        final_buf: &mut Vec<Vec<f32>>,
-                        tmp_resampler_in_len: &mut usize,
-                        desired_tmp_in_frames: &mut usize|
-     -> Result<(), LoadError> {
-        let (_, output_frames) =
-            resampler.process_into_buffer(tmp_resampler_in_buf, tmp_resampler_out_buf, None)?;
-
-        if delay_frames_left >= output_frames {
-            // Wait until the first non-delayed output sample.
-            delay_frames_left -= output_frames;
-        } else {
-            for (final_ch, res_ch) in final_buf.iter_mut().zip(tmp_resampler_out_buf.iter()) {
-                final_ch.extend_from_slice(&res_ch[delay_frames_left..output_frames]);
-            }
-            delay_frames_left = 0;
-        }
-
-        *desired_tmp_in_frames = resampler.input_frames_next();
-        *tmp_resampler_in_len = 0;
-
-        Ok(())
-    };
-
     while let Ok(packet) = probed.format.next_packet() {
         // If the packet does not belong to the selected track, skip over it.
         if packet.track_id() != track_id {
@@ -106,6 +189,25 @@ pub(crate) fn decode_resampled(
 
         match decoder.decode(&packet) {
             Ok(decoded) => {
+                // If this is the first decoded packet, create the sink now that the
+                // sample format of the file is known.
+                if sink.is_none() {
+                    let estimated_final_frames =
+                        resampler.max_output_frames(file_frames.unwrap_or(44100) as usize);
+                    let new_sink = make_sink(&decoded, estimated_final_frames);
+
+                    max_frames = max_bytes / (new_sink.sample_bytes() * out_channels);
+
+                    if let Some(frames) = file_frames {
+                        if frames > max_frames as u64 {
+                            return Err(LoadError::FileTooLarge(max_bytes));
+                        }
+                    }
+
+                    sink = Some(new_sink);
+                }
+                let sink = sink.as_mut().unwrap();
+
                 // If this is the first decoded packet, allocate the temporary conversion
                 // buffer with the required capacity.
                 if tmp_conversion_buf.is_none() {
@@ -124,110 +226,63 @@ pub(crate) fn decode_resampled(
 
                 decoded.convert(tmp_conversion_buf);
                 let tmp_conversion_planes = tmp_conversion_buf.planes();
-                let converted_planes = tmp_conversion_planes.planes();
-
-                // Fill the temporary input buffer for the resampler.
-                let decoded_frames = tmp_conversion_buf.frames();
-                let mut total_copied_frames = 0;
-                while total_copied_frames < decoded_frames {
-                    let copy_frames = (decoded_frames - total_copied_frames)
-                        .min(desired_tmp_in_frames - tmp_resampler_in_len);
-                    for (tmp_ch, decoded_ch) in
-                        tmp_resampler_in_buf.iter_mut().zip(converted_planes)
-                    {
-                        tmp_ch[tmp_resampler_in_len..tmp_resampler_in_len + copy_frames]
-                            .copy_from_slice(
-                                &decoded_ch[total_copied_frames..total_copied_frames + copy_frames],
+                let converted_planes = &select_planes(tmp_conversion_planes.planes(), channel_map);
+
+                resampler.process(
+                    tmp_conversion_buf.frames(),
+                    |ch, src_start, buf| {
+                        if let Some(mix) = mix {
+                            mix_planes(converted_planes, mix.row(ch), src_start, buf);
+                        } else {
+                            buf.copy_from_slice(
+                                &converted_planes[ch][src_start..src_start + buf.len()],
                             );
-                    }
-
-                    tmp_resampler_in_len += copy_frames;
-                    if tmp_resampler_in_len == desired_tmp_in_frames {
-                        resample(
-                            &tmp_resampler_in_buf,
-                            &mut tmp_resampler_out_buf,
-                            &mut final_buf,
-                            &mut tmp_resampler_in_len,
-                            &mut desired_tmp_in_frames,
-                        )?;
-                    }
-
-                    total_copied_frames += copy_frames;
-                }
+                        }
+                    },
+                    sink,
+                )?;
 
                 if file_frames.is_none() {
                     // Protect against really large files causing out of memory errors.
-                    if final_buf[0].len() > max_frames {
+                    if sink.frames() > max_frames {
                         return Err(LoadError::FileTooLarge(max_bytes));
                     }
                 }
-
-                total_in_frames += decoded_frames;
             }
             Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
             Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
         }
     }
 
-    let total_frames = (total_in_frames as f64
-        * (target_sample_rate as f64 / pcm_sample_rate as f64))
-        .ceil() as usize;
-
-    // Process any leftover samples.
-    if tmp_resampler_in_len > 0 {
-        // Zero-pad remaining samples.
-        for ch in tmp_resampler_in_buf.iter_mut() {
-            ch[tmp_resampler_in_len..desired_tmp_in_frames].fill(0.0);
-        }
-
-        resample(
-            &tmp_resampler_in_buf,
-            &mut tmp_resampler_out_buf,
-            &mut final_buf,
-            &mut tmp_resampler_in_len,
-            &mut desired_tmp_in_frames,
-        )?;
-    }
-
-    // Extract any leftover samples from the resampler.
-    while final_buf[0].len() < total_frames {
-        // Clear samples.
-        for ch in tmp_resampler_in_buf.iter_mut() {
-            ch[..desired_tmp_in_frames].fill(0.0);
-        }
+    // If no packet was decoded, create the sink from the decoder's empty buffer
+    // instead, so that it still has the format the samples would have had.
+    let mut sink = sink.unwrap_or_else(|| make_sink(&decoder.last_decoded(), 0));
 
-        resample(
-            &tmp_resampler_in_buf,
-            &mut tmp_resampler_out_buf,
-            &mut final_buf,
-            &mut tmp_resampler_in_len,
-            &mut desired_tmp_in_frames,
-        )?;
-    }
-
-    // Truncate the extra padded data.
-    for ch in final_buf.iter_mut() {
-        ch.resize(total_frames, 0.0);
-
-        // If the allocated capacity is significantly greater than the
-        // length, shrink it to save memory.
-        if ch.capacity() > ch.len() + SHRINK_THRESHOLD {
-            ch.shrink_to_fit();
-        }
-    }
+    resampler.finish(&mut sink)?;
 
-    Ok(DecodedAudioF32::new(final_buf, target_sample_rate))
+    Ok(sink)
 }
 
 pub(crate) fn decode_f32(
     probed: &mut ProbeResult,
-    n_channels: usize,
     codec_registry: &CodecRegistry,
-    sample_rate: u32,
-    max_bytes: usize,
+    params: DecodeParams,
 ) -> Result<DecodedAudioF32, LoadError> {
+    let DecodeParams {
+        n_channels,
+        channel_map,
+        sample_rate,
+        mix,
+        layout,
+        max_bytes,
+        ..
+    } = params;
+
     assert_ne!(n_channels, 0);
 
+    // The number of channels after mixing.
+    let out_channels = layout.channels();
+
     // Get the default track in the audio stream.
     let track = probed
         .format
@@ -235,7 +290,7 @@ pub(crate) fn decode_f32(
         .ok_or_else(|| LoadError::NoTrackFound)?;
 
     let file_frames = track.codec_params.n_frames;
-    let max_frames = max_bytes / (4 * n_channels);
+    let max_frames = max_bytes / (4 * out_channels);
 
     if let Some(frames) = file_frames {
         if frames > max_frames as u64 {
@@ -248,12 +303,12 @@ pub(crate) fn decode_f32(
     // Create a decoder for the track.
     let mut decoder = codec_registry
         .make(&track.codec_params, &decode_opts)
-        .map_err(|e| LoadError::CouldNotCreateDecoder(e))?;
+        .map_err(LoadError::CouldNotCreateDecoder)?;
 
     let mut tmp_conversion_buf: Option<AudioBuffer<f32>> = None;
 
     let estimated_final_frames = file_frames.unwrap_or(44100) as usize;
-    let mut final_buf: Vec<Vec<f32>> = (0..n_channels)
+    let mut final_buf: Vec<Vec<f32>> = (0..out_channels)
         .map(|_| {
             let mut m = Vec::new();
             m.reserve_exact(estimated_final_frames);
@@ -290,10 +345,21 @@ pub(crate) fn decode_f32(
                 decoded.convert(tmp_conversion_buf);
 
                 let tmp_conversion_planes = tmp_conversion_buf.planes();
-                let converted_planes = tmp_conversion_planes.planes();
+                let converted_planes = &select_planes(tmp_conversion_planes.planes(), channel_map);
+
+                if let Some(mix) = mix {
+                    let decoded_frames = tmp_conversion_buf.frames();
+
+                    for (out_ch, final_ch) in final_buf.iter_mut().enumerate() {
+                        let start = final_ch.len();
+                        final_ch.resize(start + decoded_frames, 0.0);
 
-                for (final_ch, decoded_ch) in final_buf.iter_mut().zip(converted_planes) {
-                    final_ch.extend_from_slice(&decoded_ch);
+                        mix_planes(converted_planes, mix.row(out_ch), 0, &mut final_ch[start..]);
+                    }
+                } else {
+                    for (final_ch, decoded_ch) in final_buf.iter_mut().zip(converted_planes) {
+                        final_ch.extend_from_slice(decoded_ch);
+                    }
                 }
 
                 if file_frames.is_none() {
@@ -310,16 +376,31 @@ pub(crate) fn decode_f32(
 
     shrink_buffer(&mut final_buf);
 
-    Ok(DecodedAudioF32::new(final_buf, sample_rate))
+    Ok(DecodedAudioF32::new_with_layout(
+        final_buf,
+        sample_rate,
+        layout,
+    ))
 }
 
 pub(crate) fn decode_native_bitdepth(
     probed: &mut ProbeResult,
-    n_channels: usize,
     codec_registry: &CodecRegistry,
-    sample_rate: u32,
-    max_bytes: usize,
+    params: DecodeParams,
 ) -> Result<DecodedAudio, LoadError> {
+    let DecodeParams {
+        n_channels,
+        channel_map,
+        sample_rate,
+        mix,
+        layout,
+        max_bytes,
+        #[cfg(feature = "f16")]
+        float_as_f16,
+        storage,
+        conversion,
+    } = params;
+
     assert_ne!(n_channels, 0);
 
     // Get the default track in the audio stream.
@@ -328,692 +409,450 @@ pub(crate) fn decode_native_bitdepth(
         .default_track()
         .ok_or_else(|| LoadError::NoTrackFound)?;
 
-    let file_frames = track.codec_params.n_frames;
-
     let decode_opts: DecoderOptions = Default::default();
 
     // Create a decoder for the track.
     let mut decoder = codec_registry
         .make(&track.codec_params, &decode_opts)
-        .map_err(|e| LoadError::CouldNotCreateDecoder(e))?;
-
-    let mut max_frames = 0;
-    let mut total_frames = 0;
-
-    enum FirstPacketType {
-        U8(Vec<Vec<u8>>),
-        U16(Vec<Vec<u16>>),
-        U24(Vec<Vec<[u8; 3]>>),
-        U32(Vec<Vec<f32>>),
-        S8(Vec<Vec<i8>>),
-        S16(Vec<Vec<i16>>),
-        S24(Vec<Vec<[u8; 3]>>),
-        S32(Vec<Vec<f32>>),
-        F32(Vec<Vec<f32>>),
-        F64(Vec<Vec<f64>>),
-    }
-
-    let track_id = track.id;
+        .map_err(LoadError::CouldNotCreateDecoder)?;
+
+    let mut state = NativeDecodeState {
+        track_id: track.id,
+        planes: channel_map.map_or_else(|| (0..n_channels).collect(), <[usize]>::to_vec),
+        mix,
+        mixed_channels: vec![Vec::new(); layout.channels()],
+        file_frames: track.codec_params.n_frames,
+        max_bytes,
+        max_frames: 0,
+        total_frames: 0,
+    };
 
-    let check_total_frames =
-        |total_frames: &mut usize, max_frames: usize, packet_len: usize| -> Result<(), LoadError> {
-            *total_frames += packet_len;
-            if *total_frames > max_frames {
-                Err(LoadError::FileTooLarge(max_bytes))
-            } else {
-                Ok(())
+    // Decode the first packet to get the sample format, and then decode the
+    // rest of the packets in that format.
+    macro_rules! decode_rest {
+        ($t:ty => $stored:ty, $first:expr) => {{
+            // The samples are converted one packet at a time if they are stored
+            // in another format or mixed.
+            let native = <$stored as Sample>::FORMAT;
+            match storage.target(native).or(mix.map(|_| native)) {
+                Some(format) => {
+                    let started =
+                        state.start_converted::<$t, $stored>($first, format, &conversion)?;
+                    state.finish_converted::<$t, $stored>(
+                        started,
+                        decoder.as_mut(),
+                        probed.format.as_mut(),
+                    )?
+                }
+                None => {
+                    let decoded_channels = state.start::<$t, $stored>($first)?;
+                    state.finish::<$t, $stored>(
+                        decoded_channels,
+                        decoder.as_mut(),
+                        probed.format.as_mut(),
+                    )?
+                }
             }
-        };
+        }};
+    }
 
-    // Decode the first packet to get the sample format.
-    let mut first_packet = None;
     while let Ok(packet) = probed.format.next_packet() {
         // If the packet does not belong to the selected track, skip over it.
-        if packet.track_id() != track_id {
+        if packet.track_id() != state.track_id {
             continue;
         }
 
-        match decoder.decode(&packet) {
+        let pcm_type = match decoder.decode(&packet) {
             Ok(decoded) => match decoded {
-                AudioBufferRef::U8(d) => {
-                    let mut decoded_channels = Vec::<Vec<u8>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / n_channels;
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_u8_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::U8(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::U16(d) => {
-                    let mut decoded_channels = Vec::<Vec<u16>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / (2 * n_channels);
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_u16_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::U16(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::U24(d) => {
-                    let mut decoded_channels = Vec::<Vec<[u8; 3]>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / (3 * n_channels);
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_u24_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::U24(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::U32(d) => {
-                    let mut decoded_channels = Vec::<Vec<f32>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / (4 * n_channels);
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_u32_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::U32(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::S8(d) => {
-                    let mut decoded_channels = Vec::<Vec<i8>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / n_channels;
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_i8_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::S8(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::S16(d) => {
-                    let mut decoded_channels = Vec::<Vec<i16>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / (2 * n_channels);
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_i16_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::S16(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::S24(d) => {
-                    let mut decoded_channels = Vec::<Vec<[u8; 3]>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / (3 * n_channels);
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_i24_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::S24(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::S32(d) => {
-                    let mut decoded_channels = Vec::<Vec<f32>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / (4 * n_channels);
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_i32_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::S32(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::F32(d) => {
-                    let mut decoded_channels = Vec::<Vec<f32>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / (4 * n_channels);
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_f32_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::F32(decoded_channels));
-                    break;
-                }
-                AudioBufferRef::F64(d) => {
-                    let mut decoded_channels = Vec::<Vec<f64>>::new();
-                    for _ in 0..n_channels {
-                        decoded_channels
-                            .push(Vec::with_capacity(file_frames.unwrap_or(0) as usize));
-                    }
-
-                    max_frames = max_bytes / (8 * n_channels);
-                    if let Some(file_frames) = file_frames {
-                        if file_frames > max_frames as u64 {
-                            return Err(LoadError::FileTooLarge(max_bytes));
-                        }
-                    } else {
-                        check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                    }
-
-                    decode_f64_packet(&mut decoded_channels, d, n_channels);
-
-                    first_packet = Some(FirstPacketType::F64(decoded_channels));
-                    break;
-                }
+                #[cfg(feature = "f16")]
+                AudioBufferRef::F32(d) if float_as_f16 => decode_rest!(f32 => half::f16, d),
+                #[cfg(feature = "f16")]
+                AudioBufferRef::F64(d) if float_as_f16 => decode_rest!(f64 => half::f16, d),
+                AudioBufferRef::U8(d) => decode_rest!(u8 => u8, d),
+                AudioBufferRef::U16(d) => decode_rest!(u16 => u16, d),
+                AudioBufferRef::U24(d) => decode_rest!(u24 => U24, d),
+                AudioBufferRef::U32(d) => decode_rest!(u32 => f32, d),
+                AudioBufferRef::S8(d) => decode_rest!(i8 => i8, d),
+                AudioBufferRef::S16(d) => decode_rest!(i16 => i16, d),
+                AudioBufferRef::S24(d) => decode_rest!(i24 => I24, d),
+                AudioBufferRef::S32(d) => decode_rest!(i32 => f32, d),
+                AudioBufferRef::F32(d) => decode_rest!(f32 => f32, d),
+                AudioBufferRef::F64(d) => decode_rest!(f64 => f64, d),
             },
             Err(symphonia::core::errors::Error::DecodeError(err)) => {
                 decode_warning(err);
+                continue;
             }
             Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
         };
-    }
 
-    if first_packet.is_none() {
-        return Err(LoadError::UnexpectedErrorWhileDecoding(
-            "no packet was found".into(),
-        ));
+        return Ok(DecodedAudio::new_with_layout(pcm_type, sample_rate, layout));
     }
 
-    let unexpected_format = |expected: &str| -> LoadError {
-        LoadError::UnexpectedErrorWhileDecoding(
-            format!(
-                "Symphonia returned a packet that was not the expected format of {}",
-                expected
-            )
-            .into(),
-        )
-    };
-
-    let pcm_type = match first_packet.take().unwrap() {
-        FirstPacketType::U8(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
+    Err(LoadError::UnexpectedErrorWhileDecoding(
+        "no packet was found".into(),
+    ))
+}
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::U8(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+/// The format [`decode_native_bitdepth`] stores samples decoded as `decoded` in.
+#[cfg(feature = "resampler")]
+fn native_format(
+    decoded: &AudioBufferRef,
+    #[cfg(feature = "f16")] float_as_f16: bool,
+) -> SampleFormat {
+    match decoded {
+        #[cfg(feature = "f16")]
+        AudioBufferRef::F32(_) | AudioBufferRef::F64(_) if float_as_f16 => SampleFormat::F16,
+        AudioBufferRef::U8(_) => SampleFormat::U8,
+        AudioBufferRef::U16(_) => SampleFormat::U16,
+        AudioBufferRef::U24(_) => SampleFormat::U24,
+        AudioBufferRef::U32(_) => SampleFormat::F32,
+        AudioBufferRef::S8(_) => SampleFormat::S8,
+        AudioBufferRef::S16(_) => SampleFormat::S16,
+        AudioBufferRef::S24(_) => SampleFormat::S24,
+        AudioBufferRef::S32(_) => SampleFormat::F32,
+        AudioBufferRef::F32(_) => SampleFormat::F32,
+        AudioBufferRef::F64(_) => SampleFormat::F64,
+    }
+}
 
-                            decode_u8_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("u8")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
-            }
+/// A sample type Symphonia can decode packets into.
+trait PacketSample: SymphoniaSample {
+    const NAME: &'static str;
 
-            shrink_buffer(&mut decoded_channels);
+    /// Returns the packet if its samples are of this type.
+    fn packet(decoded: AudioBufferRef) -> Option<Cow<AudioBuffer<Self>>>;
+}
 
-            DecodedAudioType::U8(decoded_channels)
-        }
-        FirstPacketType::U16(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
+/// How samples decoded as `Self` are stored as `S`.
+trait DecodeInto<S: Sample>: PacketSample {
+    fn extend(decoded_ch: &mut Vec<S>, samples: &[Self]);
+}
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::U16(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+macro_rules! impl_packet_sample {
+    ($t:ty, $variant:ident) => {
+        impl PacketSample for $t {
+            const NAME: &'static str = stringify!($t);
 
-                            decode_u16_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("u16")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
+            fn packet(decoded: AudioBufferRef) -> Option<Cow<AudioBuffer<Self>>> {
+                match decoded {
+                    AudioBufferRef::$variant(d) => Some(d),
+                    _ => None,
                 }
             }
-
-            shrink_buffer(&mut decoded_channels);
-
-            DecodedAudioType::U16(decoded_channels)
         }
-        FirstPacketType::U24(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
-
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::U24(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+    };
+}
 
-                            decode_u24_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("u24")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
+impl_packet_sample!(u8, U8);
+impl_packet_sample!(u16, U16);
+impl_packet_sample!(u24, U24);
+impl_packet_sample!(u32, U32);
+impl_packet_sample!(i8, S8);
+impl_packet_sample!(i16, S16);
+impl_packet_sample!(i24, S24);
+impl_packet_sample!(i32, S32);
+impl_packet_sample!(f32, F32);
+impl_packet_sample!(f64, F64);
+
+macro_rules! impl_decode_into {
+    ($t:ty => $stored:ty, |$decoded_ch:ident, $samples:ident| $extend:expr) => {
+        impl DecodeInto<$stored> for $t {
+            #[inline]
+            fn extend($decoded_ch: &mut Vec<$stored>, $samples: &[Self]) {
+                $extend
             }
-
-            shrink_buffer(&mut decoded_channels);
-
-            DecodedAudioType::U24(decoded_channels)
         }
-        FirstPacketType::U32(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
+    };
+}
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::U32(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+impl_decode_into!(u8 => u8, |decoded_ch, samples| decoded_ch.extend_from_slice(samples));
+impl_decode_into!(u16 => u16, |decoded_ch, samples| decoded_ch.extend_from_slice(samples));
+impl_decode_into!(u24 => U24, |decoded_ch, samples| decoded_ch
+    .extend(samples.iter().map(|s| U24(s.to_ne_bytes()))));
+impl_decode_into!(u32 => f32, |decoded_ch, samples| {
+    let start = decoded_ch.len();
+
+    decoded_ch.resize(start + samples.len(), 0.0);
+    convert::pcm_u32_slice_to_f32(samples, &mut decoded_ch[start..]);
+});
+impl_decode_into!(i8 => i8, |decoded_ch, samples| decoded_ch.extend_from_slice(samples));
+impl_decode_into!(i16 => i16, |decoded_ch, samples| decoded_ch.extend_from_slice(samples));
+impl_decode_into!(i24 => I24, |decoded_ch, samples| decoded_ch
+    .extend(samples.iter().map(|s| I24(s.to_ne_bytes()))));
+impl_decode_into!(i32 => f32, |decoded_ch, samples| {
+    let start = decoded_ch.len();
+
+    decoded_ch.resize(start + samples.len(), 0.0);
+    convert::pcm_i32_slice_to_f32(samples, &mut decoded_ch[start..]);
+});
+impl_decode_into!(f32 => f32, |decoded_ch, samples| decoded_ch.extend_from_slice(samples));
+impl_decode_into!(f64 => f64, |decoded_ch, samples| decoded_ch.extend_from_slice(samples));
+#[cfg(feature = "f16")]
+impl_decode_into!(f32 => half::f16, |decoded_ch, samples| {
+    use half::slice::HalfFloatSliceExt;
+
+    let start = decoded_ch.len();
+
+    decoded_ch.resize(start + samples.len(), half::f16::ZERO);
+    decoded_ch[start..].convert_from_f32_slice(samples);
+});
+#[cfg(feature = "f16")]
+impl_decode_into!(f64 => half::f16, |decoded_ch, samples| {
+    use half::slice::HalfFloatSliceExt;
+
+    let start = decoded_ch.len();
+
+    decoded_ch.resize(start + samples.len(), half::f16::ZERO);
+    decoded_ch[start..].convert_from_f64_slice(samples);
+});
+
+/// The state of decoding a resource in its native sample format.
+struct NativeDecodeState<'a> {
+    track_id: u32,
+    /// The planes of the decoded packets to keep, in order.
+    planes: Vec<usize>,
+    /// The matrix to mix the planes with, if any.
+    mix: Option<&'a MixMatrix>,
+    /// Holds the mixed samples of one packet before they are converted, with
+    /// one channel for each channel that is stored.
+    mixed_channels: Vec<Vec<f64>>,
+    file_frames: Option<u64>,
+    max_bytes: usize,
+    max_frames: usize,
+    total_frames: usize,
+}
 
-                            decode_u32_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("u32")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
-            }
+impl NativeDecodeState<'_> {
+    /// Allocate the channels for the samples in the first packet and decode it.
+    fn start<P: DecodeInto<S>, S: Sample>(
+        &mut self,
+        first: Cow<AudioBuffer<P>>,
+    ) -> Result<Vec<Vec<S>>, LoadError> {
+        self.set_max_frames(S::BYTES)?;
 
-            shrink_buffer(&mut decoded_channels);
+        let capacity = self.file_frames.unwrap_or(0) as usize;
+        let mut decoded_channels: Vec<Vec<S>> = (0..self.planes.len())
+            .map(|_| Vec::with_capacity(capacity))
+            .collect();
 
-            DecodedAudioType::F32(decoded_channels)
-        }
-        FirstPacketType::S8(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
+        self.decode_packet(&mut decoded_channels, first)?;
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::S8(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+        Ok(decoded_channels)
+    }
 
-                            decode_i8_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("i8")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
-            }
+    /// Decode the rest of the packets, which must all be the same format as
+    /// the first one.
+    fn finish<P: DecodeInto<S>, S: Sample>(
+        &mut self,
+        mut decoded_channels: Vec<Vec<S>>,
+        decoder: &mut dyn Decoder,
+        format: &mut dyn FormatReader,
+    ) -> Result<DecodedAudioType, LoadError> {
+        self.for_each_packet::<P>(decoder, format, |state, packet| {
+            state.decode_packet(&mut decoded_channels, packet)
+        })?;
+
+        shrink_buffer(&mut decoded_channels);
+
+        Ok(S::into_audio_type(decoded_channels))
+    }
 
-            shrink_buffer(&mut decoded_channels);
+    /// Create a converter to `to` and convert the samples in the first packet
+    /// with it.
+    fn start_converted<P: DecodeInto<S>, S: Sample>(
+        &mut self,
+        first: Cow<AudioBuffer<P>>,
+        to: SampleFormat,
+        options: &ConversionOptions,
+    ) -> Result<(FormatConverter, Vec<Vec<S>>), LoadError> {
+        self.set_max_frames(to.bytes())?;
+
+        // Mixed samples are requantized from `f64`.
+        let from = if self.mix.is_some() {
+            SampleFormat::F64
+        } else {
+            S::FORMAT
+        };
 
-            DecodedAudioType::S8(decoded_channels)
-        }
-        FirstPacketType::S16(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
+        let capacity = self.file_frames.unwrap_or(0) as usize;
+        let mut converter =
+            FormatConverter::new(from, to, options, self.mixed_channels.len(), capacity);
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::S16(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+        // Holds the samples of one packet before they are converted, so that
+        // the samples are never all held in their native format.
+        let mut packet_channels: Vec<Vec<S>> = vec![Vec::new(); self.planes.len()];
 
-                            decode_i16_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("i16")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
-            }
+        self.convert_packet(&mut converter, &mut packet_channels, first)?;
+
+        Ok((converter, packet_channels))
+    }
 
-            shrink_buffer(&mut decoded_channels);
+    /// Decode the rest of the packets and convert them one at a time.
+    fn finish_converted<P: DecodeInto<S>, S: Sample>(
+        &mut self,
+        (mut converter, mut packet_channels): (FormatConverter, Vec<Vec<S>>),
+        decoder: &mut dyn Decoder,
+        format: &mut dyn FormatReader,
+    ) -> Result<DecodedAudioType, LoadError> {
+        self.for_each_packet::<P>(decoder, format, |state, packet| {
+            state.convert_packet(&mut converter, &mut packet_channels, packet)
+        })?;
+
+        Ok(converter.finish())
+    }
 
-            DecodedAudioType::S16(decoded_channels)
+    fn convert_packet<P: DecodeInto<S>, S: Sample>(
+        &mut self,
+        converter: &mut FormatConverter,
+        packet_channels: &mut [Vec<S>],
+        packet: Cow<AudioBuffer<P>>,
+    ) -> Result<(), LoadError> {
+        for ch in packet_channels.iter_mut() {
+            ch.clear();
         }
-        FirstPacketType::S24(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::S24(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+        self.decode_packet(packet_channels, packet)?;
 
-                            decode_i24_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("i24")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
-            }
-
-            shrink_buffer(&mut decoded_channels);
+        match self.mix {
+            Some(mix) => {
+                mix_channels(packet_channels, mix, &mut self.mixed_channels);
 
-            DecodedAudioType::S24(decoded_channels)
+                let pcm: Vec<&[f64]> = self.mixed_channels.iter().map(Vec::as_slice).collect();
+                converter.push(&pcm);
+            }
+            None => {
+                let pcm: Vec<&[S]> = packet_channels.iter().map(Vec::as_slice).collect();
+                converter.push(&pcm);
+            }
         }
-        FirstPacketType::S32(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::S32(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+        Ok(())
+    }
 
-                            decode_i32_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("i32")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
+    /// Calculate the maximum number of frames from the size of one sample,
+    /// and check the number of frames in the file against it.
+    fn set_max_frames(&mut self, bytes_per_sample: usize) -> Result<(), LoadError> {
+        self.max_frames = self.max_bytes / (bytes_per_sample * self.mixed_channels.len());
+        if let Some(file_frames) = self.file_frames {
+            if file_frames > self.max_frames as u64 {
+                return Err(LoadError::FileTooLarge(self.max_bytes));
             }
-
-            shrink_buffer(&mut decoded_channels);
-
-            DecodedAudioType::F32(decoded_channels)
         }
-        FirstPacketType::F32(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::F32(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+        Ok(())
+    }
 
-                            decode_f32_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("f32")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
+    /// Decode the rest of the packets in the track, which must all be in the
+    /// format `P`, and pass each of them to `f`.
+    fn for_each_packet<P: PacketSample>(
+        &mut self,
+        decoder: &mut dyn Decoder,
+        format: &mut dyn FormatReader,
+        mut f: impl FnMut(&mut Self, Cow<AudioBuffer<P>>) -> Result<(), LoadError>,
+    ) -> Result<(), LoadError> {
+        while let Ok(packet) = format.next_packet() {
+            // If the packet does not belong to the selected track, skip over it.
+            if packet.track_id() != self.track_id {
+                continue;
             }
 
-            shrink_buffer(&mut decoded_channels);
-
-            DecodedAudioType::F32(decoded_channels)
+            match decoder.decode(&packet) {
+                Ok(decoded) => match P::packet(decoded) {
+                    Some(d) => f(self, d)?,
+                    None => {
+                        return Err(LoadError::UnexpectedErrorWhileDecoding(
+                            format!(
+                            "Symphonia returned a packet that was not the expected format of {}",
+                            P::NAME
+                        )
+                            .into(),
+                        ))
+                    }
+                },
+                Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
+                Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
+            }
         }
-        FirstPacketType::F64(mut decoded_channels) => {
-            while let Ok(packet) = probed.format.next_packet() {
-                // If the packet does not belong to the selected track, skip over it.
-                if packet.track_id() != track_id {
-                    continue;
-                }
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => match decoded {
-                        AudioBufferRef::F64(d) => {
-                            if file_frames.is_none() {
-                                check_total_frames(&mut total_frames, max_frames, d.chan(0).len())?;
-                            }
+        Ok(())
+    }
 
-                            decode_f64_packet(&mut decoded_channels, d, n_channels);
-                        }
-                        _ => return Err(unexpected_format("f64")),
-                    },
-                    Err(symphonia::core::errors::Error::DecodeError(err)) => decode_warning(err),
-                    Err(e) => return Err(LoadError::ErrorWhileDecoding(e)),
-                }
+    fn decode_packet<P: DecodeInto<S>, S: Sample>(
+        &mut self,
+        decoded_channels: &mut [Vec<S>],
+        packet: Cow<AudioBuffer<P>>,
+    ) -> Result<(), LoadError> {
+        if self.file_frames.is_none() {
+            // Protect against really large files causing out of memory errors.
+            self.total_frames += packet.frames();
+            if self.total_frames > self.max_frames {
+                return Err(LoadError::FileTooLarge(self.max_bytes));
             }
-
-            shrink_buffer(&mut decoded_channels);
-
-            DecodedAudioType::F64(decoded_channels)
         }
-    };
 
-    Ok(DecodedAudio::new(pcm_type, sample_rate))
-}
-
-fn shrink_buffer<T>(channels: &mut [Vec<T>]) {
-    for ch in channels.iter_mut() {
-        // If the allocated capacity is significantly greater than the
-        // length, shrink it to save memory.
-        if ch.capacity() > ch.len() + SHRINK_THRESHOLD {
-            ch.shrink_to_fit();
+        for (decoded_ch, &plane) in decoded_channels.iter_mut().zip(&self.planes) {
+            P::extend(decoded_ch, packet.chan(plane));
         }
-    }
-}
-
-#[inline]
-fn decode_u8_packet(
-    decoded_channels: &mut Vec<Vec<u8>>,
-    packet: Cow<AudioBuffer<u8>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        decoded_channels[i].extend_from_slice(packet.chan(i));
-    }
-}
 
-#[inline]
-fn decode_u16_packet(
-    decoded_channels: &mut Vec<Vec<u16>>,
-    packet: Cow<AudioBuffer<u16>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        decoded_channels[i].extend_from_slice(packet.chan(i));
+        Ok(())
     }
 }
 
-#[inline]
-fn decode_u24_packet(
-    decoded_channels: &mut Vec<Vec<[u8; 3]>>,
-    packet: Cow<AudioBuffer<u24>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        for s in packet.chan(i).iter() {
-            decoded_channels[i].push(s.to_ne_bytes());
-        }
+/// Returns the planes of the channels in `channel_map`, in order, or all of
+/// the planes if it is `None`.
+fn select_planes<'p, T>(planes: &[&'p [T]], channel_map: Option<&[usize]>) -> Vec<&'p [T]> {
+    match channel_map {
+        Some(channel_map) => channel_map.iter().map(|&plane| planes[plane]).collect(),
+        None => planes.to_vec(),
     }
 }
 
-#[inline]
-fn decode_u32_packet(
-    decoded_channels: &mut Vec<Vec<f32>>,
-    packet: Cow<AudioBuffer<u32>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        for s in packet.chan(i).iter() {
-            let s_f32 = convert::pcm_u32_to_f32(*s);
-
-            decoded_channels[i].push(s_f32);
-        }
-    }
-}
+/// Mix the channels of one packet into `out` using the given matrix. This is
+/// done in `f64` so that no precision is lost before the samples are
+/// requantized.
+fn mix_channels<S: Sample>(channels: &[Vec<S>], mix: &MixMatrix, out: &mut [Vec<f64>]) {
+    let frames = channels.first().map_or(0, Vec::len);
 
-#[inline]
-fn decode_i8_packet(
-    decoded_channels: &mut Vec<Vec<i8>>,
-    packet: Cow<AudioBuffer<i8>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        decoded_channels[i].extend_from_slice(packet.chan(i));
-    }
-}
+    for (out_ch, mixed_ch) in out.iter_mut().enumerate() {
+        mixed_ch.clear();
+        mixed_ch.resize(frames, 0.0);
 
-#[inline]
-fn decode_i16_packet(
-    decoded_channels: &mut Vec<Vec<i16>>,
-    packet: Cow<AudioBuffer<i16>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        decoded_channels[i].extend_from_slice(packet.chan(i));
-    }
-}
+        for (ch, &gain) in channels.iter().zip(mix.row(out_ch)) {
+            if gain == 0.0 {
+                continue;
+            }
 
-#[inline]
-fn decode_i24_packet(
-    decoded_channels: &mut Vec<Vec<[u8; 3]>>,
-    packet: Cow<AudioBuffer<i24>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        for s in packet.chan(i).iter() {
-            decoded_channels[i].push(s.to_ne_bytes());
+            for (out_s, &s) in mixed_ch.iter_mut().zip(ch) {
+                *out_s += s.to_f64() * f64::from(gain);
+            }
         }
     }
 }
 
-#[inline]
-fn decode_i32_packet(
-    decoded_channels: &mut Vec<Vec<f32>>,
-    packet: Cow<AudioBuffer<i32>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        for s in packet.chan(i).iter() {
-            let s_f32 = convert::pcm_i32_to_f32(*s);
-
-            decoded_channels[i].push(s_f32);
+/// Mix the decoded planes starting at `src_start` into `out` using the given
+/// gains, one for each plane.
+fn mix_planes(planes: &[&[f32]], gains: &[f32], src_start: usize, out: &mut [f32]) {
+    out.fill(0.0);
+
+    for (plane, &gain) in planes.iter().zip(gains) {
+        if gain == 0.0 {
+            continue;
         }
-    }
-}
 
-#[inline]
-fn decode_f32_packet(
-    decoded_channels: &mut Vec<Vec<f32>>,
-    packet: Cow<AudioBuffer<f32>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        decoded_channels[i].extend_from_slice(packet.chan(i));
+        for (out_s, &s) in out.iter_mut().zip(&plane[src_start..]) {
+            *out_s += s * gain;
+        }
     }
 }
 
-#[inline]
-fn decode_f64_packet(
-    decoded_channels: &mut Vec<Vec<f64>>,
-    packet: Cow<AudioBuffer<f64>>,
-    num_channels: usize,
-) {
-    for i in 0..num_channels {
-        decoded_channels[i].extend_from_slice(packet.chan(i));
+fn shrink_buffer<T>(channels: &mut [Vec<T>]) {
+    for ch in channels.iter_mut() {
+        // If the allocated capacity is significantly greater than the
+        // length, shrink it to save memory.
+        if ch.capacity() > ch.len() + SHRINK_THRESHOLD {
+            ch.shrink_to_fit();
+        }
     }
 }
 
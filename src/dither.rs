@@ -0,0 +1,131 @@
+/// A generator of triangular probability density function (TPDF) dither.
+///
+/// Adding TPDF dither before reducing the bit depth of a signal turns the
+/// distortion caused by rounding into a constant, signal-independent noise
+/// floor.
+///
+/// This uses a small xorshift generator, so it does not allocate and is safe
+/// to use on the realtime thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TpdfDither {
+    state: u32,
+}
+
+impl TpdfDither {
+    /// Create a new dither generator with the given seed.
+    pub fn new(seed: u32) -> Self {
+        Self {
+            // The state of a xorshift generator must never be zero.
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    /// Returns the next dither value in units of the least significant bit,
+    /// in the range `(-1.0, 1.0)`.
+    #[inline]
+    pub fn next_lsb(&mut self) -> f32 {
+        self.next_uniform() - self.next_uniform()
+    }
+
+    /// Returns a uniformly distributed value in the range `[0.0, 1.0)`.
+    #[inline]
+    fn next_uniform(&mut self) -> f32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+
+        // Use the top 24 bits, which is all the precision an `f32` can hold.
+        (x >> 8) as f32 * (1.0 / 16_777_216.0)
+    }
+}
+
+impl Default for TpdfDither {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// How the error introduced by reducing the bit depth of a signal is shaped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoiseShaping {
+    /// The error is left as white noise.
+    #[default]
+    None,
+    /// First-order error feedback, which moves the noise towards high
+    /// frequencies (+6 dB per octave).
+    FirstOrder,
+    /// Second-order error feedback, which moves even more of the noise
+    /// towards high frequencies (+12 dB per octave), where it is less audible.
+    SecondOrder,
+}
+
+/// Options for converting a resource to a different sample format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConversionOptions {
+    /// Whether to add TPDF dither when reducing the bit depth.
+    ///
+    /// By default this is set to `false`.
+    pub dither: bool,
+    /// How to shape the quantization error when reducing the bit depth.
+    ///
+    /// By default this is set to [`NoiseShaping::None`].
+    pub noise_shaping: NoiseShaping,
+    /// The seed of the dither generator. Each channel uses a different seed
+    /// derived from this, so that the dither is not correlated between
+    /// channels.
+    pub seed: u32,
+}
+
+/// Rounds values to whole steps, adding dither and noise shaping as
+/// configured. Each channel needs its own quantizer.
+pub(crate) struct Quantizer {
+    dither: Option<TpdfDither>,
+    noise_shaping: NoiseShaping,
+    /// The previous two quantization errors.
+    error: [f64; 2],
+}
+
+impl Quantizer {
+    pub(crate) fn new(options: &ConversionOptions, channel: usize) -> Self {
+        Self {
+            dither: options.dither.then(|| {
+                TpdfDither::new(
+                    options
+                        .seed
+                        .wrapping_add((channel as u32).wrapping_mul(0x9E37_79B9)),
+                )
+            }),
+            noise_shaping: options.noise_shaping,
+            error: [0.0; 2],
+        }
+    }
+
+    /// Round `v` (in units of the step size) to a whole number. The result is
+    /// not clipped.
+    #[inline]
+    pub(crate) fn quantize(&mut self, v: f64) -> f64 {
+        let shaped = match self.noise_shaping {
+            NoiseShaping::None => v,
+            NoiseShaping::FirstOrder => v - self.error[0],
+            NoiseShaping::SecondOrder => v - (2.0 * self.error[0] - self.error[1]),
+        };
+
+        let dither = self
+            .dither
+            .as_mut()
+            .map(|d| f64::from(d.next_lsb()))
+            .unwrap_or(0.0);
+
+        let q = (shaped + dither).round();
+
+        if self.noise_shaping != NoiseShaping::None {
+            // Limit the error so that clipping can't make the feedback unstable.
+            self.error[1] = self.error[0];
+            self.error[0] = (q - shaped).clamp(-2.0, 2.0);
+        }
+
+        q
+    }
+}
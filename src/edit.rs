@@ -0,0 +1,427 @@
+use std::sync::Arc;
+
+use super::fade::{Fade, FadeCurve};
+use super::resource::{DecodedAudio, DecodedAudioType};
+
+/// A non-destructive edit applied by [`EditedAudio`].
+///
+/// Edits are applied in order, and each one acts on the result of the edits
+/// before it. Frame positions are relative to that result, not to the source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditOp {
+    /// Keep only the frames in the range `[start, end)`.
+    Trim { start: usize, end: usize },
+    /// Multiply every sample by the given gain.
+    Gain(f32),
+    /// Fade in from the start.
+    FadeIn(Fade),
+    /// Fade out at the end.
+    FadeOut(Fade),
+    /// Play backwards.
+    Reverse,
+    /// Insert the given number of frames of silence at the start.
+    Offset(usize),
+}
+
+/// A gain envelope created by a fade, in the coordinates of the edited audio.
+///
+/// The start may be negative if the audio was trimmed after the fade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Envelope {
+    start: isize,
+    frames: usize,
+    curve: FadeCurve,
+    rising: bool,
+}
+
+impl Envelope {
+    fn end(&self) -> isize {
+        self.start + self.frames as isize
+    }
+
+    fn gain_at(&self, frame: usize) -> f32 {
+        let frame = frame as isize;
+        let fade = Fade::new(self.frames, self.curve);
+
+        if self.rising {
+            if frame < self.start {
+                0.0
+            } else {
+                fade.gain_at((frame - self.start) as usize)
+            }
+        } else if frame >= self.end() {
+            0.0
+        } else if frame < self.start {
+            1.0
+        } else {
+            // The silent end of a fade-out is its last frame.
+            fade.gain_at((self.end() - frame - 1) as usize)
+        }
+    }
+}
+
+/// The result of applying a list of edits, computed once so that rendering is
+/// cheap and does not allocate.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct RenderPlan {
+    /// The total length of the edited audio in frames.
+    frames: usize,
+    /// The number of frames of silence before the content.
+    pre: usize,
+    /// The number of frames of content.
+    len: usize,
+    /// The first frame of the content in the source.
+    src_start: usize,
+    /// Whether the content is played backwards.
+    reversed: bool,
+    gain: f32,
+    envelopes: Vec<Envelope>,
+}
+
+impl RenderPlan {
+    fn new(source_frames: usize) -> Self {
+        Self {
+            frames: source_frames,
+            pre: 0,
+            len: source_frames,
+            src_start: 0,
+            reversed: false,
+            gain: 1.0,
+            envelopes: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, op: &EditOp) {
+        match *op {
+            EditOp::Trim { start, end } => {
+                let end = end.min(self.frames);
+                let start = start.min(end);
+
+                // The part of the content that is kept.
+                let a = self.pre.max(start);
+                let b = (self.pre + self.len).min(end);
+
+                if a < b {
+                    if self.reversed {
+                        self.src_start += self.pre + self.len - b;
+                    } else {
+                        self.src_start += a - self.pre;
+                    }
+                    self.pre = a - start;
+                    self.len = b - a;
+                } else {
+                    self.pre = 0;
+                    self.len = 0;
+                }
+
+                self.frames = end - start;
+
+                for env in self.envelopes.iter_mut() {
+                    env.start -= start as isize;
+                }
+                // Drop the envelopes that no longer have any effect.
+                let frames = self.frames as isize;
+                self.envelopes.retain(|env| {
+                    if env.rising {
+                        env.end() > 0
+                    } else {
+                        env.start < frames
+                    }
+                });
+            }
+            EditOp::Gain(gain) => self.gain *= gain,
+            EditOp::FadeIn(fade) => {
+                if fade.frames > 0 {
+                    self.envelopes.push(Envelope {
+                        start: 0,
+                        frames: fade.frames,
+                        curve: fade.curve,
+                        rising: true,
+                    });
+                }
+            }
+            EditOp::FadeOut(fade) => {
+                if fade.frames > 0 {
+                    let frames = fade.frames.min(self.frames);
+                    self.envelopes.push(Envelope {
+                        start: (self.frames - frames) as isize,
+                        frames,
+                        curve: fade.curve,
+                        rising: false,
+                    });
+                }
+            }
+            EditOp::Reverse => {
+                self.pre = self.frames - (self.pre + self.len);
+                self.reversed = !self.reversed;
+
+                for env in self.envelopes.iter_mut() {
+                    env.start = self.frames as isize - env.end();
+                    env.rising = !env.rising;
+                }
+            }
+            EditOp::Offset(frames) => {
+                self.pre += frames;
+                self.frames += frames;
+
+                for env in self.envelopes.iter_mut() {
+                    env.start += frames as isize;
+                }
+            }
+        }
+    }
+
+    /// Returns the number of frames of leading silence and the number of
+    /// frames of content in the range `[frame, frame + frames)`.
+    fn split(&self, frame: usize, frames: usize) -> (usize, usize) {
+        let silence = self.pre.saturating_sub(frame).min(frames);
+        let content = (self.pre + self.len)
+            .min(frame + frames)
+            .saturating_sub(frame.max(self.pre));
+
+        (silence, content)
+    }
+
+    /// Returns the frame in the source at the given frame of content.
+    fn src_frame(&self, frame: usize) -> usize {
+        let offset = frame - self.pre;
+
+        if self.reversed {
+            self.src_start + self.len - 1 - offset
+        } else {
+            self.src_start + offset
+        }
+    }
+
+    fn gain_at(&self, frame: usize) -> f32 {
+        self.envelopes
+            .iter()
+            .fold(self.gain, |gain, env| gain * env.gain_at(frame))
+    }
+}
+
+/// A non-destructive view of a [`DecodedAudio`] resource with a list of edits
+/// applied to it.
+///
+/// The source data is shared and never modified. The edited result can be
+/// read on the realtime thread with [`EditedAudio::fill_channel`] and
+/// [`EditedAudio::fill_stereo`], or rendered into a new resource with
+/// [`EditedAudio::bake`].
+#[derive(Clone)]
+pub struct EditedAudio {
+    source: Arc<DecodedAudio>,
+    edits: Vec<EditOp>,
+    plan: RenderPlan,
+}
+
+impl EditedAudio {
+    /// Create a view of the given resource with no edits.
+    pub fn new(source: Arc<DecodedAudio>) -> Self {
+        Self::with_edits(source, Vec::new())
+    }
+
+    /// Create a view of the given resource with the given edits.
+    pub fn with_edits(source: Arc<DecodedAudio>, edits: Vec<EditOp>) -> Self {
+        let mut plan = RenderPlan::new(source.frames());
+        for op in edits.iter() {
+            plan.apply(op);
+        }
+
+        Self {
+            source,
+            edits,
+            plan,
+        }
+    }
+
+    /// Add an edit to the end of the edit list.
+    pub fn push(&mut self, op: EditOp) {
+        self.plan.apply(&op);
+        self.edits.push(op);
+    }
+
+    /// Remove the last edit from the edit list.
+    pub fn pop(&mut self) -> Option<EditOp> {
+        let op = self.edits.pop();
+
+        if op.is_some() {
+            self.plan = RenderPlan::new(self.source.frames());
+            for op in self.edits.iter() {
+                self.plan.apply(op);
+            }
+        }
+
+        op
+    }
+
+    /// Remove all edits.
+    pub fn clear(&mut self) {
+        self.edits.clear();
+        self.plan = RenderPlan::new(self.source.frames());
+    }
+
+    /// The list of edits, in the order they are applied.
+    pub fn edits(&self) -> &[EditOp] {
+        &self.edits
+    }
+
+    /// The unedited source resource.
+    pub fn source(&self) -> &Arc<DecodedAudio> {
+        &self.source
+    }
+
+    /// The number of channels in this resource.
+    pub fn channels(&self) -> usize {
+        self.source.channels()
+    }
+
+    /// The length of this resource in frames, after the edits are applied.
+    pub fn frames(&self) -> usize {
+        self.plan.frames
+    }
+
+    /// The sample rate of this resource.
+    pub fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    /// Fill the buffer with samples from the given `channel`, starting from the
+    /// given `frame` of the edited audio.
+    ///
+    /// This does not allocate, so it is safe to call on the realtime thread.
+    ///
+    /// If the length of the buffer exceeds the length of the edited audio, then
+    /// the remaining samples will be filled with zeros.
+    ///
+    /// This returns the number of frames that were copied into the buffer. (If
+    /// this number is less than the length of `buf`, then it means that the
+    /// remaining samples were filled with zeros.)
+    ///
+    /// The will return an error if the given channel does not exist.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill_channel(&self, channel: usize, frame: usize, buf: &mut [f32]) -> Result<usize, ()> {
+        if channel >= self.channels() {
+            return Err(());
+        }
+
+        let fill_frames = if frame >= self.plan.frames {
+            0
+        } else {
+            (self.plan.frames - frame).min(buf.len())
+        };
+
+        let (silence, content) = self.plan.split(frame, fill_frames);
+
+        buf[0..silence].fill(0.0);
+        buf[silence + content..].fill(0.0);
+
+        if content > 0 {
+            let content_frame = frame + silence;
+            let buf_part = &mut buf[silence..silence + content];
+            let src_frame = self.plan.src_frame(content_frame);
+
+            if self.plan.reversed {
+                self.source
+                    .fill_channel_reverse(channel, src_frame, buf_part)?;
+            } else {
+                self.source.fill_channel(channel, src_frame, buf_part)?;
+            }
+
+            if self.plan.gain != 1.0 || !self.plan.envelopes.is_empty() {
+                for (i, s) in buf_part.iter_mut().enumerate() {
+                    *s *= self.plan.gain_at(content_frame + i);
+                }
+            }
+        }
+
+        Ok(fill_frames)
+    }
+
+    /// Fill the stereo buffer with samples, starting from the given `frame` of
+    /// the edited audio.
+    ///
+    /// If this resource has only one channel, then both channels will be
+    /// filled with the same data.
+    ///
+    /// See [`EditedAudio::fill_channel`] for more details.
+    pub fn fill_stereo(&self, frame: usize, buf_l: &mut [f32], buf_r: &mut [f32]) -> usize {
+        let buf_len = buf_l.len().min(buf_r.len());
+        let buf_l = &mut buf_l[0..buf_len];
+        let buf_r = &mut buf_r[0..buf_len];
+
+        let fill_frames = self.fill_channel(0, frame, buf_l).unwrap();
+
+        if self.source.stored_channels() == 1 {
+            buf_r.copy_from_slice(buf_l);
+            fill_frames
+        } else {
+            self.fill_channel(1, frame, buf_r).unwrap()
+        }
+    }
+
+    /// Render the edited audio into a new resource.
+    ///
+    /// The samples are stored as `f32`, since gain and fades may have been
+    /// applied.
+    pub fn bake(&self) -> DecodedAudio {
+        let data = (0..self.channels())
+            .map(|channel| {
+                let mut buf = vec![0.0; self.frames()];
+                self.fill_channel(channel, 0, &mut buf).unwrap();
+                buf
+            })
+            .collect();
+
+        DecodedAudio::new_with_layout(
+            DecodedAudioType::F32(data),
+            self.sample_rate(),
+            self.source.layout().clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_list() {
+        let source = Arc::new(DecodedAudio::new(
+            DecodedAudioType::F32(vec![vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]]),
+            44100,
+        ));
+
+        let mut edited = EditedAudio::new(Arc::clone(&source));
+        edited.push(EditOp::Trim { start: 1, end: 5 });
+        edited.push(EditOp::Reverse);
+        edited.push(EditOp::Offset(2));
+        edited.push(EditOp::Gain(2.0));
+        assert_eq!(edited.frames(), 6);
+
+        let mut out_buf: [f32; 8] = [10.0; 8];
+        let fill_frames = edited.fill_channel(0, 0, &mut out_buf);
+        assert_eq!(fill_frames, Ok(6));
+        assert_eq!(out_buf, [0.0, 0.0, 10.0, 8.0, 6.0, 4.0, 0.0, 0.0]);
+
+        out_buf = [10.0; 8];
+        let fill_frames = edited.fill_channel(0, 3, &mut out_buf[0..2]);
+        assert_eq!(fill_frames, Ok(2));
+        assert_eq!(&out_buf[0..2], &[8.0, 6.0]);
+
+        // A fade-out applied before reversing becomes a fade-in.
+        let edited = EditedAudio::with_edits(
+            source,
+            vec![
+                EditOp::FadeOut(Fade::new(2, FadeCurve::Linear)),
+                EditOp::Reverse,
+                EditOp::Trim { start: 0, end: 3 },
+            ],
+        );
+        let baked = edited.bake();
+        assert_eq!(baked.frames(), 3);
+
+        let mut out_buf: [f32; 3] = [10.0; 3];
+        baked.fill_channel(0, 0, &mut out_buf).unwrap();
+        assert_eq!(out_buf, [0.0, 2.5, 4.0]);
+    }
+}
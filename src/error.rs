@@ -12,11 +12,25 @@ pub enum LoadError {
     CouldNotCreateDecoder(symphonia::core::errors::Error),
     ErrorWhileDecoding(symphonia::core::errors::Error),
     UnexpectedErrorWhileDecoding(Box<dyn Error>),
+    /// A channel in [`LoadOptions::channel_map`] is not a channel of the file.
+    ///
+    /// [`LoadOptions::channel_map`]: crate::LoadOptions::channel_map
+    ChannelOutOfRange {
+        channel: usize,
+        n_channels: usize,
+    },
     #[cfg(feature = "resampler")]
     InvalidResampler {
         needed_channels: usize,
         got_channels: usize,
     },
+    /// The settings of a [`ResampleQuality::Custom`] resampler are rejected,
+    /// either by rubato or because the resampler could not make progress
+    /// with them.
+    ///
+    /// [`ResampleQuality::Custom`]: crate::ResampleQuality::Custom
+    #[cfg(feature = "resampler")]
+    InvalidResamplerConfig(Box<dyn Error>),
     #[cfg(feature = "resampler")]
     ErrorWhileResampling(rubato::ResampleError),
 }
@@ -41,6 +55,14 @@ impl fmt::Display for LoadError {
             CouldNotCreateDecoder(e) => write!(f, "Failed to create decoder: {}", e),
             ErrorWhileDecoding(e) => write!(f, "Error while decoding: {}", e),
             UnexpectedErrorWhileDecoding(e) => write!(f, "Unexpected error while decoding: {}", e),
+            ChannelOutOfRange {
+                channel,
+                n_channels,
+            } => write!(
+                f,
+                "Channel {} is out of range: the file has {} channels",
+                channel, n_channels
+            ),
             #[cfg(feature = "resampler")]
             InvalidResampler {
                 got_channels,
@@ -53,6 +75,8 @@ impl fmt::Display for LoadError {
                 )
             }
             #[cfg(feature = "resampler")]
+            InvalidResamplerConfig(e) => write!(f, "Invalid resampler config: {}", e),
+            #[cfg(feature = "resampler")]
             ErrorWhileResampling(e) => write!(f, "Error while resampling: {}", e),
         }
     }
@@ -70,3 +94,70 @@ impl From<rubato::ResampleError> for LoadError {
         Self::ErrorWhileResampling(e)
     }
 }
+
+#[cfg(feature = "resampler")]
+impl From<rubato::ResamplerConstructionError> for LoadError {
+    fn from(e: rubato::ResamplerConstructionError) -> Self {
+        Self::InvalidResamplerConfig(Box::new(e))
+    }
+}
+
+/// An error that occurred while editing a decoded resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError {
+    FrameOutOfRange { frame: usize, frames: usize },
+    InvalidRange { start: usize, end: usize },
+    ChannelOutOfRange { channel: usize, channels: usize },
+    SampleRateMismatch { expected: u32, got: u32 },
+    ChannelCountMismatch { expected: usize, got: usize },
+    FrameCountMismatch { expected: usize, got: usize },
+    SampleFormatMismatch,
+}
+
+impl Error for EditError {}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use EditError::*;
+
+        match self {
+            FrameOutOfRange { frame, frames } => {
+                write!(
+                    f,
+                    "Frame {} is out of range: resource has {} frames",
+                    frame, frames
+                )
+            }
+            InvalidRange { start, end } => write!(f, "Invalid frame range: {}..{}", start, end),
+            ChannelOutOfRange { channel, channels } => {
+                write!(
+                    f,
+                    "Channel {} is out of range: resource has {} channels",
+                    channel, channels
+                )
+            }
+            SampleRateMismatch { expected, got } => {
+                write!(
+                    f,
+                    "Sample rate mismatch: expected {}, got {}",
+                    expected, got
+                )
+            }
+            ChannelCountMismatch { expected, got } => {
+                write!(
+                    f,
+                    "Channel count mismatch: expected {}, got {}",
+                    expected, got
+                )
+            }
+            FrameCountMismatch { expected, got } => {
+                write!(
+                    f,
+                    "Frame count mismatch: expected {}, got {}",
+                    expected, got
+                )
+            }
+            SampleFormatMismatch => write!(f, "Resources do not have the same sample format"),
+        }
+    }
+}
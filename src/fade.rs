@@ -0,0 +1,116 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// The shape of a fade.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FadeCurve {
+    /// The gain changes at a constant rate.
+    Linear,
+    /// The gain follows a quarter sine, so that crossfading two uncorrelated
+    /// signals keeps a constant loudness.
+    #[default]
+    EqualPower,
+    /// The gain follows a raised cosine, which starts and ends gently.
+    SCurve,
+}
+
+impl FadeCurve {
+    /// Returns the gain of a fade-in at the position `t` in the range
+    /// `[0.0, 1.0]`. (A fade-out is the same curve played backwards.)
+    #[inline]
+    pub fn gain(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::EqualPower => (t * FRAC_PI_2).sin(),
+            FadeCurve::SCurve => 0.5 - 0.5 * (t * PI).cos(),
+        }
+    }
+}
+
+/// A fade-in or fade-out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fade {
+    /// The length of the fade in frames. A length of `0` means no fade.
+    pub frames: usize,
+    pub curve: FadeCurve,
+}
+
+impl Fade {
+    pub fn new(frames: usize, curve: FadeCurve) -> Self {
+        Self { frames, curve }
+    }
+
+    /// Returns the gain of the frame that is `i` frames away from the silent
+    /// end of the fade.
+    #[inline]
+    pub(crate) fn gain_at(&self, i: usize) -> f32 {
+        if i >= self.frames {
+            1.0
+        } else {
+            self.curve.gain(i as f32 / self.frames as f32)
+        }
+    }
+}
+
+/// A region of a resource to play, with fades at either edge so that starting
+/// and stopping playback mid-resource does not click.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlaybackRegion {
+    /// The first frame of the region.
+    pub start: usize,
+    /// The frame after the last frame of the region (exclusive).
+    ///
+    /// This is clamped to the length of the resource.
+    pub end: usize,
+    /// The fade applied to the start of the region.
+    pub fade_in: Fade,
+    /// The fade applied to the end of the region.
+    pub fade_out: Fade,
+}
+
+impl PlaybackRegion {
+    /// A region with no fades.
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            fade_in: Fade::default(),
+            fade_out: Fade::default(),
+        }
+    }
+
+    /// The length of the region in frames.
+    pub fn frames(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Fill `buf` with the samples in `pcm`, applying the fades of a region.
+///
+/// `offset` is the position of the first sample of `pcm` within the region,
+/// and `region_frames` is the length of the region.
+pub(crate) fn fill_faded<T: Copy>(
+    pcm: &[T],
+    buf: &mut [f32],
+    offset: usize,
+    region_frames: usize,
+    fade_in: &Fade,
+    fade_out: &Fade,
+    to_f32: impl Fn(T) -> f32,
+) {
+    for (i, (out_s, &s)) in buf.iter_mut().zip(pcm).enumerate() {
+        let pos = offset + i;
+
+        let mut gain = 1.0;
+        if pos < fade_in.frames {
+            gain *= fade_in.gain_at(pos);
+        }
+        let remaining = region_frames - pos - 1;
+        if remaining < fade_out.frames {
+            gain *= fade_out.gain_at(remaining);
+        }
+
+        *out_s = to_f32(s) * gain;
+    }
+}
@@ -0,0 +1,126 @@
+use std::f64::consts::PI;
+
+/// The number of input frames on each side of the read position that are
+/// used by [`Interpolation::Sinc`].
+const SINC_HALF_TAPS: i64 = 8;
+
+/// The algorithm used to read samples at fractional positions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interpolation {
+    /// Linear interpolation between the two nearest frames.
+    ///
+    /// This is the cheapest option, but it attenuates high frequencies and
+    /// aliases when pitching up.
+    #[default]
+    Linear,
+    /// Cubic Hermite (Catmull-Rom) interpolation over the four nearest frames.
+    ///
+    /// A good trade-off between quality and performance.
+    CubicHermite,
+    /// Blackman-windowed sinc interpolation over the sixteen nearest frames.
+    ///
+    /// The highest quality option. When `step` is greater than `1.0`, the
+    /// cutoff of the filter is lowered to reduce aliasing.
+    Sinc,
+}
+
+/// Fill `buf` with samples read from `pcm` at `position`, `position + step`,
+/// `position + 2.0 * step`, and so on.
+///
+/// Frames outside of `pcm` are treated as silence. Once the read position
+/// leaves the range `[0, pcm.len())`, the rest of `buf` is filled with zeros.
+///
+/// This returns the number of frames that were read before the read position
+/// left the range.
+pub(crate) fn fill_interpolated<T: Copy>(
+    pcm: &[T],
+    position: f64,
+    step: f64,
+    interpolation: Interpolation,
+    buf: &mut [f32],
+    to_f32: impl Fn(T) -> f32,
+) -> usize {
+    let frames = pcm.len() as f64;
+
+    let sample = |i: i64| -> f64 {
+        if i < 0 || i as usize >= pcm.len() {
+            0.0
+        } else {
+            to_f32(pcm[i as usize]) as f64
+        }
+    };
+
+    // Lower the cutoff of the sinc filter when skipping over frames.
+    let cutoff = 1.0 / step.abs().max(1.0);
+
+    let mut fill_frames = 0;
+    for (i, out_s) in buf.iter_mut().enumerate() {
+        // Computing each position from the start avoids accumulating rounding errors.
+        let pos = position + step * i as f64;
+
+        // This also catches `NaN`.
+        if !(pos >= 0.0 && pos < frames) {
+            break;
+        }
+
+        let i0 = pos.floor() as i64;
+        let fract = pos - i0 as f64;
+
+        *out_s = match interpolation {
+            Interpolation::Linear => {
+                let s0 = sample(i0);
+                let s1 = sample(i0 + 1);
+
+                s0 + (s1 - s0) * fract
+            }
+            Interpolation::CubicHermite => {
+                let xm1 = sample(i0 - 1);
+                let x0 = sample(i0);
+                let x1 = sample(i0 + 1);
+                let x2 = sample(i0 + 2);
+
+                let c1 = 0.5 * (x1 - xm1);
+                let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+                let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+
+                ((c3 * fract + c2) * fract + c1) * fract + x0
+            }
+            Interpolation::Sinc => {
+                let mut sum = 0.0;
+                for k in (1 - SINC_HALF_TAPS)..=SINC_HALF_TAPS {
+                    let s = sample(i0 + k);
+                    if s != 0.0 {
+                        sum += s * windowed_sinc(k as f64 - fract, cutoff);
+                    }
+                }
+
+                sum
+            }
+        } as f32;
+
+        fill_frames += 1;
+    }
+
+    buf[fill_frames..].fill(0.0);
+
+    fill_frames
+}
+
+fn windowed_sinc(x: f64, cutoff: f64) -> f64 {
+    let half_width = SINC_HALF_TAPS as f64;
+    if x.abs() >= half_width {
+        return 0.0;
+    }
+
+    let sinc = if x == 0.0 {
+        cutoff
+    } else {
+        (PI * cutoff * x).sin() / (PI * x)
+    };
+
+    // Blackman window centered on `x = 0`.
+    let w = PI * (x / half_width + 1.0);
+    let window = 0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+
+    sinc * window
+}
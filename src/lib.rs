@@ -1,76 +1,318 @@
 use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 
-#[cfg(feature = "resampler")]
-use std::collections::HashMap;
-
 use symphonia::core::codecs::CodecRegistry;
 use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::io::{MediaSource, MediaSourceStream, SeekBuffered};
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::{Hint, Probe, ProbeResult};
 
 // Re-export symphonia
 pub use symphonia;
 
+pub mod channel;
 pub mod convert;
 pub mod error;
 
 #[cfg(feature = "resampler")]
 pub mod resample;
 #[cfg(feature = "resampler")]
-pub use resample::ResampleQuality;
+pub use resample::{ResampleQuality, ResamplerConfig};
 #[cfg(feature = "resampler")]
-use resample::{ResamplerKey, ResamplerOwned, ResamplerParams, ResamplerRefMut};
+use resample::{ResamplerCache, ResamplerParams, ResamplerRefMut};
 
 mod decode;
+mod dither;
+mod edit;
+mod fade;
+mod interpolate;
+mod looping;
+mod mix;
+mod output;
 mod resource;
+mod sample;
+
+use decode::DecodeParams;
 
+pub use channel::{ChannelLayout, ChannelOrder, ChannelPosition, MixMatrix, MonoDownmix};
+pub use dither::{ConversionOptions, NoiseShaping, TpdfDither};
+pub use edit::{EditOp, EditedAudio};
+pub use fade::{Fade, FadeCurve, PlaybackRegion};
+pub use interpolate::Interpolation;
+pub use looping::{LoopMode, LoopRegion, Playhead};
+pub use mix::{GainRamp, PanLaw};
+pub use output::OutputSample;
 pub use resource::*;
+pub use sample::{ChannelMapper, ChannelVisitor, Sample, I24, U24};
 
 use error::LoadError;
 
 /// The default maximum size of an audio file in bytes.
 pub static DEFAULT_MAX_BYTES: usize = 1_000_000_000;
 
+/// Options for loading an audio file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadOptions {
+    /// If this is `Some`, then the file will be resampled to that sample rate. (No
+    /// resampling will occur if the audio file's sample rate is already the target
+    /// sample rate). If this is `None`, then the file will not be resampled and it
+    /// will stay its original sample rate.
+    ///   * Note that resampling will convert the sample format to `f32` unless
+    ///     `resample_to_native_format` is `true`. If saving memory is a concern, then
+    ///     set this to `None` and resample in realtime.
+    ///
+    /// By default this is set to `None`.
+    #[cfg(feature = "resampler")]
+    pub target_sample_rate: Option<u32>,
+    /// The quality of the resampler to use if the `target_sample_rate` doesn't match
+    /// the source sample rate.
+    ///   - Has no effect if `target_sample_rate` is `None`.
+    ///
+    /// By default this is set to [`ResampleQuality::Normal`].
+    #[cfg(feature = "resampler")]
+    pub resample_quality: ResampleQuality,
+    /// If this is `true`, then a resampled file is stored in the same sample format
+    /// it would be stored in if it was not resampled (including `storage_format`),
+    /// instead of `f32`. The output of the resampler is requantized one chunk at a
+    /// time with `storage_conversion`, so the whole file is never held as `f32`.
+    ///   * Has no effect if `target_sample_rate` is `None`.
+    ///
+    /// By default this is set to `false`.
+    #[cfg(feature = "resampler")]
+    pub resample_to_native_format: bool,
+    /// The maximum size in bytes that the resulting resource can be in RAM. If the
+    /// resulting resource is larger than this, then an error will be returned instead.
+    /// This is useful to avoid locking up or crashing the system if the use tries to
+    /// load a really large audio file.
+    ///   * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
+    ///
+    /// By default this is set to `None`.
+    pub max_bytes: Option<usize>,
+    /// If this is `Some`, then the channels of the file will be mixed into this layout
+    /// while decoding using the standard matrix from [`MixMatrix::new`] (i.e. an
+    /// ITU-R BS.775 downmix of 5.1 to stereo).
+    ///   * Note that mixing will convert the sample format to `f32` unless the file
+    ///     already has the target layout.
+    ///   * Has no effect if `mono_downmix` is `Some`.
+    ///
+    /// By default this is set to `None`.
+    pub target_layout: Option<ChannelLayout>,
+    /// If this is `true`, then the channels will be reordered into the canonical
+    /// (WAV/SMPTE) order, so that the same mix loaded from different formats ends up
+    /// with its channels in the same positions. The order the source used is still
+    /// available from [`DecodedAudio::source_order`].
+    ///   * Has no effect if `target_layout` is `Some`, since the channels are then
+    ///     stored in the order of the target layout.
+    ///
+    /// By default this is set to `false`.
+    pub canonical_channel_order: bool,
+    /// If this is `true`, then files with floating point samples will be stored as
+    /// half-precision [`DecodedAudioType::F16`] samples, which take up half the memory
+    /// of `f32` samples at the cost of precision (about 11 bits).
+    ///   * Has no effect if the file is resampled (unless `resample_to_native_format`
+    ///     is `true`) or mixed into `target_layout`, since the result is always `f32`
+    ///     in that case.
+    ///
+    /// By default this is set to `false`.
+    #[cfg(feature = "f16")]
+    pub store_float_as_f16: bool,
+    /// The sample format to store the decoded samples in. Samples are converted one
+    /// packet at a time while decoding, so the whole file is never held in its
+    /// native format.
+    ///   * Has no effect if the file is resampled (unless `resample_to_native_format`
+    ///     is `true`) or mixed into `target_layout`, since the result is always `f32`
+    ///     in that case.
+    ///   * If this is not [`StorageFormat::Native`], then `store_float_as_f16` has no
+    ///     effect.
+    ///
+    /// By default this is set to [`StorageFormat::Native`].
+    pub storage_format: StorageFormat,
+    /// How to dither and noise shape the samples when `storage_format` reduces the
+    /// bit depth. Has no effect otherwise.
+    ///
+    /// By default this adds TPDF dither without noise shaping.
+    pub storage_conversion: ConversionOptions,
+    /// If this is `true`, then the decoded samples are analyzed to detect the number
+    /// of bits they actually use (e.g. 16-bit material padded with zeros in a 24-bit
+    /// or 32-bit float file), and are stored in the smallest format that holds them
+    /// without losing any bits. The detected bit depth is available from
+    /// [`DecodedAudio::detected_bit_depth`]. See [`DecodedAudioType::effective_bits`].
+    ///   * Integer samples drop their unused low bits. Every bit of the samples is
+    ///     kept, but each integer format reads back with its own full scale, so the
+    ///     level changes slightly: by about 0.003% when packed into 16 bits, and by
+    ///     about 0.8% when packed into 8 bits.
+    ///   * Floating point samples are stored as 8, 16 or 24-bit signed integers only
+    ///     if every sample is exactly the value of such an integer (as converted by
+    ///     [`convert`]), so they read back unchanged. Otherwise they are kept as they
+    ///     are, and only the detected bit depth is reported.
+    ///   * This is an extra pass over the samples after decoding, and it happens after
+    ///     any conversion from `storage_format`.
+    ///   * Has no effect if the file is resampled (unless `resample_to_native_format`
+    ///     is `true`) or mixed into `target_layout`, since the result is always `f32`
+    ///     in that case.
+    ///
+    /// By default this is set to `false`.
+    pub detect_bit_depth: bool,
+    /// If this is `Some`, then files where every channel is identical to the first
+    /// (such as "dual mono" stereo files) store only one channel, while still
+    /// reporting the same number of channels and layout. Channels are identical if
+    /// no pair of samples differs by more than this tolerance (in the range
+    /// `[-1.0, 1.0]`), so `Some(0.0)` only collapses channels that are exactly the
+    /// same, and a small tolerance also catches files from lossy codecs. See
+    /// [`DecodedAudio::stored_channels`].
+    ///   * Has no effect if the file is resampled (unless `resample_to_native_format`
+    ///     is `true`) or mixed into `target_layout`.
+    ///
+    /// By default this is set to `None`.
+    pub collapse_identical_channels: Option<f32>,
+    /// If this is `Some`, then only the channels of the file at these indices are
+    /// decoded, in this order (e.g. `vec![2, 3]` keeps only the third and fourth
+    /// channels). The other channels are dropped while decoding, so they never take
+    /// up memory. A channel may be listed more than once.
+    ///   * `target_layout` is mixed from the selected channels.
+    ///   * `canonical_channel_order` has no effect if this is `Some`.
+    ///   * [`LoadError::ChannelOutOfRange`] is returned if an index is not a channel
+    ///     of the file.
+    ///
+    /// By default this is set to `None`.
+    pub channel_map: Option<Vec<usize>>,
+    /// If this is `Some`, then the channels are summed into one mono channel while
+    /// decoding, one packet at a time, with each channel scaled by the gain of the
+    /// given [`MonoDownmix`]. Files that are already mono are left unchanged.
+    ///   * Unlike `target_layout`, this keeps the native sample format (the mix is
+    ///     requantized with `storage_conversion`), unless the file is resampled without
+    ///     `resample_to_native_format`.
+    ///   * `target_layout` has no effect if this is `Some`.
+    ///
+    /// By default this is set to `None`.
+    pub mono_downmix: Option<MonoDownmix>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "resampler")]
+            target_sample_rate: None,
+            #[cfg(feature = "resampler")]
+            resample_quality: ResampleQuality::default(),
+            #[cfg(feature = "resampler")]
+            resample_to_native_format: false,
+            max_bytes: None,
+            target_layout: None,
+            canonical_channel_order: false,
+            #[cfg(feature = "f16")]
+            store_float_as_f16: false,
+            storage_format: StorageFormat::Native,
+            storage_conversion: ConversionOptions {
+                dither: true,
+                ..Default::default()
+            },
+            detect_bit_depth: false,
+            collapse_identical_channels: None,
+            channel_map: None,
+            mono_downmix: None,
+        }
+    }
+}
+
 /// Used to load audio files into RAM. This stores samples in
 /// their native sample format when possible to save memory.
 pub struct SymphoniumLoader {
     // Re-use resamplers to improve performance.
     #[cfg(feature = "resampler")]
-    resamplers: HashMap<ResamplerKey, ResamplerOwned>,
+    resamplers: ResamplerCache,
 
     codec_registry: &'static CodecRegistry,
     probe: &'static Probe,
 }
 
+impl Default for SymphoniumLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SymphoniumLoader {
     /// Construct a new audio file loader.
     pub fn new() -> Self {
         Self {
             #[cfg(feature = "resampler")]
-            resamplers: HashMap::new(),
+            resamplers: ResamplerCache::default(),
             codec_registry: symphonia::default::get_codecs(),
             probe: symphonia::default::get_probe(),
         }
     }
 
+    /// Limit the memory used by the cached resamplers to about `max_bytes`.
+    ///
+    /// Resamplers are cached so they can be reused for every file with the
+    /// same sample rates, number of channels and resampler settings. When the
+    /// cache is larger than this, the least recently used resamplers are
+    /// dropped. (The resampler that is currently needed is always kept, even
+    /// if it is larger than this on its own.)
+    ///
+    /// If this is `None` (the default), then the cache is never limited.
+    #[cfg(feature = "resampler")]
+    pub fn set_resampler_cache_limit(&mut self, max_bytes: Option<usize>) {
+        self.resamplers.set_max_bytes(max_bytes);
+    }
+
+    /// Drop all cached resamplers, freeing their memory.
+    #[cfg(feature = "resampler")]
+    pub fn clear_resampler_cache(&mut self) {
+        self.resamplers.clear();
+    }
+
+    /// Create the resampler for the given parameters and quality ahead of time,
+    /// so that loading a file that needs it does not have to.
+    ///
+    /// Returns [`LoadError::InvalidResamplerConfig`] if the settings of a
+    /// [`ResampleQuality::Custom`] resampler are not valid.
+    #[cfg(feature = "resampler")]
+    pub fn prewarm_resampler(
+        &mut self,
+        params: ResamplerParams,
+        quality: ResampleQuality,
+    ) -> Result<(), LoadError> {
+        self.resamplers.get(
+            quality,
+            params.source_sample_rate,
+            params.target_sample_rate,
+            params.num_channels,
+        )?;
+
+        Ok(())
+    }
+
+    /// The number of resamplers in the cache.
+    #[cfg(feature = "resampler")]
+    pub fn cached_resamplers(&self) -> usize {
+        self.resamplers.len()
+    }
+
+    /// An estimate of the memory used by the cached resamplers, in bytes.
+    #[cfg(feature = "resampler")]
+    pub fn resampler_cache_bytes(&self) -> usize {
+        self.resamplers.bytes()
+    }
+
     /// Load an audio file from the given path into RAM.
     ///
     /// * `path` - The path to the audio file stored on disk.
     /// * `target_sample_rate` - If this is `Some`, then the file will be resampled to that
-    /// sample rate. (No resampling will occur if the audio file's sample rate is already
-    /// the target sample rate). If this is `None`, then the file will not be resampled
-    /// and it will stay its original sample rate.
+    ///   sample rate. (No resampling will occur if the audio file's sample rate is already
+    ///   the target sample rate). If this is `None`, then the file will not be resampled
+    ///   and it will stay its original sample rate.
     ///     * Note that resampling will always convert the sample format to `f32`. If
-    /// saving memory is a concern, then set this to `None` and resample in realtime.
+    ///       saving memory is a concern, then set this to `None` and resample in realtime.
     /// * `resample_quality` - The quality of the resampler to use if the `target_sample_rate`
-    /// doesn't match the source sample rate.
+    ///   doesn't match the source sample rate.
     ///     - Has no effect if `target_sample_rate` is `None`.
     /// * `max_bytes` - The maximum size in bytes that the resulting `DecodedAudio`
-    /// resource can  be in RAM. If the resulting resource is larger than this, then an error
-    /// will be returned instead. This is useful to avoid locking up or crashing the system
-    /// if the use tries to load a really large audio file.
+    ///   resource can  be in RAM. If the resulting resource is larger than this, then an error
+    ///   will be returned instead. This is useful to avoid locking up or crashing the system
+    ///   if the use tries to load a really large audio file.
     ///     * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
     pub fn load<P: AsRef<Path>>(
         &mut self,
@@ -79,45 +321,52 @@ impl SymphoniumLoader {
         #[cfg(feature = "resampler")] resample_quality: ResampleQuality,
         max_bytes: Option<usize>,
     ) -> Result<DecodedAudio, LoadError> {
-        let source = load_file(path, self.probe)?;
-
-        decode(
-            source,
-            self.codec_registry,
-            max_bytes,
-            #[cfg(feature = "resampler")]
-            target_sample_rate,
-            #[cfg(feature = "resampler")]
-            |params| {
-                self::resample::get_resampler(
-                    &mut self.resamplers,
-                    resample_quality,
-                    params.source_sample_rate,
-                    params.target_sample_rate,
-                    params.num_channels,
-                )
+        self.load_with_options(
+            path,
+            &LoadOptions {
+                #[cfg(feature = "resampler")]
+                target_sample_rate,
+                #[cfg(feature = "resampler")]
+                resample_quality,
+                max_bytes,
+                ..Default::default()
             },
         )
     }
 
+    /// Load an audio file from the given path into RAM with the given options.
+    ///
+    /// * `path` - The path to the audio file stored on disk.
+    /// * `options` - Options for how the audio is decoded, resampled, and stored. See
+    ///   [`LoadOptions`].
+    pub fn load_with_options<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: &LoadOptions,
+    ) -> Result<DecodedAudio, LoadError> {
+        let source = load_file(path, self.probe)?;
+
+        self.decode_source(source, options)
+    }
+
     /// Load an audio source into RAM.
     ///
     /// * `source` - The audio source which implements the [`MediaSource`] trait.
     /// * `hint` - An optional hint to help the format registry guess what format reader is
-    /// appropriate.
+    ///   appropriate.
     /// * `target_sample_rate` - If this is `Some`, then the file will be resampled to that
-    /// sample rate. (No resampling will occur if the audio file's sample rate is already
-    /// the target sample rate). If this is `None`, then the file will not be resampled
-    /// and it will stay its original sample rate.
+    ///   sample rate. (No resampling will occur if the audio file's sample rate is already
+    ///   the target sample rate). If this is `None`, then the file will not be resampled
+    ///   and it will stay its original sample rate.
     ///     * Note that resampling will always convert the sample format to `f32`. If
-    /// saving memory is a concern, then set this to `None` and resample in realtime.
+    ///       saving memory is a concern, then set this to `None` and resample in realtime.
     /// * `resample_quality` - The quality of the resampler to use if the `target_sample_rate`
-    /// doesn't match the source sample rate.
+    ///   doesn't match the source sample rate.
     ///     - Has no effect if `target_sample_rate` is `None`.
     /// * `max_bytes` - The maximum size in bytes that the resulting `DecodedAudio`
-    /// resource can  be in RAM. If the resulting resource is larger than this, then an error
-    /// will be returned instead. This is useful to avoid locking up or crashing the system
-    /// if the use tries to load a really large audio file.
+    ///   resource can  be in RAM. If the resulting resource is larger than this, then an error
+    ///   will be returned instead. This is useful to avoid locking up or crashing the system
+    ///   if the use tries to load a really large audio file.
     ///     * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
     pub fn load_from_source(
         &mut self,
@@ -127,36 +376,47 @@ impl SymphoniumLoader {
         #[cfg(feature = "resampler")] resample_quality: ResampleQuality,
         max_bytes: Option<usize>,
     ) -> Result<DecodedAudio, LoadError> {
-        let source = load_audio_source(source, hint, self.probe)?;
-
-        decode(
+        self.load_from_source_with_options(
             source,
-            self.codec_registry,
-            max_bytes,
-            #[cfg(feature = "resampler")]
-            target_sample_rate,
-            #[cfg(feature = "resampler")]
-            |params| {
-                self::resample::get_resampler(
-                    &mut self.resamplers,
-                    resample_quality,
-                    params.source_sample_rate,
-                    params.target_sample_rate,
-                    params.num_channels,
-                )
+            hint,
+            &LoadOptions {
+                #[cfg(feature = "resampler")]
+                target_sample_rate,
+                #[cfg(feature = "resampler")]
+                resample_quality,
+                max_bytes,
+                ..Default::default()
             },
         )
     }
 
+    /// Load an audio source into RAM with the given options.
+    ///
+    /// * `source` - The audio source which implements the [`MediaSource`] trait.
+    /// * `hint` - An optional hint to help the format registry guess what format reader is
+    ///   appropriate.
+    /// * `options` - Options for how the audio is decoded, resampled, and stored. See
+    ///   [`LoadOptions`].
+    pub fn load_from_source_with_options(
+        &mut self,
+        source: Box<dyn MediaSource>,
+        hint: Option<Hint>,
+        options: &LoadOptions,
+    ) -> Result<DecodedAudio, LoadError> {
+        let source = load_audio_source(source, hint, self.probe)?;
+
+        self.decode_source(source, options)
+    }
+
     /// Load an audio file from the given path into RAM using a custom resampler.
     ///
     /// * `path` - The path to the audio file stored on disk.
     /// * `target_sample_rate` - The target sample rate. (No resampling will occur if the audio
-    /// file's sample rate is already the target sample rate).
+    ///   file's sample rate is already the target sample rate).
     /// * `max_bytes` - The maximum size in bytes that the resulting `DecodedAudio`
-    /// resource can  be in RAM. If the resulting resource is larger than this, then an error
-    /// will be returned instead. This is useful to avoid locking up or crashing the system
-    /// if the use tries to load a really large audio file.
+    ///   resource can  be in RAM. If the resulting resource is larger than this, then an error
+    ///   will be returned instead. This is useful to avoid locking up or crashing the system
+    ///   if the use tries to load a really large audio file.
     ///     * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
     /// * `get_resampler` - Get the custom sampler with the desired parameters.
     #[cfg(feature = "resampler")]
@@ -169,12 +429,15 @@ impl SymphoniumLoader {
     ) -> Result<DecodedAudio, LoadError> {
         let source = load_file(path, self.probe)?;
 
-        decode(
+        resample_or_decode(
             source,
             self.codec_registry,
-            max_bytes,
-            Some(target_sample_rate),
-            get_resampler,
+            &LoadOptions {
+                target_sample_rate: Some(target_sample_rate),
+                max_bytes,
+                ..Default::default()
+            },
+            |params| Ok(get_resampler(params)),
         )
     }
 
@@ -182,13 +445,13 @@ impl SymphoniumLoader {
     ///
     /// * `source` - The audio source which implements the [`MediaSource`] trait.
     /// * `hint` - An optional hint to help the format registry guess what format reader is
-    /// appropriate.
+    ///   appropriate.
     /// * `target_sample_rate` - The target sample rate. (No resampling will occur if the audio
-    /// file's sample rate is already the target sample rate).
+    ///   file's sample rate is already the target sample rate).
     /// * `max_bytes` - The maximum size in bytes that the resulting `DecodedAudio`
-    /// resource can  be in RAM. If the resulting resource is larger than this, then an error
-    /// will be returned instead. This is useful to avoid locking up or crashing the system
-    /// if the use tries to load a really large audio file.
+    ///   resource can  be in RAM. If the resulting resource is larger than this, then an error
+    ///   will be returned instead. This is useful to avoid locking up or crashing the system
+    ///   if the use tries to load a really large audio file.
     ///     * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
     /// * `get_resampler` - Get the custom sampler with the desired parameters.
     #[cfg(feature = "resampler")]
@@ -202,12 +465,15 @@ impl SymphoniumLoader {
     ) -> Result<DecodedAudio, LoadError> {
         let source = load_audio_source(source, hint, self.probe)?;
 
-        decode(
+        resample_or_decode(
             source,
             self.codec_registry,
-            max_bytes,
-            Some(target_sample_rate),
-            get_resampler,
+            &LoadOptions {
+                target_sample_rate: Some(target_sample_rate),
+                max_bytes,
+                ..Default::default()
+            },
+            |params| Ok(get_resampler(params)),
         )
     }
 
@@ -215,18 +481,18 @@ impl SymphoniumLoader {
     ///
     /// * `path` - The path to the audio file stored on disk.
     /// * `target_sample_rate` - If this is `Some`, then the file will be resampled to that
-    /// sample rate. (No resampling will occur if the audio file's sample rate is already
-    /// the target sample rate). If this is `None`, then the file will not be resampled
-    /// and it will stay its original sample rate.
+    ///   sample rate. (No resampling will occur if the audio file's sample rate is already
+    ///   the target sample rate). If this is `None`, then the file will not be resampled
+    ///   and it will stay its original sample rate.
     ///     * Note that resampling will always convert the sample format to `f32`. If
-    /// saving memory is a concern, then set this to `None` and resample in realtime.
+    ///       saving memory is a concern, then set this to `None` and resample in realtime.
     /// * `resample_quality` - The quality of the resampler to use if the `target_sample_rate`
-    /// doesn't match the source sample rate.
+    ///   doesn't match the source sample rate.
     ///     - Has no effect if `target_sample_rate` is `None`.
     /// * `max_bytes` - The maximum size in bytes that the resulting `DecodedAudio`
-    /// resource can  be in RAM. If the resulting resource is larger than this, then an error
-    /// will be returned instead. This is useful to avoid locking up or crashing the system
-    /// if the use tries to load a really large audio file.
+    ///   resource can  be in RAM. If the resulting resource is larger than this, then an error
+    ///   will be returned instead. This is useful to avoid locking up or crashing the system
+    ///   if the use tries to load a really large audio file.
     ///     * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
     pub fn load_f32<P: AsRef<Path>>(
         &mut self,
@@ -235,45 +501,53 @@ impl SymphoniumLoader {
         #[cfg(feature = "resampler")] resample_quality: ResampleQuality,
         max_bytes: Option<usize>,
     ) -> Result<DecodedAudioF32, LoadError> {
-        let source = load_file(path, self.probe)?;
-
-        decode_f32(
-            source,
-            self.codec_registry,
-            max_bytes,
-            #[cfg(feature = "resampler")]
-            target_sample_rate,
-            #[cfg(feature = "resampler")]
-            |params| {
-                self::resample::get_resampler(
-                    &mut self.resamplers,
-                    resample_quality,
-                    params.source_sample_rate,
-                    params.target_sample_rate,
-                    params.num_channels,
-                )
+        self.load_f32_with_options(
+            path,
+            &LoadOptions {
+                #[cfg(feature = "resampler")]
+                target_sample_rate,
+                #[cfg(feature = "resampler")]
+                resample_quality,
+                max_bytes,
+                ..Default::default()
             },
         )
     }
 
+    /// Load an audio file from the given path into RAM with the given options and convert
+    /// to an f32 sample format.
+    ///
+    /// * `path` - The path to the audio file stored on disk.
+    /// * `options` - Options for how the audio is decoded, resampled, and stored. See
+    ///   [`LoadOptions`].
+    pub fn load_f32_with_options<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: &LoadOptions,
+    ) -> Result<DecodedAudioF32, LoadError> {
+        let source = load_file(path, self.probe)?;
+
+        self.decode_source_f32(source, options)
+    }
+
     /// Load an audio source into RAM and convert to an f32 sample format.
     ///
     /// * `source` - The audio source which implements the [`MediaSource`] trait.
     /// * `hint` - An optional hint to help the format registry guess what format reader is
-    /// appropriate.
+    ///   appropriate.
     /// * `target_sample_rate` - If this is `Some`, then the file will be resampled to that
-    /// sample rate. (No resampling will occur if the audio file's sample rate is already
-    /// the target sample rate). If this is `None`, then the file will not be resampled
-    /// and it will stay its original sample rate.
+    ///   sample rate. (No resampling will occur if the audio file's sample rate is already
+    ///   the target sample rate). If this is `None`, then the file will not be resampled
+    ///   and it will stay its original sample rate.
     ///     * Note that resampling will always convert the sample format to `f32`. If
-    /// saving memory is a concern, then set this to `None` and resample in realtime.
+    ///       saving memory is a concern, then set this to `None` and resample in realtime.
     /// * `resample_quality` - The quality of the resampler to use if the `target_sample_rate`
-    /// doesn't match the source sample rate.
+    ///   doesn't match the source sample rate.
     ///     - Has no effect if `target_sample_rate` is `None`.
     /// * `max_bytes` - The maximum size in bytes that the resulting `DecodedAudio`
-    /// resource can  be in RAM. If the resulting resource is larger than this, then an error
-    /// will be returned instead. This is useful to avoid locking up or crashing the system
-    /// if the use tries to load a really large audio file.
+    ///   resource can  be in RAM. If the resulting resource is larger than this, then an error
+    ///   will be returned instead. This is useful to avoid locking up or crashing the system
+    ///   if the use tries to load a really large audio file.
     ///     * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
     pub fn load_f32_from_source(
         &mut self,
@@ -283,37 +557,49 @@ impl SymphoniumLoader {
         #[cfg(feature = "resampler")] resample_quality: ResampleQuality,
         max_bytes: Option<usize>,
     ) -> Result<DecodedAudioF32, LoadError> {
-        let source = load_audio_source(source, hint, self.probe)?;
-
-        decode_f32(
+        self.load_f32_from_source_with_options(
             source,
-            self.codec_registry,
-            max_bytes,
-            #[cfg(feature = "resampler")]
-            target_sample_rate,
-            #[cfg(feature = "resampler")]
-            |params| {
-                self::resample::get_resampler(
-                    &mut self.resamplers,
-                    resample_quality,
-                    params.source_sample_rate,
-                    params.target_sample_rate,
-                    params.num_channels,
-                )
+            hint,
+            &LoadOptions {
+                #[cfg(feature = "resampler")]
+                target_sample_rate,
+                #[cfg(feature = "resampler")]
+                resample_quality,
+                max_bytes,
+                ..Default::default()
             },
         )
     }
 
+    /// Load an audio source into RAM with the given options and convert to an f32 sample
+    /// format.
+    ///
+    /// * `source` - The audio source which implements the [`MediaSource`] trait.
+    /// * `hint` - An optional hint to help the format registry guess what format reader is
+    ///   appropriate.
+    /// * `options` - Options for how the audio is decoded, resampled, and stored. See
+    ///   [`LoadOptions`].
+    pub fn load_f32_from_source_with_options(
+        &mut self,
+        source: Box<dyn MediaSource>,
+        hint: Option<Hint>,
+        options: &LoadOptions,
+    ) -> Result<DecodedAudioF32, LoadError> {
+        let source = load_audio_source(source, hint, self.probe)?;
+
+        self.decode_source_f32(source, options)
+    }
+
     /// Load an audio source into RAM using a custom resampler and convert to an f32 sample
     /// format.
     ///
     /// * `path` - The path to the audio file stored on disk.
     /// * `target_sample_rate` - The target sample rate. (No resampling will occur if the audio
-    /// file's sample rate is already the target sample rate).
+    ///   file's sample rate is already the target sample rate).
     /// * `max_bytes` - The maximum size in bytes that the resulting `DecodedAudio`
-    /// resource can  be in RAM. If the resulting resource is larger than this, then an error
-    /// will be returned instead. This is useful to avoid locking up or crashing the system
-    /// if the use tries to load a really large audio file.
+    ///   resource can  be in RAM. If the resulting resource is larger than this, then an error
+    ///   will be returned instead. This is useful to avoid locking up or crashing the system
+    ///   if the use tries to load a really large audio file.
     ///     * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
     /// * `get_resampler` - Get the custom sampler with the desired parameters.
     #[cfg(feature = "resampler")]
@@ -326,12 +612,15 @@ impl SymphoniumLoader {
     ) -> Result<DecodedAudioF32, LoadError> {
         let source = load_file(path, self.probe)?;
 
-        decode_f32(
+        resample_or_decode_f32(
             source,
             self.codec_registry,
-            max_bytes,
-            Some(target_sample_rate),
-            get_resampler,
+            &LoadOptions {
+                target_sample_rate: Some(target_sample_rate),
+                max_bytes,
+                ..Default::default()
+            },
+            |params| Ok(get_resampler(params)),
         )
     }
 
@@ -340,13 +629,13 @@ impl SymphoniumLoader {
     ///
     /// * `source` - The audio source which implements the [`MediaSource`] trait.
     /// * `hint` - An optional hint to help the format registry guess what format reader is
-    /// appropriate.
+    ///   appropriate.
     /// * `target_sample_rate` - The target sample rate. (No resampling will occur if the audio
-    /// file's sample rate is already the target sample rate).
+    ///   file's sample rate is already the target sample rate).
     /// * `max_bytes` - The maximum size in bytes that the resulting `DecodedAudio`
-    /// resource can  be in RAM. If the resulting resource is larger than this, then an error
-    /// will be returned instead. This is useful to avoid locking up or crashing the system
-    /// if the use tries to load a really large audio file.
+    ///   resource can  be in RAM. If the resulting resource is larger than this, then an error
+    ///   will be returned instead. This is useful to avoid locking up or crashing the system
+    ///   if the use tries to load a really large audio file.
     ///     * If this is `None`, then default of `1_000_000_000` (1GB) will be used.
     /// * `get_resampler` - Get the custom sampler with the desired parameters.
     #[cfg(feature = "resampler")]
@@ -360,20 +649,190 @@ impl SymphoniumLoader {
     ) -> Result<DecodedAudioF32, LoadError> {
         let source = load_audio_source(source, hint, self.probe)?;
 
-        decode_f32(
+        resample_or_decode_f32(
             source,
             self.codec_registry,
-            max_bytes,
-            Some(target_sample_rate),
-            get_resampler,
+            &LoadOptions {
+                target_sample_rate: Some(target_sample_rate),
+                max_bytes,
+                ..Default::default()
+            },
+            |params| Ok(get_resampler(params)),
         )
     }
+
+    #[cfg(feature = "resampler")]
+    fn decode_source(
+        &mut self,
+        source: LoadedAudioSource,
+        options: &LoadOptions,
+    ) -> Result<DecodedAudio, LoadError> {
+        resample_or_decode(source, self.codec_registry, options, |params| {
+            self.resamplers.get(
+                options.resample_quality,
+                params.source_sample_rate,
+                params.target_sample_rate,
+                params.num_channels,
+            )
+        })
+    }
+
+    #[cfg(not(feature = "resampler"))]
+    fn decode_source(
+        &mut self,
+        source: LoadedAudioSource,
+        options: &LoadOptions,
+    ) -> Result<DecodedAudio, LoadError> {
+        decode(source, self.codec_registry, options)
+    }
+
+    #[cfg(feature = "resampler")]
+    fn decode_source_f32(
+        &mut self,
+        source: LoadedAudioSource,
+        options: &LoadOptions,
+    ) -> Result<DecodedAudioF32, LoadError> {
+        resample_or_decode_f32(source, self.codec_registry, options, |params| {
+            self.resamplers.get(
+                options.resample_quality,
+                params.source_sample_rate,
+                params.target_sample_rate,
+                params.num_channels,
+            )
+        })
+    }
+
+    #[cfg(not(feature = "resampler"))]
+    fn decode_source_f32(
+        &mut self,
+        source: LoadedAudioSource,
+        options: &LoadOptions,
+    ) -> Result<DecodedAudioF32, LoadError> {
+        decode_f32(source, self.codec_registry, options)
+    }
 }
 
 struct LoadedAudioSource {
     probed: ProbeResult,
     sample_rate: u32,
     n_channels: usize,
+    layout: ChannelLayout,
+    order: ChannelOrder,
+}
+
+impl LoadedAudioSource {
+    fn finish(&self, pcm: &mut DecodedAudio, options: &LoadOptions) {
+        pcm.set_source_layout(self.layout.clone(), self.order);
+
+        if let Some(tolerance) = options.collapse_identical_channels {
+            pcm.collapse_identical_channels(f64::from(tolerance));
+        }
+
+        if options.detect_bit_depth {
+            pcm.pack_to_detected_bit_depth();
+        }
+
+        if options.canonical_channel_order
+            && options.target_layout.is_none()
+            && options.channel_map.is_none()
+        {
+            pcm.to_canonical_order();
+        }
+    }
+
+    fn finish_f32(&self, pcm: &mut DecodedAudioF32, options: &LoadOptions) {
+        pcm.set_source_layout(self.layout.clone(), self.order);
+
+        if options.canonical_channel_order
+            && options.target_layout.is_none()
+            && options.channel_map.is_none()
+        {
+            pcm.to_canonical_order();
+        }
+    }
+
+    /// The parameters to decode this source with.
+    fn decode_params<'a>(
+        &self,
+        options: &'a LoadOptions,
+        mix: Option<&'a MixMatrix>,
+        layout: ChannelLayout,
+    ) -> DecodeParams<'a> {
+        DecodeParams {
+            n_channels: self.n_channels(options),
+            channel_map: options.channel_map.as_deref(),
+            sample_rate: self.sample_rate,
+            mix,
+            layout,
+            max_bytes: options.max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+            #[cfg(feature = "f16")]
+            float_as_f16: options.store_float_as_f16
+                && options.storage_format == StorageFormat::Native,
+            storage: options.storage_format,
+            conversion: options.storage_conversion,
+        }
+    }
+
+    /// The number of channels to decode.
+    fn n_channels(&self, options: &LoadOptions) -> usize {
+        options
+            .channel_map
+            .as_ref()
+            .map_or(self.n_channels, |channel_map| channel_map.len())
+    }
+
+    /// Returns the layout of the channels selected by `options.channel_map`.
+    fn selected_layout(&self, options: &LoadOptions) -> Result<ChannelLayout, LoadError> {
+        let Some(channel_map) = &options.channel_map else {
+            return Ok(self.layout.clone());
+        };
+
+        if channel_map.is_empty() {
+            return Err(LoadError::NoChannelsFound);
+        }
+
+        let positions =
+            channel_map
+                .iter()
+                .map(|&channel| {
+                    self.layout.positions().get(channel).copied().ok_or(
+                        LoadError::ChannelOutOfRange {
+                            channel,
+                            n_channels: self.n_channels,
+                        },
+                    )
+                })
+                .collect::<Result<_, _>>()?;
+
+        Ok(ChannelLayout::new(positions))
+    }
+
+    /// Returns the matrix to mix the channels with (if mixing is needed) and the
+    /// layout of the resulting resource.
+    fn mix(&self, options: &LoadOptions) -> Result<(Option<MixMatrix>, ChannelLayout), LoadError> {
+        let layout = self.selected_layout(options)?;
+
+        if let Some(downmix) = options.mono_downmix {
+            if layout.channels() == 1 {
+                return Ok((None, layout));
+            }
+
+            let matrix = MixMatrix::mono_downmix(layout.channels(), downmix);
+            return Ok((Some(matrix), ChannelLayout::mono()));
+        }
+
+        let Some(target_layout) = &options.target_layout else {
+            return Ok((None, layout));
+        };
+
+        let matrix = MixMatrix::new(&layout, target_layout);
+
+        if matrix.is_identity() {
+            Ok((None, target_layout.clone()))
+        } else {
+            Ok((Some(matrix), target_layout.clone()))
+        }
+    }
 }
 
 fn load_file<P: AsRef<Path>>(
@@ -404,7 +863,11 @@ fn load_audio_source(
     probe: &'static Probe,
 ) -> Result<LoadedAudioSource, LoadError> {
     // Create the media source stream.
-    let mss = MediaSourceStream::new(source, Default::default());
+    let mut mss = MediaSourceStream::new(source, Default::default());
+
+    // Symphonia reports the channel mask in WAV order, but AIFF files store the
+    // channels in their own order, so detect the container before probing.
+    let order = ChannelOrder::from_header(&peek_header(&mut mss));
 
     // Use the default options for format reader, metadata reader, and decoder.
     let format_opts: FormatOptions = Default::default();
@@ -415,7 +878,7 @@ fn load_audio_source(
     // Probe the media source stream for metadata and get the format reader.
     let probed = probe
         .format(&hint, mss, &format_opts, &metadata_opts)
-        .map_err(|e| LoadError::UnkownFormat(e))?;
+        .map_err(LoadError::UnkownFormat)?;
 
     // Get the default track in the audio stream.
     let track = probed
@@ -428,38 +891,69 @@ fn load_audio_source(
         44100
     });
 
-    let n_channels = track
+    let channels = track
         .codec_params
         .channels
-        .ok_or_else(|| LoadError::NoChannelsFound)?
-        .count();
+        .ok_or_else(|| LoadError::NoChannelsFound)?;
+    let n_channels = channels.count();
 
     if n_channels == 0 {
         return Err(LoadError::NoChannelsFound);
     }
 
+    let layout = order.layout(channels);
+
     Ok(LoadedAudioSource {
         probed,
         sample_rate,
         n_channels,
+        layout,
+        order,
     })
 }
 
-fn decode<'a>(
-    mut source: LoadedAudioSource,
+/// Read the first bytes of the stream without consuming them.
+fn peek_header(mss: &mut MediaSourceStream) -> Vec<u8> {
+    let mut header = vec![0; 12];
+    let mut len = 0;
+
+    while len < header.len() {
+        match mss.read(&mut header[len..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => len += n,
+        }
+    }
+
+    mss.seek_buffered_rev(len);
+    header.truncate(len);
+    header
+}
+
+/// Decode the source, resampling it if it isn't at the target sample rate.
+#[cfg(feature = "resampler")]
+fn resample_or_decode<'a>(
+    source: LoadedAudioSource,
     codec_registry: &'static CodecRegistry,
-    max_bytes: Option<usize>,
-    #[cfg(feature = "resampler")] target_sample_rate: Option<u32>,
-    #[cfg(feature = "resampler")] get_resampler: impl FnOnce(ResamplerParams) -> ResamplerRefMut<'a>,
+    options: &LoadOptions,
+    get_resampler: impl FnOnce(ResamplerParams) -> Result<ResamplerRefMut<'a>, LoadError>,
 ) -> Result<DecodedAudio, LoadError> {
-    #[cfg(feature = "resampler")]
-    if let Some(target_sample_rate) = target_sample_rate {
+    if let Some(target_sample_rate) = options.target_sample_rate {
         if source.sample_rate != target_sample_rate {
             // Resampling is needed.
+            if options.resample_to_native_format {
+                return resample_native(
+                    source,
+                    codec_registry,
+                    options,
+                    target_sample_rate,
+                    get_resampler,
+                );
+            }
+
             return resample(
                 source,
                 codec_registry,
-                max_bytes,
+                options,
                 target_sample_rate,
                 get_resampler,
             )
@@ -467,45 +961,64 @@ fn decode<'a>(
         }
     }
 
-    let pcm = decode::decode_native_bitdepth(
-        &mut source.probed,
-        source.n_channels,
-        codec_registry,
-        source.sample_rate,
-        max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
-    )?;
-
-    Ok(pcm)
+    decode(source, codec_registry, options)
 }
 
-fn decode_f32<'a>(
-    mut source: LoadedAudioSource,
+/// Decode the source to `f32`, resampling it if it isn't at the target sample rate.
+#[cfg(feature = "resampler")]
+fn resample_or_decode_f32<'a>(
+    source: LoadedAudioSource,
     codec_registry: &'static CodecRegistry,
-    max_bytes: Option<usize>,
-    #[cfg(feature = "resampler")] target_sample_rate: Option<u32>,
-    #[cfg(feature = "resampler")] get_resampler: impl FnOnce(ResamplerParams) -> ResamplerRefMut<'a>,
+    options: &LoadOptions,
+    get_resampler: impl FnOnce(ResamplerParams) -> Result<ResamplerRefMut<'a>, LoadError>,
 ) -> Result<DecodedAudioF32, LoadError> {
-    #[cfg(feature = "resampler")]
-    if let Some(target_sample_rate) = target_sample_rate {
+    if let Some(target_sample_rate) = options.target_sample_rate {
         if source.sample_rate != target_sample_rate {
             // Resampling is needed.
             return resample(
                 source,
                 codec_registry,
-                max_bytes,
+                options,
                 target_sample_rate,
                 get_resampler,
             );
         }
     }
 
-    let pcm = decode::decode_f32(
-        &mut source.probed,
-        source.n_channels,
-        codec_registry,
-        source.sample_rate,
-        max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
-    )?;
+    decode_f32(source, codec_registry, options)
+}
+
+fn decode(
+    mut source: LoadedAudioSource,
+    codec_registry: &'static CodecRegistry,
+    options: &LoadOptions,
+) -> Result<DecodedAudio, LoadError> {
+    let (mix, layout) = source.mix(options)?;
+
+    if mix.is_some() && options.mono_downmix.is_none() {
+        // Mixing to a target layout is done in `f32`.
+        return decode_f32(source, codec_registry, options).map(|pcm| pcm.into());
+    }
+
+    let params = source.decode_params(options, mix.as_ref(), layout);
+    let mut pcm = decode::decode_native_bitdepth(&mut source.probed, codec_registry, params)?;
+
+    source.finish(&mut pcm, options);
+
+    Ok(pcm)
+}
+
+fn decode_f32(
+    mut source: LoadedAudioSource,
+    codec_registry: &'static CodecRegistry,
+    options: &LoadOptions,
+) -> Result<DecodedAudioF32, LoadError> {
+    let (mix, layout) = source.mix(options)?;
+
+    let params = source.decode_params(options, mix.as_ref(), layout);
+    let mut pcm = decode::decode_f32(&mut source.probed, codec_registry, params)?;
+
+    source.finish_f32(&mut pcm, options);
 
     Ok(pcm)
 }
@@ -514,32 +1027,451 @@ fn decode_f32<'a>(
 fn resample<'a>(
     mut source: LoadedAudioSource,
     codec_registry: &'static CodecRegistry,
-    max_bytes: Option<usize>,
+    options: &LoadOptions,
     target_sample_rate: u32,
-    get_resampler: impl FnOnce(ResamplerParams) -> ResamplerRefMut<'a>,
+    get_resampler: impl FnOnce(ResamplerParams) -> Result<ResamplerRefMut<'a>, LoadError>,
 ) -> Result<DecodedAudioF32, LoadError> {
+    let (mix, layout) = source.mix(options)?;
+
+    // Mixing happens before resampling, so the resampler only needs to process the
+    // channels in the resulting layout.
+    let out_channels = layout.channels();
+
     let resampler = get_resampler(ResamplerParams {
-        num_channels: source.n_channels,
+        num_channels: out_channels,
         source_sample_rate: source.sample_rate,
         target_sample_rate,
-    });
+    })?;
 
-    if resampler.num_channels() != source.n_channels {
-        return Err(LoadError::InvalidResampler {
-            needed_channels: source.n_channels,
-            got_channels: resampler.num_channels(),
-        });
-    }
+    let params = source.decode_params(options, mix.as_ref(), layout);
+    let mut pcm = decode::decode_resampled(
+        &mut source.probed,
+        codec_registry,
+        params,
+        target_sample_rate,
+        resampler,
+    )?;
+
+    source.finish_f32(&mut pcm, options);
+
+    Ok(pcm)
+}
+
+#[cfg(feature = "resampler")]
+fn resample_native<'a>(
+    mut source: LoadedAudioSource,
+    codec_registry: &'static CodecRegistry,
+    options: &LoadOptions,
+    target_sample_rate: u32,
+    get_resampler: impl FnOnce(ResamplerParams) -> Result<ResamplerRefMut<'a>, LoadError>,
+) -> Result<DecodedAudio, LoadError> {
+    let (mix, layout) = source.mix(options)?;
 
-    let pcm = decode::decode_resampled(
+    // Mixing happens before resampling, so the resampler only needs to process the
+    // channels in the resulting layout.
+    let out_channels = layout.channels();
+
+    let resampler = get_resampler(ResamplerParams {
+        num_channels: out_channels,
+        source_sample_rate: source.sample_rate,
+        target_sample_rate,
+    })?;
+
+    let params = source.decode_params(options, mix.as_ref(), layout);
+    let mut pcm = decode::decode_resampled_native(
         &mut source.probed,
         codec_registry,
-        source.sample_rate,
+        params,
         target_sample_rate,
-        source.n_channels,
         resampler,
-        max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
     )?;
 
-    return Ok(pcm);
+    source.finish(&mut pcm, options);
+
+    Ok(pcm)
+}
+
+#[cfg(all(test, feature = "wav", feature = "pcm"))]
+mod tests {
+    use super::*;
+
+    const SURROUND_WAV: &str = "test_files/surround_5_1_48000_16bit.wav";
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    const SURROUND_OGG: &str = "test_files/surround_5_1_48000.ogg";
+
+    /// The RMS level of each channel relative to the loudest one.
+    ///
+    /// The channels of the surround test files are the same signal at a
+    /// different level in each position (FL is the loudest and RR the
+    /// quietest), so this identifies which position each channel came from.
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    fn relative_levels(data: &[Vec<f32>]) -> Vec<f32> {
+        let rms: Vec<f32> = data
+            .iter()
+            .map(|ch| (ch.iter().map(|s| s * s).sum::<f32>() / ch.len() as f32).sqrt())
+            .collect();
+        let max = rms.iter().copied().fold(0.0, f32::max);
+
+        rms.iter().map(|level| level / max).collect()
+    }
+
+    /// The channels of a resource, converted to `f32`.
+    fn planes(pcm: &DecodedAudio) -> Vec<Vec<f32>> {
+        (0..pcm.channels())
+            .map(|ch| {
+                let mut buf = vec![0.0; pcm.frames()];
+                pcm.fill_channel(ch, 0, &mut buf).unwrap();
+                buf
+            })
+            .collect()
+    }
+
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    fn assert_levels_eq(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 0.001, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    fn vorbis_channel_order() {
+        let mut loader = SymphoniumLoader::new();
+
+        let options = LoadOptions::default();
+        let wav = loader
+            .load_f32_with_options(SURROUND_WAV, &options)
+            .unwrap();
+        let ogg = loader
+            .load_f32_with_options(SURROUND_OGG, &options)
+            .unwrap();
+
+        // Symphonia decodes Vorbis in the WAV order, so both are labelled the
+        // same and each channel holds the same position.
+        assert_eq!(ogg.layout(), wav.layout());
+        assert_eq!(ogg.source_order(), ChannelOrder::Wav);
+        assert_levels_eq(&relative_levels(&ogg.data), &relative_levels(&wav.data));
+
+        // So the downmix mixes the same channels into each side.
+        let options = LoadOptions {
+            target_layout: Some(ChannelLayout::stereo()),
+            ..Default::default()
+        };
+        let wav = loader
+            .load_f32_with_options(SURROUND_WAV, &options)
+            .unwrap();
+        let ogg = loader
+            .load_f32_with_options(SURROUND_OGG, &options)
+            .unwrap();
+
+        assert_eq!(ogg.channels(), 2);
+        assert_levels_eq(&relative_levels(&ogg.data), &relative_levels(&wav.data));
+    }
+
+    #[test]
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    fn canonical_channel_order() {
+        let mut loader = SymphoniumLoader::new();
+
+        let options = LoadOptions {
+            canonical_channel_order: true,
+            ..Default::default()
+        };
+
+        // Both files are already decoded in the canonical order, so the
+        // reorder must leave every channel where it is.
+        let wav = loader.load_with_options(SURROUND_WAV, &options).unwrap();
+        let ogg = loader.load_with_options(SURROUND_OGG, &options).unwrap();
+
+        assert!(ogg.layout().is_canonical());
+        assert_eq!(ogg.layout(), wav.layout());
+        let wav_levels = relative_levels(&planes(&wav));
+        assert_levels_eq(&relative_levels(&planes(&ogg)), &wav_levels);
+
+        let wav_f32 = loader
+            .load_f32_with_options(SURROUND_WAV, &options)
+            .unwrap();
+        let ogg_f32 = loader
+            .load_f32_with_options(SURROUND_OGG, &options)
+            .unwrap();
+
+        assert_eq!(*ogg_f32.layout(), *wav.layout());
+        assert_eq!(*wav_f32.layout(), *wav.layout());
+        assert_levels_eq(&relative_levels(&wav_f32.data), &wav_levels);
+        assert_levels_eq(&relative_levels(&ogg_f32.data), &wav_levels);
+    }
+
+    #[test]
+    #[cfg(all(feature = "aiff", feature = "pcm"))]
+    fn aiff_channel_order() {
+        use ChannelPosition::*;
+
+        // Sample `n` of channel `ch` in these files is `(ch + 1) * 1000 + n`,
+        // where `ch` is the index of the channel in the file.
+        const AIFF: &str = "test_files/surround_6ch_48000_16bit.aiff";
+        const AIFF_SOWT: &str = "test_files/surround_6ch_48000_16bit_sowt.aifc";
+
+        let mut loader = SymphoniumLoader::new();
+
+        for path in [AIFF, AIFF_SOWT] {
+            let pcm = loader
+                .load_with_options(path, &LoadOptions::default())
+                .unwrap();
+
+            // Little-endian AIFF-C is still in the AIFF order.
+            assert_eq!(pcm.source_order(), ChannelOrder::Aiff);
+            assert_eq!(
+                pcm.layout().positions(),
+                [
+                    FrontLeft,
+                    FrontLeftOfCenter,
+                    FrontCenter,
+                    FrontRight,
+                    FrontRightOfCenter,
+                    RearCenter
+                ]
+            );
+
+            let options = LoadOptions {
+                canonical_channel_order: true,
+                ..Default::default()
+            };
+            let pcm = loader.load_with_options(path, &options).unwrap();
+            let pcm_f32 = loader.load_f32_with_options(path, &options).unwrap();
+
+            let canonical = [
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                FrontLeftOfCenter,
+                FrontRightOfCenter,
+                RearCenter,
+            ];
+            assert_eq!(pcm.layout().positions(), canonical);
+            assert_eq!(pcm_f32.layout().positions(), canonical);
+            assert_eq!(pcm.source_order(), ChannelOrder::Aiff);
+
+            let DecodedAudioType::S16(data) = pcm.storage() else {
+                panic!("expected i16 samples");
+            };
+            for (ch, file_ch) in [0, 3, 2, 1, 4, 5].into_iter().enumerate() {
+                let expected: Vec<i16> = (0..64).map(|n| (file_ch + 1) * 1000 + n).collect();
+                assert_eq!(data[ch], expected);
+
+                // Symphonia converts to `f32` with a full scale of 32768.
+                let expected: Vec<f32> = expected.iter().map(|&s| f32::from(s) / 32768.0).collect();
+                assert_eq!(pcm_f32.data[ch], expected);
+            }
+        }
+    }
+
+    /// A 16-bit mono WAV file with no samples.
+    #[cfg(feature = "resampler")]
+    fn empty_wav() -> Box<dyn MediaSource> {
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&36u32.to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, 1 channel, 44100 Hz, 88200 bytes per second, 2 bytes per frame,
+        // 16 bits per sample.
+        for (v, len) in [(1, 2), (1, 2), (44100, 4), (88200, 4), (2, 2), (16, 2)] {
+            wav.extend_from_slice(&u32::to_le_bytes(v)[..len]);
+        }
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&0u32.to_le_bytes());
+
+        Box::new(std::io::Cursor::new(wav))
+    }
+
+    #[test]
+    #[cfg(feature = "resampler")]
+    fn resample_empty_file() {
+        let mut loader = SymphoniumLoader::new();
+        let mut options = LoadOptions {
+            target_sample_rate: Some(48000),
+            ..Default::default()
+        };
+
+        let pcm = loader
+            .load_from_source_with_options(empty_wav(), None, &options)
+            .unwrap();
+        assert_eq!(pcm.frames(), 0);
+        assert_eq!(pcm.format(), SampleFormat::F32);
+
+        // Without a packet the samples are stored in the format the file
+        // declares, the same as if they were decoded.
+        options.resample_to_native_format = true;
+        let pcm = loader
+            .load_from_source_with_options(empty_wav(), None, &options)
+            .unwrap();
+        assert_eq!(pcm.frames(), 0);
+        assert_eq!(pcm.channels(), 1);
+        assert_eq!(pcm.sample_rate(), 48000);
+        assert_eq!(pcm.format(), SampleFormat::S16);
+
+        options.storage_format = StorageFormat::Exact(SampleFormat::U8);
+        let pcm = loader
+            .load_from_source_with_options(empty_wav(), None, &options)
+            .unwrap();
+        assert_eq!(pcm.format(), SampleFormat::U8);
+    }
+
+    #[test]
+    fn channel_map() {
+        let mut loader = SymphoniumLoader::new();
+
+        let all = loader
+            .load_with_options(SURROUND_WAV, &LoadOptions::default())
+            .unwrap();
+        let options = LoadOptions {
+            channel_map: Some(vec![2, 0, 2]),
+            ..Default::default()
+        };
+        let pcm = loader.load_with_options(SURROUND_WAV, &options).unwrap();
+
+        // The selected channels are decoded in the given order, without
+        // changing their format or samples.
+        assert_eq!(pcm.channels(), 3);
+        assert_eq!(pcm.format(), SampleFormat::S16);
+        assert_eq!(
+            pcm.layout().positions(),
+            [
+                ChannelPosition::FrontCenter,
+                ChannelPosition::FrontLeft,
+                ChannelPosition::FrontCenter
+            ]
+        );
+        let (DecodedAudioType::S16(all), DecodedAudioType::S16(selected)) =
+            (all.storage(), pcm.storage())
+        else {
+            panic!("expected i16 samples");
+        };
+        assert_eq!(selected[0], all[2]);
+        assert_eq!(selected[1], all[0]);
+        assert_eq!(selected[2], all[2]);
+
+        let all = loader
+            .load_f32_with_options(SURROUND_WAV, &LoadOptions::default())
+            .unwrap();
+        let pcm = loader
+            .load_f32_with_options(SURROUND_WAV, &options)
+            .unwrap();
+        assert_eq!(pcm.data[0], all.data[2]);
+        assert_eq!(pcm.data[1], all.data[0]);
+        assert_eq!(pcm.data[2], all.data[2]);
+
+        let options = LoadOptions {
+            channel_map: Some(vec![6]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            loader.load_with_options(SURROUND_WAV, &options),
+            Err(LoadError::ChannelOutOfRange {
+                channel: 6,
+                n_channels: 6
+            })
+        ));
+    }
+
+    #[test]
+    fn mono_downmix() {
+        let mut loader = SymphoniumLoader::new();
+
+        let all = loader
+            .load_f32_with_options(SURROUND_WAV, &LoadOptions::default())
+            .unwrap();
+        let options = LoadOptions {
+            mono_downmix: Some(MonoDownmix::Minus6dB),
+            ..Default::default()
+        };
+
+        // Every channel is summed with the gain of the downmix.
+        let mono = loader
+            .load_f32_with_options(SURROUND_WAV, &options)
+            .unwrap();
+        assert_eq!(mono.channels(), 1);
+        assert_eq!(*mono.layout(), ChannelLayout::mono());
+        for (frame, &s) in mono.data[0].iter().enumerate() {
+            let sum: f32 = all.data.iter().map(|ch| ch[frame]).sum();
+            assert!((s - sum * 0.5).abs() < 1e-6);
+        }
+
+        // The native format is kept, with the mix requantized to it.
+        let pcm = loader.load_with_options(SURROUND_WAV, &options).unwrap();
+        assert_eq!(pcm.channels(), 1);
+        assert_eq!(pcm.format(), SampleFormat::S16);
+        for (&a, b) in planes(&pcm)[0].iter().zip(&mono.data[0]) {
+            assert!((a - b).abs() <= 2.0 / 32767.0);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "resampler")]
+    fn resample_to_native_format() {
+        let mut loader = SymphoniumLoader::new();
+
+        let mut options = LoadOptions {
+            target_sample_rate: Some(44100),
+            ..Default::default()
+        };
+        let resampled = loader
+            .load_f32_with_options(SURROUND_WAV, &options)
+            .unwrap();
+
+        // The resampled samples are requantized to the format of the file.
+        options.resample_to_native_format = true;
+        let pcm = loader.load_with_options(SURROUND_WAV, &options).unwrap();
+        assert_eq!(pcm.channels(), 6);
+        assert_eq!(pcm.sample_rate(), 44100);
+        assert_eq!(pcm.format(), SampleFormat::S16);
+        assert_eq!(pcm.frames(), resampled.frames());
+        for (native, resampled) in planes(&pcm).iter().zip(&resampled.data) {
+            for (a, b) in native.iter().zip(resampled) {
+                assert!((a - b).abs() <= 2.0 / 32767.0);
+            }
+        }
+
+        // Mixing happens before resampling, and keeps the format as well.
+        options.mono_downmix = Some(MonoDownmix::Average);
+        let pcm = loader.load_with_options(SURROUND_WAV, &options).unwrap();
+        assert_eq!(pcm.channels(), 1);
+        assert_eq!(pcm.format(), SampleFormat::S16);
+    }
+
+    #[test]
+    #[cfg(feature = "resampler")]
+    fn invalid_resampler_config() {
+        let mut loader = SymphoniumLoader::new();
+
+        let mut config = ResampleQuality::Low.config();
+        config.chunk_size = 0;
+        let options = LoadOptions {
+            target_sample_rate: Some(44100),
+            resample_quality: ResampleQuality::Custom(config),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            loader.load_with_options(SURROUND_WAV, &options),
+            Err(LoadError::InvalidResamplerConfig(_))
+        ));
+        assert!(matches!(
+            loader.load_f32_with_options(SURROUND_WAV, &options),
+            Err(LoadError::InvalidResamplerConfig(_))
+        ));
+
+        let params = ResamplerParams {
+            num_channels: 6,
+            source_sample_rate: 48000,
+            target_sample_rate: 44100,
+        };
+        assert!(matches!(
+            loader.prewarm_resampler(params, options.resample_quality),
+            Err(LoadError::InvalidResamplerConfig(_))
+        ));
+        assert_eq!(loader.cached_resamplers(), 0);
+    }
 }
@@ -0,0 +1,160 @@
+/// How the playhead behaves when it reaches the end of a [`LoopRegion`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoopMode {
+    /// Jump back to the start of the loop.
+    #[default]
+    Forward,
+    /// Reverse direction at either end of the loop.
+    ///
+    /// The crossfade of the loop region is ignored in this mode, since the
+    /// turnaround points are already continuous.
+    PingPong,
+    /// Play until the end of the loop once, and then output silence.
+    OneShot,
+}
+
+/// A region of a resource to loop over.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LoopRegion {
+    /// The first frame of the loop.
+    pub start: usize,
+    /// The frame after the last frame of the loop (exclusive).
+    ///
+    /// This is clamped to the length of the resource.
+    pub end: usize,
+    /// What happens when the playhead reaches `end`.
+    pub mode: LoopMode,
+    /// The number of frames before `end` that are crossfaded with the frames
+    /// before `start`, which hides clicks at the loop seam.
+    ///
+    /// This is clamped to the length of the loop and to `start`, since there
+    /// must be frames before the start of the loop to fade into.
+    pub crossfade: usize,
+}
+
+impl LoopRegion {
+    pub fn new(start: usize, end: usize, mode: LoopMode) -> Self {
+        Self {
+            start,
+            end,
+            mode,
+            crossfade: 0,
+        }
+    }
+}
+
+/// The read position of a looping fill.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Playhead {
+    /// The frame that will be read next.
+    pub frame: usize,
+    /// Whether the playhead is moving backwards (only used by
+    /// [`LoopMode::PingPong`]).
+    pub reverse: bool,
+}
+
+impl Playhead {
+    pub fn new(frame: usize) -> Self {
+        Self {
+            frame,
+            reverse: false,
+        }
+    }
+}
+
+/// Fill `buf` with samples read from `pcm` starting at the given playhead,
+/// looping over the given region.
+///
+/// The region must already be validated so that `start < end <= pcm.len()`.
+///
+/// This returns the playhead after the last sample in `buf`.
+pub(crate) fn fill_looped<T: Copy>(
+    pcm: &[T],
+    mut playhead: Playhead,
+    region: &LoopRegion,
+    buf: &mut [f32],
+    to_f32: impl Fn(T) -> f32,
+) -> Playhead {
+    let LoopRegion {
+        start,
+        end,
+        mode,
+        crossfade,
+    } = *region;
+
+    match mode {
+        LoopMode::Forward => {
+            playhead.reverse = false;
+
+            let crossfade = crossfade.min(start).min(end - start);
+            let fade_start = end - crossfade;
+
+            for out_s in buf.iter_mut() {
+                if playhead.frame >= end {
+                    playhead.frame = start;
+                }
+
+                let frame = playhead.frame;
+                *out_s = if frame >= fade_start {
+                    // Fade into the frames leading up to the start of the loop, so
+                    // that the jump back to `start` is continuous.
+                    let i = frame - fade_start;
+                    let t = (i as f32 + 0.5) / crossfade as f32;
+
+                    to_f32(pcm[frame]) * (1.0 - t) + to_f32(pcm[start - crossfade + i]) * t
+                } else {
+                    to_f32(pcm[frame])
+                };
+
+                playhead.frame += 1;
+                if playhead.frame >= end {
+                    playhead.frame = start;
+                }
+            }
+        }
+        LoopMode::PingPong => {
+            for out_s in buf.iter_mut() {
+                if playhead.frame >= end {
+                    playhead.frame = end - 1;
+                    playhead.reverse = true;
+                }
+
+                *out_s = to_f32(pcm[playhead.frame]);
+
+                // Turn around without repeating the frame at either end.
+                if playhead.reverse {
+                    if playhead.frame <= start {
+                        playhead.frame = (start + 1).min(end - 1);
+                        playhead.reverse = false;
+                    } else {
+                        playhead.frame -= 1;
+                    }
+                } else if playhead.frame + 1 >= end {
+                    playhead.frame = end.saturating_sub(2).max(start);
+                    playhead.reverse = true;
+                } else {
+                    playhead.frame += 1;
+                }
+            }
+        }
+        LoopMode::OneShot => {
+            playhead.reverse = false;
+
+            let fill_frames = end.saturating_sub(playhead.frame).min(buf.len());
+
+            if fill_frames > 0 {
+                let pcm_part = &pcm[playhead.frame..playhead.frame + fill_frames];
+
+                for (out_s, &s) in buf[0..fill_frames].iter_mut().zip(pcm_part) {
+                    *out_s = to_f32(s);
+                }
+
+                playhead.frame += fill_frames;
+            }
+
+            buf[fill_frames..].fill(0.0);
+        }
+    }
+
+    playhead
+}
@@ -0,0 +1,104 @@
+use std::f32::consts::FRAC_PI_4;
+
+/// A gain that changes linearly across a block of samples.
+///
+/// Ramping the gain from the value used in the previous block avoids the
+/// "zipper" noise caused by jumping straight to a new gain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GainRamp {
+    /// The gain applied to the first sample in the block.
+    pub start: f32,
+    /// The gain that would be applied to the sample after the last sample
+    /// in the block (so that the next block can start where this one ended).
+    pub end: f32,
+}
+
+impl GainRamp {
+    pub fn new(start: f32, end: f32) -> Self {
+        Self { start, end }
+    }
+
+    /// A gain that stays the same across the block.
+    pub fn constant(gain: f32) -> Self {
+        Self {
+            start: gain,
+            end: gain,
+        }
+    }
+
+    /// Returns the gain of the first sample and the amount the gain changes
+    /// with each sample in a block of `len` samples.
+    pub(crate) fn start_and_step(&self, len: usize) -> (f32, f32) {
+        if len == 0 {
+            (self.start, 0.0)
+        } else {
+            (self.start, (self.end - self.start) / len as f32)
+        }
+    }
+}
+
+impl Default for GainRamp {
+    fn default() -> Self {
+        Self::constant(1.0)
+    }
+}
+
+impl From<f32> for GainRamp {
+    fn from(gain: f32) -> Self {
+        Self::constant(gain)
+    }
+}
+
+/// How a pan position is turned into the gains of the left and right channels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PanLaw {
+    /// The gains follow a straight line, so the center is at -6 dB.
+    Linear,
+    /// The gains follow a quarter sine/cosine, so the center is at -3 dB and
+    /// the perceived loudness stays the same across the stereo field.
+    #[default]
+    ConstantPower,
+    /// The center is at 0 dB, and panning only attenuates the opposite
+    /// channel. This is the usual choice for stereo sources.
+    Balance,
+}
+
+impl PanLaw {
+    /// Returns the gains of the left and right channels for the given pan
+    /// position, where `-1.0` is hard left, `0.0` is center, and `1.0` is
+    /// hard right.
+    ///
+    /// The pan position is clamped to the range `[-1.0, 1.0]`.
+    pub fn gains(&self, pan: f32) -> (f32, f32) {
+        let pan = pan.clamp(-1.0, 1.0);
+
+        match self {
+            PanLaw::Linear => ((1.0 - pan) * 0.5, (1.0 + pan) * 0.5),
+            PanLaw::ConstantPower => {
+                let angle = (pan + 1.0) * FRAC_PI_4;
+                (angle.cos(), angle.sin())
+            }
+            PanLaw::Balance => ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0)),
+        }
+    }
+}
+
+/// Add the samples in `pcm` to `buf`, applying a gain that starts at `gain`
+/// and changes by `gain_step` with each sample.
+pub(crate) fn add_ramped<T: Copy>(
+    pcm: &[T],
+    buf: &mut [f32],
+    gain: f32,
+    gain_step: f32,
+    to_f32: impl Fn(T) -> f32,
+) {
+    if gain_step == 0.0 {
+        for (out_s, &s) in buf.iter_mut().zip(pcm) {
+            *out_s += to_f32(s) * gain;
+        }
+    } else {
+        for (i, (out_s, &s)) in buf.iter_mut().zip(pcm).enumerate() {
+            *out_s += to_f32(s) * (gain + gain_step * i as f32);
+        }
+    }
+}
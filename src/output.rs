@@ -0,0 +1,74 @@
+use crate::convert::{self, OverflowMode};
+
+/// A sample type that [`DecodedAudio`](crate::DecodedAudio) can fill output
+/// buffers with.
+///
+/// This is implemented for `f32`, `i16`, and `i32`.
+pub trait OutputSample: Copy + 'static {
+    /// The value of silence.
+    const EQUILIBRIUM: Self;
+
+    /// Whether this is an integer format, meaning dither should be added when
+    /// converting to it.
+    const IS_INTEGER: bool;
+
+    /// Convert a sample in the range `[-1.0, 1.0]` to this format, rounding to
+    /// the nearest value and clipping anything out of range.
+    ///
+    /// `dither` is added before rounding, in units of the least significant
+    /// bit. It is ignored by floating point formats.
+    fn from_f32(s: f32, dither: f32) -> Self;
+
+    /// Convert a sample in `i16` format to this format.
+    ///
+    /// `i32` shifts the sample into its upper 16 bits, which is not the same
+    /// full scale as [`OutputSample::from_f32`].
+    fn from_i16(s: i16) -> Self;
+}
+
+impl OutputSample for f32 {
+    const EQUILIBRIUM: Self = 0.0;
+    const IS_INTEGER: bool = false;
+
+    #[inline]
+    fn from_f32(s: f32, _dither: f32) -> Self {
+        s
+    }
+
+    #[inline]
+    fn from_i16(s: i16) -> Self {
+        convert::pcm_i16_to_f32(s)
+    }
+}
+
+impl OutputSample for i16 {
+    const EQUILIBRIUM: Self = 0;
+    const IS_INTEGER: bool = true;
+
+    #[inline]
+    fn from_f32(s: f32, dither: f32) -> Self {
+        convert::f32_to_pcm_i16_dithered(s, dither, OverflowMode::Saturate)
+    }
+
+    #[inline]
+    fn from_i16(s: i16) -> Self {
+        s
+    }
+}
+
+impl OutputSample for i32 {
+    const EQUILIBRIUM: Self = 0;
+    const IS_INTEGER: bool = true;
+
+    #[inline]
+    fn from_f32(s: f32, dither: f32) -> Self {
+        convert::f32_to_pcm_i32_dithered(s, dither, OverflowMode::Saturate)
+    }
+
+    #[inline]
+    fn from_i16(s: i16) -> Self {
+        // Place the sample in the upper 16 bits. See
+        // `DecodedAudio::fill_channel_as` for how this differs from `from_f32`.
+        i32::from(s) << 16
+    }
+}
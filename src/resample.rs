@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::HashMap, fmt::Debug, ops::Range};
 
 // Re-export rubato
 pub use rubato;
@@ -11,6 +11,9 @@ use rubato::{
 #[cfg(feature = "fft-resampler")]
 use rubato::FftFixedIn;
 
+use crate::error::LoadError;
+use crate::resource::FormatConverter;
+
 /// The quality of the resampling algorithm to use.
 #[repr(u32)]
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -49,6 +52,243 @@ pub enum ResampleQuality {
     /// }
     /// ```
     High,
+    /// A resampler with the given settings.
+    ///
+    /// Resamplers are cached separately for every distinct config, and a
+    /// config equal to that of a built-in quality shares its resampler.
+    Custom(ResamplerConfig),
+}
+
+impl ResampleQuality {
+    /// The settings of the resampler used for this quality.
+    pub fn config(&self) -> ResamplerConfig {
+        match self {
+            Self::Low => ResamplerConfig {
+                algorithm: ResamplerAlgorithm::Polynomial {
+                    interpolation: PolynomialInterpolation::Linear,
+                },
+                chunk_size: DEFAULT_CHUNK_SIZE,
+            },
+            #[cfg(feature = "fft-resampler")]
+            Self::Normal => ResamplerConfig {
+                algorithm: ResamplerAlgorithm::Fft { sub_chunks: 2 },
+                chunk_size: DEFAULT_CHUNK_SIZE,
+            },
+            #[cfg(not(feature = "fft-resampler"))]
+            Self::Normal => ResamplerConfig {
+                algorithm: ResamplerAlgorithm::Polynomial {
+                    interpolation: PolynomialInterpolation::Quintic,
+                },
+                chunk_size: DEFAULT_CHUNK_SIZE,
+            },
+            Self::High => ResamplerConfig {
+                algorithm: ResamplerAlgorithm::Sinc {
+                    sinc_len: 128,
+                    f_cutoff: None,
+                    oversampling_factor: 256,
+                    interpolation: SincInterpolation::Cubic,
+                    window: SincWindow::Blackman2,
+                },
+                chunk_size: DEFAULT_CHUNK_SIZE,
+            },
+            Self::Custom(config) => *config,
+        }
+    }
+}
+
+const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// The settings of a resampler. See [`ResampleQuality::Custom`].
+///
+/// The resampler is created the first time it is needed, which returns
+/// [`LoadError::InvalidResamplerConfig`] if the settings are not valid (e.g. a
+/// `chunk_size` of `0`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResamplerConfig {
+    /// The resampling algorithm and its settings.
+    pub algorithm: ResamplerAlgorithm,
+    /// The number of input frames the resampler processes at a time.
+    pub chunk_size: usize,
+}
+
+impl ResamplerConfig {
+    /// Check the settings that rubato accepts but can't resample with, or
+    /// panics on.
+    fn validate(&self) -> Result<(), LoadError> {
+        let invalid = |msg: &str| Err(LoadError::InvalidResamplerConfig(msg.into()));
+
+        if self.chunk_size == 0 {
+            return invalid("chunk_size must be greater than 0");
+        }
+
+        match self.algorithm {
+            ResamplerAlgorithm::Polynomial { .. } => {}
+            #[cfg(feature = "fft-resampler")]
+            ResamplerAlgorithm::Fft { sub_chunks } => {
+                if sub_chunks == 0 || sub_chunks > self.chunk_size {
+                    return invalid("sub_chunks must be between 1 and chunk_size");
+                }
+            }
+            ResamplerAlgorithm::Sinc {
+                sinc_len,
+                oversampling_factor,
+                ..
+            } => {
+                if sinc_len == 0 {
+                    return invalid("sinc_len must be greater than 0");
+                }
+                if oversampling_factor == 0 {
+                    return invalid("oversampling_factor must be greater than 0");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A resampling algorithm from rubato.
+#[derive(Debug, Clone, Copy)]
+pub enum ResamplerAlgorithm {
+    /// The [`FastFixedIn`] resampler, which interpolates between samples with a
+    /// polynomial.
+    Polynomial {
+        interpolation: PolynomialInterpolation,
+    },
+    /// The [`FftFixedIn`] resampler, which splits each chunk into `sub_chunks`
+    /// for the FFT.
+    #[cfg(feature = "fft-resampler")]
+    Fft { sub_chunks: usize },
+    /// The [`SincFixedIn`] resampler. See [`SincInterpolationParameters`].
+    Sinc {
+        sinc_len: usize,
+        /// The cutoff frequency relative to the Nyquist frequency, or `None` to
+        /// use [`rubato::calculate_cutoff`] for `sinc_len` and `window`.
+        f_cutoff: Option<f32>,
+        oversampling_factor: usize,
+        interpolation: SincInterpolation,
+        window: SincWindow,
+    },
+}
+
+impl ResamplerAlgorithm {
+    /// The settings as integers, with the cutoff compared bit for bit, so that
+    /// the algorithm can be part of a [`ResamplerKey`].
+    fn key(&self) -> (u8, usize, usize, Option<u32>, u8, u8) {
+        match *self {
+            Self::Polynomial { interpolation } => (0, interpolation as usize, 0, None, 0, 0),
+            #[cfg(feature = "fft-resampler")]
+            Self::Fft { sub_chunks } => (1, sub_chunks, 0, None, 0, 0),
+            Self::Sinc {
+                sinc_len,
+                f_cutoff,
+                oversampling_factor,
+                interpolation,
+                window,
+            } => (
+                2,
+                sinc_len,
+                oversampling_factor,
+                f_cutoff.map(f32::to_bits),
+                interpolation as u8,
+                window as u8,
+            ),
+        }
+    }
+}
+
+impl PartialEq for ResamplerAlgorithm {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for ResamplerAlgorithm {}
+
+impl PartialOrd for ResamplerAlgorithm {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ResamplerAlgorithm {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl std::hash::Hash for ResamplerAlgorithm {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// The interpolation of [`ResamplerAlgorithm::Polynomial`]. See
+/// [`PolynomialDegree`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PolynomialInterpolation {
+    Nearest,
+    Linear,
+    Cubic,
+    Quintic,
+    Septic,
+}
+
+impl PolynomialInterpolation {
+    fn to_rubato(self) -> PolynomialDegree {
+        match self {
+            Self::Nearest => PolynomialDegree::Nearest,
+            Self::Linear => PolynomialDegree::Linear,
+            Self::Cubic => PolynomialDegree::Cubic,
+            Self::Quintic => PolynomialDegree::Quintic,
+            Self::Septic => PolynomialDegree::Septic,
+        }
+    }
+}
+
+/// The interpolation of [`ResamplerAlgorithm::Sinc`]. See
+/// [`SincInterpolationType`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SincInterpolation {
+    Nearest,
+    Linear,
+    Quadratic,
+    Cubic,
+}
+
+impl SincInterpolation {
+    fn to_rubato(self) -> SincInterpolationType {
+        match self {
+            Self::Nearest => SincInterpolationType::Nearest,
+            Self::Linear => SincInterpolationType::Linear,
+            Self::Quadratic => SincInterpolationType::Quadratic,
+            Self::Cubic => SincInterpolationType::Cubic,
+        }
+    }
+}
+
+/// The window of [`ResamplerAlgorithm::Sinc`]. See [`WindowFunction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SincWindow {
+    Blackman,
+    Blackman2,
+    BlackmanHarris,
+    BlackmanHarris2,
+    Hann,
+    Hann2,
+}
+
+impl SincWindow {
+    fn to_rubato(self) -> WindowFunction {
+        match self {
+            Self::Blackman => WindowFunction::Blackman,
+            Self::Blackman2 => WindowFunction::Blackman2,
+            Self::BlackmanHarris => WindowFunction::BlackmanHarris,
+            Self::BlackmanHarris2 => WindowFunction::BlackmanHarris2,
+            Self::Hann => WindowFunction::Hann,
+            Self::Hann2 => WindowFunction::Hann2,
+        }
+    }
 }
 
 /// A reference to a custom resampler.
@@ -156,7 +396,7 @@ pub(crate) struct ResamplerKey {
     pcm_sr: u32,
     target_sr: u32,
     channels: u32,
-    quality: ResampleQuality,
+    config: ResamplerConfig,
 }
 
 pub(crate) enum ResamplerOwned {
@@ -167,6 +407,95 @@ pub(crate) enum ResamplerOwned {
 }
 
 impl ResamplerOwned {
+    fn new(
+        config: &ResamplerConfig,
+        pcm_sr: u32,
+        target_sr: u32,
+        n_channels: usize,
+    ) -> Result<Self, LoadError> {
+        config.validate()?;
+
+        let ratio = target_sr as f64 / pcm_sr as f64;
+
+        let resampler = match config.algorithm {
+            ResamplerAlgorithm::Polynomial { interpolation } => Self::Fast(FastFixedIn::new(
+                ratio,
+                1.0,
+                interpolation.to_rubato(),
+                config.chunk_size,
+                n_channels,
+            )?),
+            #[cfg(feature = "fft-resampler")]
+            ResamplerAlgorithm::Fft { sub_chunks } => Self::Fft(FftFixedIn::new(
+                pcm_sr as usize,
+                target_sr as usize,
+                config.chunk_size,
+                sub_chunks,
+                n_channels,
+            )?),
+            ResamplerAlgorithm::Sinc {
+                sinc_len,
+                f_cutoff,
+                oversampling_factor,
+                interpolation,
+                window,
+            } => {
+                let window = window.to_rubato();
+                let params = SincInterpolationParameters {
+                    sinc_len,
+                    f_cutoff: f_cutoff
+                        .unwrap_or_else(|| rubato::calculate_cutoff(sinc_len, window)),
+                    interpolation: interpolation.to_rubato(),
+                    oversampling_factor,
+                    window,
+                };
+
+                Self::Sinc(SincFixedIn::new(
+                    ratio,
+                    1.0,
+                    params,
+                    config.chunk_size,
+                    n_channels,
+                )?)
+            }
+        };
+
+        Ok(resampler)
+    }
+
+    /// An estimate of the memory used by the resampler, in bytes.
+    #[cfg_attr(not(feature = "fft-resampler"), allow(unused_variables))]
+    fn estimated_bytes(&mut self, config: &ResamplerConfig, pcm_sr: u32, target_sr: u32) -> usize {
+        let channels = self.as_ref_mut().num_channels();
+        let chunk_size = config.chunk_size;
+
+        // The number of `f32` values held by the resampler.
+        let samples = match config.algorithm {
+            // The input buffer, with room for the points of the polynomial.
+            ResamplerAlgorithm::Polynomial { .. } => channels * (chunk_size + 16),
+            // The input buffers and overlaps of each channel, and the filter
+            // and FFT buffers.
+            #[cfg(feature = "fft-resampler")]
+            ResamplerAlgorithm::Fft { .. } => {
+                let fft_size_out = self.as_ref_mut().output_delay() * 2;
+                let fft_size_in =
+                    (fft_size_out as u64 * u64::from(pcm_sr) / u64::from(target_sr)) as usize;
+
+                channels * (chunk_size + fft_size_in + fft_size_out)
+                    + 10 * fft_size_in
+                    + 6 * fft_size_out
+            }
+            // The table of sincs, and the input buffer.
+            ResamplerAlgorithm::Sinc {
+                sinc_len,
+                oversampling_factor,
+                ..
+            } => sinc_len * oversampling_factor + channels * (chunk_size + 2 * sinc_len),
+        };
+
+        std::mem::size_of::<CachedResampler>() + samples * std::mem::size_of::<f32>()
+    }
+
     pub fn as_ref_mut<'a>(&'a mut self) -> ResamplerRefMut<'a> {
         match self {
             Self::Fast(r) => ResamplerRefMut::Fast(r),
@@ -177,102 +506,445 @@ impl ResamplerOwned {
     }
 }
 
-pub(crate) fn get_resampler<'a>(
-    resamplers: &'a mut HashMap<ResamplerKey, ResamplerOwned>,
-    resample_quality: ResampleQuality,
-    pcm_sr: u32,
-    target_sr: u32,
-    n_channels: usize,
-) -> ResamplerRefMut<'a> {
-    const CHUNK_SIZE: usize = 1024;
-
-    match resample_quality {
-        ResampleQuality::Low => resamplers
-            .entry(ResamplerKey {
-                pcm_sr,
-                target_sr,
-                channels: n_channels as u32,
-                quality: ResampleQuality::Low,
-            })
-            .or_insert_with(|| {
-                ResamplerOwned::Fast(
-                    FastFixedIn::new(
-                        target_sr as f64 / pcm_sr as f64,
-                        1.0,
-                        PolynomialDegree::Linear,
-                        CHUNK_SIZE,
-                        n_channels,
-                    )
-                    .unwrap(),
-                )
-            })
-            .as_ref_mut(),
-        ResampleQuality::Normal => resamplers
-            .entry(ResamplerKey {
-                pcm_sr,
-                target_sr,
-                channels: n_channels as u32,
-                quality: ResampleQuality::Normal,
-            })
-            .or_insert_with(|| {
-                #[cfg(feature = "fft-resampler")]
-                return ResamplerOwned::Fft(
-                    FftFixedIn::new(
-                        pcm_sr as usize,
-                        target_sr as usize,
-                        CHUNK_SIZE,
-                        2,
-                        n_channels,
-                    )
-                    .unwrap(),
-                );
+/// The resamplers kept by a [`SymphoniumLoader`] to be reused.
+///
+/// If the resamplers take up more than `max_bytes`, then the least recently
+/// used ones are dropped.
+///
+/// [`SymphoniumLoader`]: crate::SymphoniumLoader
+#[derive(Default)]
+pub(crate) struct ResamplerCache {
+    resamplers: HashMap<ResamplerKey, CachedResampler>,
+    max_bytes: Option<usize>,
+    total_bytes: usize,
+    /// Incremented every time a resampler is used.
+    clock: u64,
+}
 
-                #[cfg(not(feature = "fft-resampler"))]
-                return ResamplerOwned::Fast(
-                    FastFixedIn::new(
-                        target_sr as f64 / pcm_sr as f64,
-                        1.0,
-                        PolynomialDegree::Quintic,
-                        CHUNK_SIZE,
-                        n_channels,
-                    )
-                    .unwrap(),
+struct CachedResampler {
+    resampler: ResamplerOwned,
+    bytes: usize,
+    last_used: u64,
+}
+
+impl ResamplerCache {
+    /// Get the resampler with the given settings, creating it if it is not
+    /// cached.
+    ///
+    /// Returns [`LoadError::InvalidResamplerConfig`] if the resampler can't be
+    /// created with the settings of `resample_quality`.
+    pub fn get(
+        &mut self,
+        resample_quality: ResampleQuality,
+        pcm_sr: u32,
+        target_sr: u32,
+        n_channels: usize,
+    ) -> Result<ResamplerRefMut<'_>, LoadError> {
+        let config = resample_quality.config();
+        let key = ResamplerKey {
+            pcm_sr,
+            target_sr,
+            channels: n_channels as u32,
+            config,
+        };
+
+        if !self.resamplers.contains_key(&key) {
+            let mut resampler = ResamplerOwned::new(&config, pcm_sr, target_sr, n_channels)?;
+            let bytes = resampler.estimated_bytes(&config, pcm_sr, target_sr);
+
+            self.total_bytes += bytes;
+            self.resamplers.insert(
+                key,
+                CachedResampler {
+                    resampler,
+                    bytes,
+                    last_used: 0,
+                },
+            );
+
+            self.evict(Some(key));
+        }
+
+        self.clock += 1;
+
+        let cached = self.resamplers.get_mut(&key).unwrap();
+        cached.last_used = self.clock;
+        Ok(cached.resampler.as_ref_mut())
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: Option<usize>) {
+        self.max_bytes = max_bytes;
+        self.evict(None);
+    }
+
+    pub fn clear(&mut self) {
+        self.resamplers.clear();
+        self.total_bytes = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.resamplers.len()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    /// Drop the least recently used resamplers other than `keep` until the
+    /// cache fits in `max_bytes`.
+    fn evict(&mut self, keep: Option<ResamplerKey>) {
+        let Some(max_bytes) = self.max_bytes else {
+            return;
+        };
+
+        while self.total_bytes > max_bytes {
+            let Some(lru) = self
+                .resamplers
+                .iter()
+                .filter(|(key, _)| Some(**key) != keep)
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+
+            let evicted = self.resamplers.remove(&lru).unwrap();
+            self.total_bytes -= evicted.bytes;
+        }
+    }
+}
+
+/// Where the output of a [`ChunkedResampler`] is written to.
+pub(crate) trait ResampledSink {
+    /// Append the frames in `range` of each channel.
+    fn extend(&mut self, channels: &[Vec<f32>], range: Range<usize>);
+
+    /// The number of frames written so far.
+    fn frames(&self) -> usize;
+
+    /// Shorten the output to the given number of frames.
+    fn truncate(&mut self, frames: usize);
+
+    /// The number of bytes each output sample takes up in memory.
+    fn sample_bytes(&self) -> usize;
+}
+
+impl ResampledSink for Vec<Vec<f32>> {
+    fn extend(&mut self, channels: &[Vec<f32>], range: Range<usize>) {
+        for (out_ch, ch) in self.iter_mut().zip(channels) {
+            out_ch.extend_from_slice(&ch[range.clone()]);
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.first().map_or(0, Vec::len)
+    }
+
+    fn truncate(&mut self, frames: usize) {
+        for ch in self.iter_mut() {
+            ch.truncate(frames);
+        }
+    }
+
+    fn sample_bytes(&self) -> usize {
+        4
+    }
+}
+
+/// Requantizes the output one chunk at a time, so that it is never all held
+/// as `f32`.
+impl ResampledSink for FormatConverter {
+    fn extend(&mut self, channels: &[Vec<f32>], range: Range<usize>) {
+        let pcm: Vec<&[f32]> = channels.iter().map(|ch| &ch[range.clone()]).collect();
+        self.push(&pcm);
+    }
+
+    fn frames(&self) -> usize {
+        FormatConverter::frames(self)
+    }
+
+    fn truncate(&mut self, frames: usize) {
+        FormatConverter::truncate(self, frames);
+    }
+
+    fn sample_bytes(&self) -> usize {
+        self.format().bytes()
+    }
+}
+
+/// Feeds samples of any length through a resampler in the chunks it needs,
+/// discarding the delay of the resampler so that the output lines up with the
+/// input.
+pub(crate) struct ChunkedResampler<'a> {
+    resampler: ResamplerRefMut<'a>,
+    in_buf: Vec<Vec<f32>>,
+    out_buf: Vec<Vec<f32>>,
+    in_len: usize,
+    desired_in_frames: usize,
+    delay_frames_left: usize,
+    total_in_frames: usize,
+    /// The target sample rate divided by the source sample rate.
+    ratio: f64,
+}
+
+impl<'a> ChunkedResampler<'a> {
+    /// This returns an error if the resampler does not process the given
+    /// number of channels.
+    pub fn new(
+        mut resampler: ResamplerRefMut<'a>,
+        channels: usize,
+        source_sample_rate: u32,
+        target_sample_rate: u32,
+    ) -> Result<Self, LoadError> {
+        if resampler.num_channels() != channels {
+            return Err(LoadError::InvalidResampler {
+                needed_channels: channels,
+                got_channels: resampler.num_channels(),
+            });
+        }
+
+        resampler.reset();
+
+        Ok(Self {
+            in_buf: vec![vec![0.0; resampler.input_frames_max()]; channels],
+            out_buf: vec![vec![0.0; resampler.output_frames_max()]; channels],
+            in_len: 0,
+            desired_in_frames: resampler.input_frames_next(),
+            delay_frames_left: resampler.output_delay(),
+            total_in_frames: 0,
+            ratio: target_sample_rate as f64 / source_sample_rate as f64,
+            resampler,
+        })
+    }
+
+    /// An estimate of the number of output frames for the given number of
+    /// input frames, which is never too small.
+    pub fn max_output_frames(&mut self, in_frames: usize) -> usize {
+        (in_frames as f64 * self.ratio).ceil() as usize + self.resampler.output_frames_max()
+    }
+
+    /// Resample `frames` frames of input, writing any output to `sink`.
+    ///
+    /// `fill(channel, start, buf)` is called to write the input frames of
+    /// `channel` in the range `[start, start + buf.len())` into `buf`.
+    pub fn process(
+        &mut self,
+        frames: usize,
+        mut fill: impl FnMut(usize, usize, &mut [f32]),
+        sink: &mut impl ResampledSink,
+    ) -> Result<(), LoadError> {
+        let mut copied_frames = 0;
+        while copied_frames < frames {
+            let copy_frames = (frames - copied_frames).min(self.desired_in_frames - self.in_len);
+
+            for (ch, in_ch) in self.in_buf.iter_mut().enumerate() {
+                fill(
+                    ch,
+                    copied_frames,
+                    &mut in_ch[self.in_len..self.in_len + copy_frames],
                 );
-            })
-            .as_ref_mut(),
-        ResampleQuality::High => resamplers
-            .entry(ResamplerKey {
-                pcm_sr,
-                target_sr,
-                channels: n_channels as u32,
-                quality: ResampleQuality::High,
-            })
-            .or_insert_with(|| {
-                let sinc_len = 128;
-                let oversampling_factor = 256;
-                let interpolation = SincInterpolationType::Cubic;
-                let window = WindowFunction::Blackman2;
-
-                let f_cutoff = rubato::calculate_cutoff(sinc_len, window);
-                let params = SincInterpolationParameters {
-                    sinc_len,
-                    f_cutoff,
-                    interpolation,
-                    oversampling_factor,
-                    window,
-                };
+            }
+
+            self.in_len += copy_frames;
+            if self.in_len == self.desired_in_frames {
+                self.resample_chunk(sink)?;
+            }
+
+            copied_frames += copy_frames;
+        }
+
+        self.total_in_frames += frames;
+
+        Ok(())
+    }
+
+    /// Flush the resampler, so that the output is exactly as long as the input
+    /// at the target sample rate.
+    pub fn finish(mut self, sink: &mut impl ResampledSink) -> Result<(), LoadError> {
+        let total_frames = (self.total_in_frames as f64 * self.ratio).ceil() as usize;
+
+        // Process any leftover samples.
+        if self.in_len > 0 {
+            // Zero-pad remaining samples.
+            for ch in self.in_buf.iter_mut() {
+                ch[self.in_len..self.desired_in_frames].fill(0.0);
+            }
+
+            self.resample_chunk(sink)?;
+        }
+
+        // Extract any leftover samples from the resampler.
+        while sink.frames() < total_frames {
+            // Clear samples.
+            for ch in self.in_buf.iter_mut() {
+                ch[..self.desired_in_frames].fill(0.0);
+            }
+
+            self.resample_chunk(sink)?;
+        }
+
+        // Truncate the extra padded data.
+        sink.truncate(total_frames);
+
+        Ok(())
+    }
+
+    fn resample_chunk(&mut self, sink: &mut impl ResampledSink) -> Result<(), LoadError> {
+        let (_, output_frames) =
+            self.resampler
+                .process_into_buffer(&self.in_buf, &mut self.out_buf, None)?;
+
+        if self.delay_frames_left >= output_frames {
+            // Wait until the first non-delayed output sample.
+            self.delay_frames_left -= output_frames;
+        } else {
+            sink.extend(&self.out_buf, self.delay_frames_left..output_frames);
+            self.delay_frames_left = 0;
+        }
+
+        self.desired_in_frames = self.resampler.input_frames_next();
+        self.in_len = 0;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_resampler_cache() {
+        let mut resamplers = ResamplerCache::default();
+
+        let config = ResamplerConfig {
+            algorithm: ResamplerAlgorithm::Sinc {
+                sinc_len: 64,
+                f_cutoff: Some(0.9),
+                oversampling_factor: 128,
+                interpolation: SincInterpolation::Linear,
+                window: SincWindow::Hann,
+            },
+            chunk_size: 256,
+        };
+
+        let mut resampler = resamplers
+            .get(ResampleQuality::Custom(config), 44100, 48000, 2)
+            .unwrap();
+        assert_eq!(resampler.input_frames_next(), 256);
+        assert_eq!(resampler.num_channels(), 2);
+
+        // The same config reuses the cached resampler.
+        resamplers
+            .get(ResampleQuality::Custom(config), 44100, 48000, 2)
+            .unwrap();
+        assert_eq!(resamplers.len(), 1);
+
+        // Any change to the config is a different resampler, including the
+        // cutoff.
+        let mut other = config;
+        if let ResamplerAlgorithm::Sinc { f_cutoff, .. } = &mut other.algorithm {
+            *f_cutoff = Some(0.95);
+        }
+        assert_ne!(config, other);
+        resamplers
+            .get(ResampleQuality::Custom(other), 44100, 48000, 2)
+            .unwrap();
+        assert_eq!(resamplers.len(), 2);
+
+        // A built-in quality shares its resampler with a custom one with the
+        // same config.
+        resamplers
+            .get(ResampleQuality::High, 44100, 48000, 2)
+            .unwrap();
+        resamplers
+            .get(
+                ResampleQuality::Custom(ResampleQuality::High.config()),
+                44100,
+                48000,
+                2,
+            )
+            .unwrap();
+        assert_eq!(resamplers.len(), 3);
+    }
+
+    #[test]
+    fn resampler_cache_eviction() {
+        let mut resamplers = ResamplerCache::default();
+
+        resamplers
+            .get(ResampleQuality::Low, 44100, 48000, 2)
+            .unwrap();
+        let bytes = resamplers.bytes();
+        assert!(bytes > 0);
+
+        resamplers.set_max_bytes(Some(bytes * 2));
+        resamplers
+            .get(ResampleQuality::Low, 22050, 48000, 2)
+            .unwrap();
+        assert_eq!(resamplers.bytes(), bytes * 2);
+
+        // The resampler for 22050 Hz is now the least recently used one.
+        resamplers
+            .get(ResampleQuality::Low, 44100, 48000, 2)
+            .unwrap();
+        resamplers
+            .get(ResampleQuality::Low, 32000, 48000, 2)
+            .unwrap();
+        assert_eq!(resamplers.len(), 2);
+        assert_eq!(resamplers.bytes(), bytes * 2);
+        assert!(resamplers.resamplers.keys().all(|key| key.pcm_sr != 22050));
+
+        // The resampler that is needed is kept even if it is too large.
+        resamplers.set_max_bytes(Some(bytes / 2));
+        assert_eq!(resamplers.len(), 0);
+        resamplers
+            .get(ResampleQuality::Low, 44100, 48000, 2)
+            .unwrap();
+        assert_eq!(resamplers.len(), 1);
+
+        resamplers.clear();
+        assert_eq!(resamplers.len(), 0);
+        assert_eq!(resamplers.bytes(), 0);
+    }
+
+    #[test]
+    fn invalid_resampler_config() {
+        let mut resamplers = ResamplerCache::default();
+
+        let sinc = |sinc_len, oversampling_factor, chunk_size| ResamplerConfig {
+            algorithm: ResamplerAlgorithm::Sinc {
+                sinc_len,
+                f_cutoff: None,
+                oversampling_factor,
+                interpolation: SincInterpolation::Linear,
+                window: SincWindow::Hann,
+            },
+            chunk_size,
+        };
+
+        #[cfg_attr(not(feature = "fft-resampler"), allow(unused_mut))]
+        let mut invalid = vec![sinc(64, 128, 0), sinc(0, 128, 256), sinc(64, 0, 256)];
+        #[cfg(feature = "fft-resampler")]
+        invalid.extend([0, 257].map(|sub_chunks| ResamplerConfig {
+            algorithm: ResamplerAlgorithm::Fft { sub_chunks },
+            chunk_size: 256,
+        }));
+
+        for config in invalid {
+            assert!(matches!(
+                resamplers.get(ResampleQuality::Custom(config), 44100, 48000, 2),
+                Err(LoadError::InvalidResamplerConfig(_))
+            ));
+        }
+
+        // A sample rate that rubato rejects.
+        assert!(matches!(
+            resamplers.get(ResampleQuality::Custom(sinc(64, 128, 256)), 44100, 0, 2),
+            Err(LoadError::InvalidResamplerConfig(_))
+        ));
 
-                ResamplerOwned::Sinc(
-                    SincFixedIn::new(
-                        target_sr as f64 / pcm_sr as f64,
-                        1.0,
-                        params,
-                        CHUNK_SIZE,
-                        n_channels,
-                    )
-                    .unwrap(),
-                )
-            })
-            .as_ref_mut(),
+        assert_eq!(resamplers.len(), 0);
+        assert_eq!(resamplers.bytes(), 0);
     }
 }
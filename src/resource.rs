@@ -1,23 +1,127 @@
-use super::convert;
+use std::borrow::Cow;
+use std::ops::{Bound, Range, RangeBounds};
+use std::sync::Arc;
+
+use super::channel::{ChannelLayout, ChannelOrder, MixMatrix};
+use super::dither::TpdfDither;
+use super::error::EditError;
+use super::fade::{self, PlaybackRegion};
+use super::interpolate::{self, Interpolation};
+use super::looping::{self, LoopRegion, Playhead};
+use super::mix::{self, GainRamp, PanLaw};
+use super::output::OutputSample;
+use super::sample::{ChannelMapper, ChannelVisitor, Sample, I24, U24};
+
+/// Evaluate `$body` with `$pcm` bound to the channels of whichever variant
+/// `$resource` is.
+macro_rules! with_channels {
+    ($resource:expr, $pcm:ident => $body:expr) => {
+        match $resource {
+            DecodedAudioType::U8($pcm) => $body,
+            DecodedAudioType::U16($pcm) => $body,
+            DecodedAudioType::U24($pcm) => $body,
+            DecodedAudioType::S8($pcm) => $body,
+            DecodedAudioType::S16($pcm) => $body,
+            DecodedAudioType::S24($pcm) => $body,
+            DecodedAudioType::F32($pcm) => $body,
+            DecodedAudioType::F64($pcm) => $body,
+            #[cfg(feature = "f16")]
+            DecodedAudioType::F16($pcm) => $body,
+        }
+    };
+}
+
+/// Same as `with_channels`, but also rebuilds a `DecodedAudioType` of the same
+/// variant from the result of `$body`.
+macro_rules! map_channels {
+    ($resource:expr, $pcm:ident => $body:expr) => {
+        match $resource {
+            DecodedAudioType::U8($pcm) => DecodedAudioType::U8($body),
+            DecodedAudioType::U16($pcm) => DecodedAudioType::U16($body),
+            DecodedAudioType::U24($pcm) => DecodedAudioType::U24($body),
+            DecodedAudioType::S8($pcm) => DecodedAudioType::S8($body),
+            DecodedAudioType::S16($pcm) => DecodedAudioType::S16($body),
+            DecodedAudioType::S24($pcm) => DecodedAudioType::S24($body),
+            DecodedAudioType::F32($pcm) => DecodedAudioType::F32($body),
+            DecodedAudioType::F64($pcm) => DecodedAudioType::F64($body),
+            #[cfg(feature = "f16")]
+            DecodedAudioType::F16($pcm) => DecodedAudioType::F16($body),
+        }
+    };
+}
+
+/// Evaluate `$body` with `$a` and `$b` bound to the channels of two resources,
+/// or return an error if they are not the same variant.
+macro_rules! with_channels_pair {
+    ($a_res:expr, $b_res:expr, ($a:ident, $b:ident) => $body:expr) => {
+        match ($a_res, $b_res) {
+            (DecodedAudioType::U8($a), DecodedAudioType::U8($b)) => $body,
+            (DecodedAudioType::U16($a), DecodedAudioType::U16($b)) => $body,
+            (DecodedAudioType::U24($a), DecodedAudioType::U24($b)) => $body,
+            (DecodedAudioType::S8($a), DecodedAudioType::S8($b)) => $body,
+            (DecodedAudioType::S16($a), DecodedAudioType::S16($b)) => $body,
+            (DecodedAudioType::S24($a), DecodedAudioType::S24($b)) => $body,
+            (DecodedAudioType::F32($a), DecodedAudioType::F32($b)) => $body,
+            (DecodedAudioType::F64($a), DecodedAudioType::F64($b)) => $body,
+            #[cfg(feature = "f16")]
+            (DecodedAudioType::F16($a), DecodedAudioType::F16($b)) => $body,
+            _ => return Err(EditError::SampleFormatMismatch),
+        }
+    };
+}
+
+mod format;
+mod ops;
+#[cfg(feature = "resampler")]
+mod resample;
+
+pub(crate) use format::FormatConverter;
+pub use format::{SampleFormat, StorageFormat};
 
 /// A resource of raw f32 audio samples stored in deinterleaved format.
 ///
 /// This struct stores samples
 /// in their native sample format when possible to save memory.
 pub struct DecodedAudioF32 {
+    /// The samples of each channel.
+    ///
+    /// If channels are added or removed, then [`DecodedAudioF32::layout`] no
+    /// longer knows their positions and returns the default layout for the
+    /// new number of channels.
     pub data: Vec<Vec<f32>>,
     pub sample_rate: u32,
+    layout: ChannelLayout,
+    source_layout: ChannelLayout,
+    source_order: ChannelOrder,
 }
 
 impl DecodedAudioF32 {
     pub fn new(data: Vec<Vec<f32>>, sample_rate: u32) -> Self {
+        let layout = ChannelLayout::default_for_channels(data.len());
+
+        Self::new_with_layout(data, sample_rate, layout)
+    }
+
+    /// Construct a new resource with the given channel layout.
+    ///
+    /// This will panic if the number of channels in `layout` does not match
+    /// the number of channels in `data`.
+    pub fn new_with_layout(data: Vec<Vec<f32>>, sample_rate: u32, layout: ChannelLayout) -> Self {
         let frames = data[0].len();
 
         for ch in data.iter().skip(1) {
             assert_eq!(ch.len(), frames);
         }
 
-        Self { data, sample_rate }
+        assert_eq!(layout.channels(), data.len());
+
+        Self {
+            data,
+            sample_rate,
+            source_layout: layout.clone(),
+            source_order: ChannelOrder::Wav,
+            layout,
+        }
     }
 
     /// The number of channels in this resource.
@@ -30,16 +134,59 @@ impl DecodedAudioF32 {
     pub fn frames(&self) -> usize {
         self.data[0].len()
     }
+
+    /// The speaker positions of the channels in `data`.
+    ///
+    /// If the number of channels in `data` was changed since this resource
+    /// was created, then this is [`ChannelLayout::default_for_channels`].
+    pub fn layout(&self) -> Cow<'_, ChannelLayout> {
+        if self.layout.channels() == self.data.len() {
+            Cow::Borrowed(&self.layout)
+        } else {
+            Cow::Owned(ChannelLayout::default_for_channels(self.data.len()))
+        }
+    }
+
+    /// The speaker positions of the channels in the order they were stored in
+    /// the source.
+    pub fn source_layout(&self) -> &ChannelLayout {
+        &self.source_layout
+    }
+
+    /// The channel ordering convention used by the source.
+    pub fn source_order(&self) -> ChannelOrder {
+        self.source_order
+    }
+
+    pub(crate) fn set_source_layout(&mut self, layout: ChannelLayout, order: ChannelOrder) {
+        self.source_layout = layout;
+        self.source_order = order;
+    }
+
+    /// Reorder the channels of this resource into the canonical (WAV/SMPTE)
+    /// order. See [`ChannelLayout::canonical_order`].
+    pub fn to_canonical_order(&mut self) {
+        let layout = self.layout().into_owned();
+
+        permute_channels(&mut self.data, &layout.canonical_order());
+        self.layout = layout.to_canonical();
+    }
 }
 
-impl Into<DecodedAudio> for DecodedAudioF32 {
-    fn into(self) -> DecodedAudio {
-        let channels = self.channels();
-        let frames = self.frames();
+impl From<DecodedAudioF32> for DecodedAudio {
+    fn from(pcm: DecodedAudioF32) -> Self {
+        let channels = pcm.channels();
+        let frames = pcm.frames();
+        let layout = pcm.layout().into_owned();
 
         DecodedAudio {
-            resource_type: DecodedAudioType::F32(self.data),
-            sample_rate: self.sample_rate,
+            resource_type: Arc::new(DecodedAudioType::F32(pcm.data)),
+            offset: 0,
+            sample_rate: pcm.sample_rate,
+            layout,
+            source_layout: pcm.source_layout,
+            source_order: pcm.source_order,
+            detected_bit_depth: None,
             channels,
             frames,
         }
@@ -50,9 +197,16 @@ impl Into<DecodedAudio> for DecodedAudioF32 {
 ///
 /// This struct stores samples
 /// in their native sample format when possible to save memory.
+#[derive(Clone)]
 pub struct DecodedAudio {
-    resource_type: DecodedAudioType,
+    resource_type: Arc<DecodedAudioType>,
+    /// The first frame of this view into `resource_type`.
+    offset: usize,
     sample_rate: u32,
+    layout: ChannelLayout,
+    source_layout: ChannelLayout,
+    source_order: ChannelOrder,
+    detected_bit_depth: Option<u32>,
     channels: usize,
     frames: usize,
 }
@@ -62,101 +216,119 @@ pub struct DecodedAudio {
 /// Note that there is no option for U32/I32. This is because in processing
 /// we ultimately use `f32` for everything anyway. We only store the other
 /// types to save memory.
+///
+/// Use [`DecodedAudioType::visit`] and [`DecodedAudioType::map`] to work
+/// with the samples without matching on every variant.
+#[derive(Clone)]
 pub enum DecodedAudioType {
     U8(Vec<Vec<u8>>),
     U16(Vec<Vec<u16>>),
     /// The endianness of the samples must be the native endianness of the
     /// target platform.
-    U24(Vec<Vec<[u8; 3]>>),
+    ///
+    /// The samples used to be stored as `[u8; 3]`. [`U24`] converts to and
+    /// from the bytes with [`From`].
+    U24(Vec<Vec<U24>>),
     S8(Vec<Vec<i8>>),
     S16(Vec<Vec<i16>>),
     /// The endianness of the samples must be the native endianness of the
     /// target platform.
-    S24(Vec<Vec<[u8; 3]>>),
+    ///
+    /// The samples used to be stored as `[u8; 3]`. [`I24`] converts to and
+    /// from the bytes with [`From`].
+    S24(Vec<Vec<I24>>),
     F32(Vec<Vec<f32>>),
     F64(Vec<Vec<f64>>),
+    /// Half-precision floating point samples, which take up half the memory
+    /// of `f32` samples.
+    #[cfg(feature = "f16")]
+    F16(Vec<Vec<half::f16>>),
 }
 
-impl DecodedAudio {
-    pub fn new(resource_type: DecodedAudioType, sample_rate: u32) -> Self {
-        let (channels, frames) = match &resource_type {
-            DecodedAudioType::U8(b) => {
-                let len = b[0].len();
-
-                for ch in b.iter().skip(1) {
-                    assert_eq!(ch.len(), len);
-                }
-
-                (b.len(), len)
-            }
-            DecodedAudioType::U16(b) => {
-                let len = b[0].len();
-
-                for ch in b.iter().skip(1) {
-                    assert_eq!(ch.len(), len);
-                }
-
-                (b.len(), len)
-            }
-            DecodedAudioType::U24(b) => {
-                let len = b[0].len();
-
-                for ch in b.iter().skip(1) {
-                    assert_eq!(ch.len(), len);
-                }
-
-                (b.len(), len)
-            }
-            DecodedAudioType::S8(b) => {
-                let len = b[0].len();
+impl DecodedAudioType {
+    /// Call the visitor with the channels of this resource, whichever sample
+    /// type they are stored as.
+    ///
+    /// ```
+    /// # use symphonium::{ChannelVisitor, DecodedAudioType, Sample};
+    /// struct Peak;
+    ///
+    /// impl ChannelVisitor for Peak {
+    ///     type Output = f32;
+    ///
+    ///     fn visit<T: Sample>(self, channels: &[Vec<T>]) -> f32 {
+    ///         channels
+    ///             .iter()
+    ///             .flatten()
+    ///             .fold(0.0, |peak, s| s.to_f32().abs().max(peak))
+    ///     }
+    /// }
+    ///
+    /// let pcm = DecodedAudioType::S16(vec![vec![0, -16384, 8192]]);
+    /// assert_eq!(pcm.visit(Peak), 16384.0 / 32767.0);
+    /// ```
+    pub fn visit<V: ChannelVisitor>(&self, visitor: V) -> V::Output {
+        with_channels!(self, pcm => visitor.visit(pcm))
+    }
 
-                for ch in b.iter().skip(1) {
-                    assert_eq!(ch.len(), len);
-                }
+    /// Build a new resource of the same sample type from the channels
+    /// returned by the mapper.
+    pub fn map<M: ChannelMapper>(&self, mapper: M) -> DecodedAudioType {
+        map_channels!(self, pcm => mapper.map(pcm))
+    }
 
-                (b.len(), len)
-            }
-            DecodedAudioType::S16(b) => {
-                let len = b[0].len();
+    /// Returns the number of channels.
+    fn channel_count(&self) -> usize {
+        with_channels!(self, pcm => pcm.len())
+    }
 
-                for ch in b.iter().skip(1) {
-                    assert_eq!(ch.len(), len);
-                }
+    /// Returns the number of channels and frames, panicking if the channels
+    /// are not all the same length.
+    fn dimensions(&self) -> (usize, usize) {
+        with_channels!(self, b => {
+            let len = b[0].len();
 
-                (b.len(), len)
+            for ch in b.iter().skip(1) {
+                assert_eq!(ch.len(), len);
             }
-            DecodedAudioType::S24(b) => {
-                let len = b[0].len();
 
-                for ch in b.iter().skip(1) {
-                    assert_eq!(ch.len(), len);
-                }
-
-                (b.len(), len)
-            }
-            DecodedAudioType::F32(b) => {
-                let len = b[0].len();
+            (b.len(), len)
+        })
+    }
+}
 
-                for ch in b.iter().skip(1) {
-                    assert_eq!(ch.len(), len);
-                }
+impl DecodedAudio {
+    pub fn new(resource_type: DecodedAudioType, sample_rate: u32) -> Self {
+        let (channels, _) = resource_type.dimensions();
 
-                (b.len(), len)
-            }
-            DecodedAudioType::F64(b) => {
-                let len = b[0].len();
+        Self::new_with_layout(
+            resource_type,
+            sample_rate,
+            ChannelLayout::default_for_channels(channels),
+        )
+    }
 
-                for ch in b.iter().skip(1) {
-                    assert_eq!(ch.len(), len);
-                }
+    /// Construct a new resource with the given channel layout.
+    ///
+    /// This will panic if the number of channels in `layout` does not match
+    /// the number of channels in `resource_type`.
+    pub fn new_with_layout(
+        resource_type: DecodedAudioType,
+        sample_rate: u32,
+        layout: ChannelLayout,
+    ) -> Self {
+        let (channels, frames) = resource_type.dimensions();
 
-                (b.len(), len)
-            }
-        };
+        assert_eq!(layout.channels(), channels);
 
         Self {
-            resource_type,
+            resource_type: Arc::new(resource_type),
+            offset: 0,
             sample_rate,
+            source_layout: layout.clone(),
+            source_order: ChannelOrder::Wav,
+            detected_bit_depth: None,
+            layout,
             channels,
             frames,
         }
@@ -167,6 +339,18 @@ impl DecodedAudio {
         self.channels
     }
 
+    /// The number of channels stored in [`DecodedAudio::storage`].
+    ///
+    /// This is less than [`DecodedAudio::channels`] if the channels were
+    /// identical and were collapsed into one while loading (see
+    /// [`LoadOptions::collapse_identical_channels`]), in which case the one
+    /// stored channel is used for every channel.
+    ///
+    /// [`LoadOptions::collapse_identical_channels`]: crate::LoadOptions::collapse_identical_channels
+    pub fn stored_channels(&self) -> usize {
+        self.resource_type.channel_count()
+    }
+
     /// The length of this resource in frames (length of a single channel in
     /// samples).
     pub fn frames(&self) -> usize {
@@ -178,10 +362,205 @@ impl DecodedAudio {
         self.sample_rate
     }
 
-    pub fn get(&self) -> &DecodedAudioType {
+    /// The speaker positions of the channels in this resource.
+    pub fn layout(&self) -> &ChannelLayout {
+        &self.layout
+    }
+
+    /// The speaker positions of the channels in the order they were stored in
+    /// the source.
+    pub fn source_layout(&self) -> &ChannelLayout {
+        &self.source_layout
+    }
+
+    /// The channel ordering convention used by the source.
+    pub fn source_order(&self) -> ChannelOrder {
+        self.source_order
+    }
+
+    /// The number of bits that the samples of the source actually use, if it
+    /// was detected while loading. See [`LoadOptions::detect_bit_depth`].
+    ///
+    /// [`LoadOptions::detect_bit_depth`]: crate::LoadOptions::detect_bit_depth
+    pub fn detected_bit_depth(&self) -> Option<u32> {
+        self.detected_bit_depth
+    }
+
+    /// Detect the number of bits that the samples actually use, and store them
+    /// in the smallest format that holds them.
+    pub(crate) fn pack_to_detected_bit_depth(&mut self) {
+        let bits = self.resource_type.effective_bits();
+
+        if let Some(packed) = self.resource_type.repack(bits) {
+            self.resource_type = Arc::new(packed);
+        }
+
+        self.detected_bit_depth = Some(bits);
+    }
+
+    /// Store only the first channel if every channel is within `tolerance` of
+    /// it, while still reporting the same number of channels.
+    pub(crate) fn collapse_identical_channels(&mut self, tolerance: f64) {
+        if self.stored_channels() < 2 {
+            return;
+        }
+
+        let range = self.view();
+        let identical = with_channels!(&*self.resource_type, pcm => {
+            channels_match(pcm, range.clone(), tolerance)
+        });
+
+        if identical {
+            let storage = map_channels!(&*self.resource_type, pcm => vec![pcm[0][range].to_vec()]);
+
+            self.resource_type = Arc::new(storage);
+            self.offset = 0;
+        }
+    }
+
+    /// Store every channel again if they were collapsed into one.
+    fn expand_channels(&mut self) {
+        if self.stored_channels() == self.channels {
+            return;
+        }
+
+        let channels = self.channels;
+        with_channels!(self.storage_mut(), pcm => {
+            let ch = pcm[0].clone();
+            pcm.resize(channels, ch);
+        });
+    }
+
+    /// The index of the stored channel that holds the samples of `channel`.
+    fn stored_channel(&self, channel: usize) -> usize {
+        if self.stored_channels() == 1 {
+            0
+        } else {
+            channel
+        }
+    }
+
+    /// Returns a resource with the given storage, and the same channels and
+    /// metadata as this one.
+    fn with_storage(&self, storage: DecodedAudioType) -> DecodedAudio {
+        let (_, frames) = storage.dimensions();
+
+        DecodedAudio {
+            resource_type: Arc::new(storage),
+            offset: 0,
+            frames,
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn set_source_layout(&mut self, layout: ChannelLayout, order: ChannelOrder) {
+        self.source_layout = layout;
+        self.source_order = order;
+    }
+
+    /// Reorder the channels of this resource into the canonical (WAV/SMPTE)
+    /// order. See [`ChannelLayout::canonical_order`].
+    pub fn to_canonical_order(&mut self) {
+        let order = self.layout.canonical_order();
+
+        // Collapsed channels are all the same, so they do not need reordering.
+        if self.stored_channels() == self.channels {
+            with_channels!(self.storage_mut(), pcm => permute_channels(pcm, &order));
+        }
+
+        self.layout = self.layout.to_canonical();
+    }
+
+    /// The raw samples backing this resource.
+    ///
+    /// This is not always the same as the samples of this resource:
+    ///
+    /// * If this resource is a view created with [`DecodedAudio::slice`], then
+    ///   this is the whole storage shared with the resource it was sliced
+    ///   from, and the frames of this view are the range
+    ///   `[offset(), offset() + frames())` of each channel.
+    /// * If [`DecodedAudio::stored_channels`] is less than
+    ///   [`DecodedAudio::channels`], then the one stored channel is used for
+    ///   every channel.
+    ///
+    /// Use [`DecodedAudio::fill_channel`] to read the samples of this resource
+    /// without handling either case.
+    pub fn storage(&self) -> &DecodedAudioType {
         &self.resource_type
     }
 
+    /// The raw samples backing this resource.
+    #[deprecated(note = "use storage(); views and collapsed channels are not reflected")]
+    pub fn get(&self) -> &DecodedAudioType {
+        self.storage()
+    }
+
+    /// The first frame of this resource within the storage returned by
+    /// [`DecodedAudio::storage`]. This is `0` unless this resource is a view
+    /// created with [`DecodedAudio::slice`].
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns a view of the frames in the given range of this resource.
+    ///
+    /// This does not copy any samples, since the view shares the storage of
+    /// this resource. Editing a view (or a resource that shares its storage
+    /// with a view) copies the samples first, so views are never affected by
+    /// edits to other views.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Result<DecodedAudio, EditError> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.frames,
+        };
+
+        if start > end {
+            return Err(EditError::InvalidRange { start, end });
+        }
+        if end > self.frames {
+            return Err(EditError::FrameOutOfRange {
+                frame: end,
+                frames: self.frames,
+            });
+        }
+
+        let mut view = self.clone();
+        view.offset += start;
+        view.frames = end - start;
+
+        Ok(view)
+    }
+
+    /// The range of frames of this view within the shared storage.
+    fn view(&self) -> Range<usize> {
+        self.offset..self.offset + self.frames
+    }
+
+    /// Returns the storage of this resource for editing, first copying the
+    /// frames of this view into new storage if the storage is shared or
+    /// contains frames outside of this view.
+    fn storage_mut(&mut self) -> &mut DecodedAudioType {
+        let (_, storage_frames) = self.resource_type.dimensions();
+
+        if self.offset != 0 || self.frames != storage_frames {
+            let range = self.view();
+            let storage = map_channels!(&*self.resource_type, pcm => {
+                pcm.iter().map(|ch| ch[range.clone()].to_vec()).collect()
+            });
+
+            self.resource_type = Arc::new(storage);
+            self.offset = 0;
+        }
+
+        Arc::make_mut(&mut self.resource_type)
+    }
+
     /// Fill the buffer with samples from the given `channel`, starting from the
     /// given `frame`.
     ///
@@ -193,11 +572,14 @@ impl DecodedAudio {
     /// remaining samples were filled with zeros.)
     ///
     /// The will return an error if the given channel does not exist.
+    #[allow(clippy::result_unit_err)]
     pub fn fill_channel(&self, channel: usize, frame: usize, buf: &mut [f32]) -> Result<usize, ()> {
         if channel >= self.channels {
             return Err(());
         }
 
+        let stored = self.stored_channel(channel);
+
         if frame >= self.frames {
             // Out of range, fill with zeros instead.
             buf.fill(0.0);
@@ -214,63 +596,13 @@ impl DecodedAudio {
         };
 
         let buf_part = &mut buf[0..fill_frames];
+        let frame = self.offset + frame;
 
-        match &self.resource_type {
-            DecodedAudioType::U8(pcm) => {
-                let pcm_part = &pcm[channel][frame..frame + fill_frames];
-
-                for i in 0..fill_frames {
-                    buf_part[i] = convert::pcm_u8_to_f32(pcm_part[i]);
-                }
-            }
-            DecodedAudioType::U16(pcm) => {
-                let pcm_part = &pcm[channel][frame..frame + fill_frames];
-
-                for i in 0..fill_frames {
-                    buf_part[i] = convert::pcm_u16_to_f32(pcm_part[i]);
-                }
-            }
-            DecodedAudioType::U24(pcm) => {
-                let pcm_part = &pcm[channel][frame..frame + fill_frames];
-
-                for i in 0..fill_frames {
-                    buf_part[i] = convert::pcm_u24_to_f32_ne(pcm_part[i]);
-                }
-            }
-            DecodedAudioType::S8(pcm) => {
-                let pcm_part = &pcm[channel][frame..frame + fill_frames];
-
-                for i in 0..fill_frames {
-                    buf_part[i] = convert::pcm_i8_to_f32(pcm_part[i]);
-                }
-            }
-            DecodedAudioType::S16(pcm) => {
-                let pcm_part = &pcm[channel][frame..frame + fill_frames];
-
-                for i in 0..fill_frames {
-                    buf_part[i] = convert::pcm_i16_to_f32(pcm_part[i]);
-                }
-            }
-            DecodedAudioType::S24(pcm) => {
-                let pcm_part = &pcm[channel][frame..frame + fill_frames];
-
-                for i in 0..fill_frames {
-                    buf_part[i] = convert::pcm_i24_to_f32_ne(pcm_part[i]);
-                }
-            }
-            DecodedAudioType::F32(pcm) => {
-                let pcm_part = &pcm[channel][frame..frame + fill_frames];
-
-                buf_part.copy_from_slice(pcm_part);
-            }
-            DecodedAudioType::F64(pcm) => {
-                let pcm_part = &pcm[channel][frame..frame + fill_frames];
+        with_channels!(&*self.resource_type, pcm => {
+            let pcm_part = &pcm[stored][frame..frame + fill_frames];
 
-                for i in 0..fill_frames {
-                    buf_part[i] = pcm_part[i] as f32;
-                }
-            }
-        }
+            Sample::slice_to_f32(pcm_part, buf_part);
+        });
 
         Ok(fill_frames)
     }
@@ -289,7 +621,7 @@ impl DecodedAudio {
     pub fn fill_stereo(&self, frame: usize, buf_l: &mut [f32], buf_r: &mut [f32]) -> usize {
         let buf_len = buf_l.len().min(buf_r.len());
 
-        if self.channels == 1 {
+        if self.stored_channels() == 1 {
             let fill_frames = self.fill_channel(0, frame, buf_l).unwrap();
             buf_r.copy_from_slice(buf_l);
             return fill_frames;
@@ -314,103 +646,717 @@ impl DecodedAudio {
 
         let buf_l_part = &mut buf_l[0..fill_frames];
         let buf_r_part = &mut buf_r[0..fill_frames];
+        let frame = self.offset + frame;
 
-        match &self.resource_type {
-            DecodedAudioType::U8(pcm) => {
-                let pcm_l_part = &pcm[0][frame..frame + fill_frames];
-                let pcm_r_part = &pcm[1][frame..frame + fill_frames];
+        with_channels!(&*self.resource_type, pcm => {
+            let pcm_l_part = &pcm[0][frame..frame + fill_frames];
+            let pcm_r_part = &pcm[1][frame..frame + fill_frames];
 
-                for i in 0..fill_frames {
-                    buf_l_part[i] = convert::pcm_u8_to_f32(pcm_l_part[i]);
-                    buf_r_part[i] = convert::pcm_u8_to_f32(pcm_r_part[i]);
-                }
-            }
-            DecodedAudioType::U16(pcm) => {
-                let pcm_l_part = &pcm[0][frame..frame + fill_frames];
-                let pcm_r_part = &pcm[1][frame..frame + fill_frames];
+            Sample::slice_to_f32(pcm_l_part, buf_l_part);
+            Sample::slice_to_f32(pcm_r_part, buf_r_part);
+        });
 
-                for i in 0..fill_frames {
-                    buf_l_part[i] = convert::pcm_u16_to_f32(pcm_l_part[i]);
-                    buf_r_part[i] = convert::pcm_u16_to_f32(pcm_r_part[i]);
-                }
-            }
-            DecodedAudioType::U24(pcm) => {
-                let pcm_l_part = &pcm[0][frame..frame + fill_frames];
-                let pcm_r_part = &pcm[1][frame..frame + fill_frames];
+        fill_frames
+    }
 
-                for i in 0..fill_frames {
-                    buf_l_part[i] = convert::pcm_u24_to_f32_ne(pcm_l_part[i]);
-                    buf_r_part[i] = convert::pcm_u24_to_f32_ne(pcm_r_part[i]);
-                }
-            }
-            DecodedAudioType::S8(pcm) => {
-                let pcm_l_part = &pcm[0][frame..frame + fill_frames];
-                let pcm_r_part = &pcm[1][frame..frame + fill_frames];
+    /// Fill the buffer with samples from the given `channel` within the given
+    /// `region`, starting `offset` frames into the region.
+    ///
+    /// The fades of the region are applied while the samples are converted,
+    /// so that starting and stopping playback mid-resource does not click.
+    ///
+    /// If the buffer extends past the end of the region, then the remaining
+    /// samples will be filled with zeros.
+    ///
+    /// This returns the number of frames that were copied into the buffer. (If
+    /// this number is less than the length of `buf`, then it means that the
+    /// remaining samples were filled with zeros.)
+    ///
+    /// The will return an error if the given channel does not exist.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill_channel_region(
+        &self,
+        channel: usize,
+        region: &PlaybackRegion,
+        offset: usize,
+        buf: &mut [f32],
+    ) -> Result<usize, ()> {
+        if channel >= self.channels {
+            return Err(());
+        }
 
-                for i in 0..fill_frames {
-                    buf_l_part[i] = convert::pcm_i8_to_f32(pcm_l_part[i]);
-                    buf_r_part[i] = convert::pcm_i8_to_f32(pcm_r_part[i]);
-                }
-            }
-            DecodedAudioType::S16(pcm) => {
-                let pcm_l_part = &pcm[0][frame..frame + fill_frames];
-                let pcm_r_part = &pcm[1][frame..frame + fill_frames];
+        let stored = self.stored_channel(channel);
 
-                for i in 0..fill_frames {
-                    buf_l_part[i] = convert::pcm_i16_to_f32(pcm_l_part[i]);
-                    buf_r_part[i] = convert::pcm_i16_to_f32(pcm_r_part[i]);
-                }
-            }
-            DecodedAudioType::S24(pcm) => {
-                let pcm_l_part = &pcm[0][frame..frame + fill_frames];
-                let pcm_r_part = &pcm[1][frame..frame + fill_frames];
+        let region_frames = region.end.min(self.frames).saturating_sub(region.start);
 
-                for i in 0..fill_frames {
-                    buf_l_part[i] = convert::pcm_i24_to_f32_ne(pcm_l_part[i]);
-                    buf_r_part[i] = convert::pcm_i24_to_f32_ne(pcm_r_part[i]);
-                }
-            }
-            DecodedAudioType::F32(pcm) => {
-                let pcm_l_part = &pcm[0][frame..frame + fill_frames];
-                let pcm_r_part = &pcm[1][frame..frame + fill_frames];
+        if offset >= region_frames {
+            // Out of range, fill with zeros instead.
+            buf.fill(0.0);
+            return Ok(0);
+        }
 
-                buf_l_part.copy_from_slice(pcm_l_part);
-                buf_r_part.copy_from_slice(pcm_r_part);
-            }
-            DecodedAudioType::F64(pcm) => {
-                let pcm_l_part = &pcm[0][frame..frame + fill_frames];
-                let pcm_r_part = &pcm[1][frame..frame + fill_frames];
+        let fill_frames = if offset + buf.len() > region_frames {
+            // Fill the out-of-range part with zeros.
+            let fill_frames = region_frames - offset;
+            buf[fill_frames..].fill(0.0);
+            fill_frames
+        } else {
+            buf.len()
+        };
 
-                for i in 0..fill_frames {
-                    buf_l_part[i] = pcm_l_part[i] as f32;
-                    buf_r_part[i] = pcm_r_part[i] as f32;
-                }
-            }
-        }
+        let buf_part = &mut buf[0..fill_frames];
+        let frame = self.offset + region.start + offset;
+        let range = frame..frame + fill_frames;
+
+        with_channels!(&*self.resource_type, pcm => fade::fill_faded(
+            &pcm[stored][range],
+            buf_part,
+            offset,
+            region_frames,
+            &region.fade_in,
+            &region.fade_out,
+            Sample::to_f32,
+        ));
 
-        fill_frames
+        Ok(fill_frames)
     }
 
-    /// Consume this resource and return the raw samples.
-    pub fn into_raw(self) -> DecodedAudioType {
-        self.resource_type
-    }
-}
+    /// Fill the stereo buffer with samples within the given `region`, starting
+    /// `offset` frames into the region.
+    ///
+    /// If this resource has only one channel, then both channels will be
+    /// filled with the same data.
+    ///
+    /// See [`DecodedAudio::fill_channel_region`] for more details.
+    pub fn fill_stereo_region(
+        &self,
+        region: &PlaybackRegion,
+        offset: usize,
+        buf_l: &mut [f32],
+        buf_r: &mut [f32],
+    ) -> usize {
+        let buf_len = buf_l.len().min(buf_r.len());
+        let buf_l = &mut buf_l[0..buf_len];
+        let buf_r = &mut buf_r[0..buf_len];
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let fill_frames = self.fill_channel_region(0, region, offset, buf_l).unwrap();
 
-    #[test]
-    fn pcm_fill_range_test() {
-        let test_pcm =
-            DecodedAudio::new(DecodedAudioType::F32(vec![vec![1.0, 2.0, 3.0, 4.0]]), 44100);
+        if self.stored_channels() == 1 {
+            buf_r.copy_from_slice(buf_l);
+            fill_frames
+        } else {
+            self.fill_channel_region(1, region, offset, buf_r).unwrap()
+        }
+    }
 
-        let mut out_buf: [f32; 8] = [10.0; 8];
+    /// Fill the buffer with samples from the given `channel`, starting from the
+    /// given `frame`, converting directly from the stored format to the format
+    /// of the buffer (`f32`, `i16`, or `i32`).
+    ///
+    /// Samples stored as `i16` are copied as-is into an `i16` buffer, and are
+    /// shifted into the upper 16 bits of an `i32` buffer. All other
+    /// conversions go through `f32`, and round to the nearest value and clip.
+    /// If `dither` is `Some`, then TPDF dither is added when converting to an
+    /// integer format.
+    ///
+    /// Since the shift maps `i16::MAX` to `i16::MAX << 16` rather than
+    /// `i32::MAX`, an `i32` buffer filled from `i16` samples is about 0.003%
+    /// quieter than one filled from the same samples converted to `f32`.
+    ///
+    /// If the length of the buffer exceeds the length of the PCM resource, then
+    /// the remaining samples will be filled with zeros.
+    ///
+    /// This returns the number of frames that were copied into the buffer. (If
+    /// this number is less than the length of `buf`, then it means that the
+    /// remaining samples were filled with zeros.)
+    ///
+    /// The will return an error if the given channel does not exist.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill_channel_as<T: OutputSample>(
+        &self,
+        channel: usize,
+        frame: usize,
+        buf: &mut [T],
+        dither: Option<&mut TpdfDither>,
+    ) -> Result<usize, ()> {
+        if channel >= self.channels {
+            return Err(());
+        }
 
-        let fill_frames = test_pcm.fill_channel(0, 0, &mut out_buf[0..4]);
-        assert_eq!(fill_frames, Ok(4));
-        assert_eq!(&out_buf[0..4], &[1.0, 2.0, 3.0, 4.0]);
+        let stored = self.stored_channel(channel);
+
+        if frame >= self.frames {
+            // Out of range, fill with zeros instead.
+            buf.fill(T::EQUILIBRIUM);
+            return Ok(0);
+        }
+
+        let fill_frames = if frame + buf.len() > self.frames {
+            // Fill the out-of-range part with zeros.
+            let fill_frames = self.frames - frame;
+            buf[fill_frames..].fill(T::EQUILIBRIUM);
+            fill_frames
+        } else {
+            buf.len()
+        };
+
+        let buf_part = &mut buf[0..fill_frames];
+        let range = self.offset + frame..self.offset + frame + fill_frames;
+
+        match &*self.resource_type {
+            DecodedAudioType::S16(pcm) => {
+                // Converting from `i16` never needs dither.
+                for (out_s, &s) in buf_part.iter_mut().zip(&pcm[stored][range]) {
+                    *out_s = T::from_i16(s);
+                }
+            }
+            resource_type => with_channels!(resource_type, pcm => {
+                convert_into(&pcm[stored][range], buf_part, dither, Sample::to_f32)
+            }),
+        }
+
+        Ok(fill_frames)
+    }
+
+    /// Fill the stereo buffer with samples, starting from the given `frame`,
+    /// converting directly from the stored format to the format of the buffer
+    /// (`f32`, `i16`, or `i32`).
+    ///
+    /// If this resource has only one channel, then both channels will be
+    /// filled with the same data.
+    ///
+    /// See [`DecodedAudio::fill_channel_as`] for more details.
+    pub fn fill_stereo_as<T: OutputSample>(
+        &self,
+        frame: usize,
+        buf_l: &mut [T],
+        buf_r: &mut [T],
+        mut dither: Option<&mut TpdfDither>,
+    ) -> usize {
+        let buf_len = buf_l.len().min(buf_r.len());
+        let buf_l = &mut buf_l[0..buf_len];
+        let buf_r = &mut buf_r[0..buf_len];
+
+        let fill_frames = self
+            .fill_channel_as(0, frame, buf_l, dither.as_deref_mut())
+            .unwrap();
+
+        if self.stored_channels() == 1 {
+            buf_r.copy_from_slice(buf_l);
+            fill_frames
+        } else {
+            self.fill_channel_as(1, frame, buf_r, dither).unwrap()
+        }
+    }
+
+    /// Add the samples from the given `channel`, starting from the given
+    /// `frame`, to the buffer.
+    ///
+    /// Unlike [`DecodedAudio::fill_channel`], this does not overwrite the
+    /// buffer, so many resources can be summed into the same buffer. The
+    /// `gain` ramps linearly across the whole length of the buffer.
+    ///
+    /// If the length of the buffer exceeds the length of the PCM resource, then
+    /// the remaining samples are left untouched.
+    ///
+    /// This returns the number of frames that were added to the buffer.
+    ///
+    /// The will return an error if the given channel does not exist.
+    #[allow(clippy::result_unit_err)]
+    pub fn mix_channel(
+        &self,
+        channel: usize,
+        frame: usize,
+        gain: impl Into<GainRamp>,
+        buf: &mut [f32],
+    ) -> Result<usize, ()> {
+        if channel >= self.channels {
+            return Err(());
+        }
+
+        let (gain, gain_step) = gain.into().start_and_step(buf.len());
+
+        Ok(self.add_channel_to(channel, frame, buf, gain, gain_step))
+    }
+
+    /// Add the samples, starting from the given `frame`, to the stereo buffer.
+    ///
+    /// The `gain` ramps linearly across the whole length of the buffer, and
+    /// `pan` (in the range `[-1.0, 1.0]`) is turned into the gains of the left
+    /// and right channels with the given `pan_law`. If this resource has only
+    /// one channel, then it is panned between the two channels. Otherwise the
+    /// pan gains are applied to the first two channels as a balance control.
+    ///
+    /// If the length of the buffer exceeds the length of the PCM resource, then
+    /// the remaining samples are left untouched.
+    ///
+    /// This returns the number of frames that were added to the buffer.
+    pub fn mix_stereo(
+        &self,
+        frame: usize,
+        gain: impl Into<GainRamp>,
+        pan: f32,
+        pan_law: PanLaw,
+        buf_l: &mut [f32],
+        buf_r: &mut [f32],
+    ) -> usize {
+        let buf_len = buf_l.len().min(buf_r.len());
+
+        let (gain, gain_step) = gain.into().start_and_step(buf_len);
+        let (pan_l, pan_r) = pan_law.gains(pan);
+
+        let src_r = if self.stored_channels() == 1 { 0 } else { 1 };
+
+        self.add_channel_to(
+            0,
+            frame,
+            &mut buf_l[0..buf_len],
+            gain * pan_l,
+            gain_step * pan_l,
+        );
+        self.add_channel_to(
+            src_r,
+            frame,
+            &mut buf_r[0..buf_len],
+            gain * pan_r,
+            gain_step * pan_r,
+        )
+    }
+
+    fn add_channel_to(
+        &self,
+        channel: usize,
+        frame: usize,
+        buf: &mut [f32],
+        gain: f32,
+        gain_step: f32,
+    ) -> usize {
+        let stored = self.stored_channel(channel);
+
+        if frame >= self.frames {
+            return 0;
+        }
+
+        let fill_frames = (self.frames - frame).min(buf.len());
+        let buf_part = &mut buf[0..fill_frames];
+        let range = self.offset + frame..self.offset + frame + fill_frames;
+
+        with_channels!(&*self.resource_type, pcm => mix::add_ramped(
+            &pcm[stored][range],
+            buf_part,
+            gain,
+            gain_step,
+            Sample::to_f32,
+        ));
+
+        fill_frames
+    }
+
+    /// Fill the buffer with samples from the given `channel`, reading backwards
+    /// starting from the given `frame`.
+    ///
+    /// The first sample in the buffer is `frame`, the second is `frame - 1`,
+    /// and so on. If the buffer extends past the start of the PCM resource,
+    /// then the remaining samples will be filled with zeros.
+    ///
+    /// This returns the number of frames that were copied into the buffer. (If
+    /// this number is less than the length of `buf`, then it means that the
+    /// remaining samples were filled with zeros.)
+    ///
+    /// The will return an error if the given channel does not exist.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill_channel_reverse(
+        &self,
+        channel: usize,
+        frame: usize,
+        buf: &mut [f32],
+    ) -> Result<usize, ()> {
+        if channel >= self.channels {
+            return Err(());
+        }
+
+        let stored = self.stored_channel(channel);
+
+        if frame >= self.frames {
+            // Out of range, fill with zeros instead.
+            buf.fill(0.0);
+            return Ok(0);
+        }
+
+        let fill_frames = if frame + 1 < buf.len() {
+            // Fill the out-of-range part with zeros.
+            let fill_frames = frame + 1;
+            buf[fill_frames..].fill(0.0);
+            fill_frames
+        } else {
+            buf.len()
+        };
+
+        let buf_part = &mut buf[0..fill_frames];
+        let frame = self.offset + frame;
+        let range = frame + 1 - fill_frames..frame + 1;
+
+        with_channels!(&*self.resource_type, pcm => {
+            copy_reversed(&pcm[stored][range], buf_part, Sample::to_f32)
+        });
+
+        Ok(fill_frames)
+    }
+
+    /// Fill the stereo buffer with samples, reading backwards starting from
+    /// the given `frame`.
+    ///
+    /// If this resource has only one channel, then both channels will be
+    /// filled with the same data.
+    ///
+    /// If the buffer extends past the start of the PCM resource, then the
+    /// remaining samples will be filled with zeros.
+    ///
+    /// This returns the number of frames that were copied into the buffer. (If
+    /// this number is less than the length of `buf`, then it means that the
+    /// remaining samples were filled with zeros.)
+    pub fn fill_stereo_reverse(&self, frame: usize, buf_l: &mut [f32], buf_r: &mut [f32]) -> usize {
+        let buf_len = buf_l.len().min(buf_r.len());
+        let buf_l = &mut buf_l[0..buf_len];
+        let buf_r = &mut buf_r[0..buf_len];
+
+        let fill_frames = self.fill_channel_reverse(0, frame, buf_l).unwrap();
+
+        if self.stored_channels() == 1 {
+            buf_r.copy_from_slice(buf_l);
+            fill_frames
+        } else {
+            self.fill_channel_reverse(1, frame, buf_r).unwrap()
+        }
+    }
+
+    /// Fill the buffer with samples from the given `channel`, reading at a
+    /// fractional `position` (in frames) and advancing by `step` frames for
+    /// each sample in the buffer.
+    ///
+    /// A `step` of `1.0` plays the resource at its original speed, `2.0` plays
+    /// it an octave higher, and `0.5` an octave lower. A negative `step` reads
+    /// backwards.
+    ///
+    /// This does not allocate, so it is safe to call on the realtime thread.
+    ///
+    /// If the read position leaves the range of the PCM resource, then the
+    /// remaining samples will be filled with zeros.
+    ///
+    /// This returns the number of frames that were read into the buffer. (If
+    /// this number is less than the length of `buf`, then it means that the
+    /// remaining samples were filled with zeros.) The read position of the
+    /// next call can be computed as `position + step * buf.len() as f64`.
+    ///
+    /// The will return an error if the given channel does not exist.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill_channel_interpolated(
+        &self,
+        channel: usize,
+        position: f64,
+        step: f64,
+        interpolation: Interpolation,
+        buf: &mut [f32],
+    ) -> Result<usize, ()> {
+        if channel >= self.channels {
+            return Err(());
+        }
+
+        let stored = self.stored_channel(channel);
+
+        let fill_frames = with_channels!(&*self.resource_type, pcm => interpolate::fill_interpolated(
+            &pcm[stored][self.view()],
+            position,
+            step,
+            interpolation,
+            buf,
+            Sample::to_f32,
+        ));
+
+        Ok(fill_frames)
+    }
+
+    /// Fill the stereo buffer with samples, reading at a fractional `position`
+    /// (in frames) and advancing by `step` frames for each sample in the
+    /// buffer.
+    ///
+    /// If this resource has only one channel, then both channels will be
+    /// filled with the same data.
+    ///
+    /// See [`DecodedAudio::fill_channel_interpolated`] for more details.
+    pub fn fill_stereo_interpolated(
+        &self,
+        position: f64,
+        step: f64,
+        interpolation: Interpolation,
+        buf_l: &mut [f32],
+        buf_r: &mut [f32],
+    ) -> usize {
+        let buf_len = buf_l.len().min(buf_r.len());
+        let buf_l = &mut buf_l[0..buf_len];
+        let buf_r = &mut buf_r[0..buf_len];
+
+        let fill_frames = self
+            .fill_channel_interpolated(0, position, step, interpolation, buf_l)
+            .unwrap();
+
+        if self.stored_channels() == 1 {
+            buf_r.copy_from_slice(buf_l);
+            fill_frames
+        } else {
+            self.fill_channel_interpolated(1, position, step, interpolation, buf_r)
+                .unwrap()
+        }
+    }
+
+    /// Fill the buffer with samples from the given `channel`, starting from the
+    /// given `playhead` and looping over the given `region` instead of running
+    /// off the end of the resource.
+    ///
+    /// This does not allocate, so it is safe to call on the realtime thread.
+    ///
+    /// This returns the playhead after the last sample in the buffer, which
+    /// should be passed to the next call.
+    ///
+    /// This will return an error if the given channel does not exist, or if
+    /// the region is empty once its end is clamped to the length of the
+    /// resource.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill_channel_looped(
+        &self,
+        channel: usize,
+        playhead: Playhead,
+        region: &LoopRegion,
+        buf: &mut [f32],
+    ) -> Result<Playhead, ()> {
+        if channel >= self.channels {
+            return Err(());
+        }
+
+        let stored = self.stored_channel(channel);
+
+        let region = LoopRegion {
+            end: region.end.min(self.frames),
+            ..*region
+        };
+        if region.start >= region.end {
+            return Err(());
+        }
+
+        let playhead = with_channels!(&*self.resource_type, pcm => looping::fill_looped(
+            &pcm[stored][self.view()],
+            playhead,
+            &region,
+            buf,
+            Sample::to_f32,
+        ));
+
+        Ok(playhead)
+    }
+
+    /// Fill the stereo buffer with samples, starting from the given `playhead`
+    /// and looping over the given `region`.
+    ///
+    /// If this resource has only one channel, then both channels will be
+    /// filled with the same data.
+    ///
+    /// See [`DecodedAudio::fill_channel_looped`] for more details.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill_stereo_looped(
+        &self,
+        playhead: Playhead,
+        region: &LoopRegion,
+        buf_l: &mut [f32],
+        buf_r: &mut [f32],
+    ) -> Result<Playhead, ()> {
+        let buf_len = buf_l.len().min(buf_r.len());
+        let buf_l = &mut buf_l[0..buf_len];
+        let buf_r = &mut buf_r[0..buf_len];
+
+        let new_playhead = self.fill_channel_looped(0, playhead, region, buf_l)?;
+
+        if self.stored_channels() == 1 {
+            buf_r.copy_from_slice(buf_l);
+            Ok(new_playhead)
+        } else {
+            self.fill_channel_looped(1, playhead, region, buf_r)
+        }
+    }
+
+    /// Fill the buffers with the channels of this resource mixed together
+    /// using the given matrix, starting from the given `frame`.
+    ///
+    /// A matrix from this resource's layout to a desired layout can be
+    /// constructed with [`MixMatrix::new`]. This should be done outside of
+    /// the realtime thread since it allocates.
+    ///
+    /// If the length of the buffers exceeds the length of the PCM resource,
+    /// then the remaining samples will be filled with zeros.
+    ///
+    /// This returns the number of frames that were copied into the buffers.
+    /// (If this number is less than the length of the buffers, then it means
+    /// that the remaining samples were filled with zeros.)
+    ///
+    /// This will return an error if the matrix does not have the same number
+    /// of input channels as this resource, or if there are fewer buffers
+    /// than output channels in the matrix.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill_mixed(
+        &self,
+        frame: usize,
+        matrix: &MixMatrix,
+        bufs: &mut [&mut [f32]],
+    ) -> Result<usize, ()> {
+        if matrix.in_channels() != self.channels || bufs.len() < matrix.out_channels() {
+            return Err(());
+        }
+
+        let buf_len = bufs
+            .iter()
+            .take(matrix.out_channels())
+            .map(|b| b.len())
+            .min()
+            .unwrap_or(0);
+
+        let fill_frames = if frame >= self.frames {
+            0
+        } else {
+            (self.frames - frame).min(buf_len)
+        };
+
+        for buf in bufs.iter_mut().take(matrix.out_channels()) {
+            // Fill the out-of-range part with zeros.
+            buf[fill_frames..].fill(0.0);
+        }
+
+        if fill_frames == 0 {
+            return Ok(0);
+        }
+
+        let range = self.offset + frame..self.offset + frame + fill_frames;
+
+        with_channels!(&*self.resource_type, pcm => mix_into(pcm, range, matrix, bufs, Sample::to_f32));
+
+        Ok(fill_frames)
+    }
+
+    /// Consume this resource and return the raw samples, with one channel for
+    /// each of the [`DecodedAudio::channels`].
+    ///
+    /// This copies the samples if the storage is shared with another resource
+    /// or view, or if the channels were collapsed into one.
+    pub fn into_raw(mut self) -> DecodedAudioType {
+        self.expand_channels();
+        self.into_storage()
+    }
+
+    /// Consume this resource and return the stored samples.
+    fn into_storage(mut self) -> DecodedAudioType {
+        self.storage_mut();
+
+        Arc::try_unwrap(self.resource_type).unwrap_or_else(|storage| (*storage).clone())
+    }
+}
+
+/// Reorder the channels so that channel `i` is the channel previously at
+/// index `order[i]`.
+fn permute_channels<T>(channels: &mut Vec<Vec<T>>, order: &[usize]) {
+    let mut old: Vec<Option<Vec<T>>> = std::mem::take(channels).into_iter().map(Some).collect();
+
+    *channels = order.iter().map(|&i| old[i].take().unwrap()).collect();
+}
+
+fn convert_into<S: Copy, T: OutputSample>(
+    pcm: &[S],
+    buf: &mut [T],
+    dither: Option<&mut TpdfDither>,
+    to_f32: impl Fn(S) -> f32,
+) {
+    match dither {
+        Some(dither) if T::IS_INTEGER => {
+            for (out_s, &s) in buf.iter_mut().zip(pcm) {
+                *out_s = T::from_f32(to_f32(s), dither.next_lsb());
+            }
+        }
+        _ => {
+            for (out_s, &s) in buf.iter_mut().zip(pcm) {
+                *out_s = T::from_f32(to_f32(s), 0.0);
+            }
+        }
+    }
+}
+
+/// Whether every channel is within `tolerance` of the first channel in the
+/// given range.
+fn channels_match<S: Sample>(pcm: &[Vec<S>], range: Range<usize>, tolerance: f64) -> bool {
+    let first = &pcm[0][range.clone()];
+
+    pcm[1..].iter().all(|ch| {
+        ch[range.clone()]
+            .iter()
+            .zip(first)
+            .all(|(&a, &b)| (a.to_f64() - b.to_f64()).abs() <= tolerance)
+    })
+}
+
+fn copy_reversed<T: Copy>(pcm: &[T], buf: &mut [f32], to_f32: impl Fn(T) -> f32) {
+    for (out_s, &s) in buf.iter_mut().zip(pcm.iter().rev()) {
+        *out_s = to_f32(s);
+    }
+}
+
+fn mix_into<T: Copy>(
+    pcm: &[Vec<T>],
+    range: std::ops::Range<usize>,
+    matrix: &MixMatrix,
+    bufs: &mut [&mut [f32]],
+    to_f32: impl Fn(T) -> f32,
+) {
+    let fill_frames = range.len();
+
+    for (out_ch, buf) in bufs.iter_mut().take(matrix.out_channels()).enumerate() {
+        let buf_part = &mut buf[0..fill_frames];
+        buf_part.fill(0.0);
+
+        // A resource with collapsed channels stores one channel for all of
+        // them, so it gets the sum of their gains.
+        let row = matrix.row(out_ch);
+        let collapsed_gain = [row.iter().sum::<f32>()];
+        let gains = if pcm.len() == 1 {
+            &collapsed_gain[..]
+        } else {
+            row
+        };
+
+        for (pcm_ch, &gain) in pcm.iter().zip(gains) {
+            if gain == 0.0 {
+                continue;
+            }
+
+            for (out_s, &s) in buf_part.iter_mut().zip(&pcm_ch[range.clone()]) {
+                *out_s += to_f32(s) * gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::ChannelPosition;
+    use crate::fade::{Fade, FadeCurve};
+    use crate::looping::LoopMode;
+
+    #[test]
+    fn pcm_fill_range_test() {
+        let test_pcm =
+            DecodedAudio::new(DecodedAudioType::F32(vec![vec![1.0, 2.0, 3.0, 4.0]]), 44100);
+
+        let mut out_buf: [f32; 8] = [10.0; 8];
+
+        let fill_frames = test_pcm.fill_channel(0, 0, &mut out_buf[0..4]);
+        assert_eq!(fill_frames, Ok(4));
+        assert_eq!(&out_buf[0..4], &[1.0, 2.0, 3.0, 4.0]);
 
         out_buf = [10.0; 8];
         let fill_frames = test_pcm.fill_channel(0, 0, &mut out_buf[0..5]);
@@ -442,4 +1388,409 @@ mod tests {
         assert_eq!(fill_frames, Ok(3));
         assert_eq!(&out_buf[0..4], &[2.0, 3.0, 4.0, 0.0]);
     }
+
+    #[test]
+    fn pcm_slice_test() {
+        let test_pcm = DecodedAudio::new(
+            DecodedAudioType::U8(vec![vec![0, 1, 2, 3, 4, 5, 6, 7]]),
+            44100,
+        );
+        let s = |i: u8| i.to_f32();
+
+        let slice = test_pcm.slice(2..5).unwrap();
+        assert_eq!(slice.frames(), 3);
+        assert_eq!(slice.offset(), 2);
+        assert!(Arc::ptr_eq(&slice.resource_type, &test_pcm.resource_type));
+
+        let mut out_buf: [f32; 4] = [10.0; 4];
+        let fill_frames = slice.fill_channel(0, 1, &mut out_buf);
+        assert_eq!(fill_frames, Ok(2));
+        assert_eq!(out_buf, [s(3), s(4), 0.0, 0.0]);
+
+        let fill_frames = slice.fill_channel_reverse(0, 2, &mut out_buf);
+        assert_eq!(fill_frames, Ok(3));
+        assert_eq!(out_buf, [s(4), s(3), s(2), 0.0]);
+
+        // Slicing a view is relative to the view.
+        let mut slice = slice.slice(1..).unwrap();
+        assert_eq!(slice.offset(), 3);
+        assert_eq!(
+            slice.slice(0..3).err(),
+            Some(EditError::FrameOutOfRange {
+                frame: 3,
+                frames: 2
+            })
+        );
+
+        // Editing a view copies its frames instead of changing the shared storage.
+        slice.reverse();
+        assert_eq!(slice.offset(), 0);
+        match slice.into_raw() {
+            DecodedAudioType::U8(pcm) => assert_eq!(pcm, vec![vec![4, 3]]),
+            _ => unreachable!(),
+        }
+        match test_pcm.storage() {
+            DecodedAudioType::U8(pcm) => assert_eq!(pcm[0], vec![0, 1, 2, 3, 4, 5, 6, 7]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn pcm_fill_region_test() {
+        let test_pcm = DecodedAudio::new(DecodedAudioType::F32(vec![vec![1.0; 8]]), 44100);
+
+        let region = PlaybackRegion {
+            fade_in: Fade::new(2, FadeCurve::Linear),
+            fade_out: Fade::new(4, FadeCurve::Linear),
+            ..PlaybackRegion::new(1, 7)
+        };
+
+        let mut out_buf: [f32; 8] = [10.0; 8];
+        let fill_frames = test_pcm.fill_channel_region(0, &region, 0, &mut out_buf);
+        assert_eq!(fill_frames, Ok(6));
+        assert_eq!(out_buf, [0.0, 0.5, 0.75, 0.5, 0.25, 0.0, 0.0, 0.0]);
+
+        out_buf = [10.0; 8];
+        let fill_frames = test_pcm.fill_channel_region(0, &region, 4, &mut out_buf[0..4]);
+        assert_eq!(fill_frames, Ok(2));
+        assert_eq!(&out_buf[0..4], &[0.25, 0.0, 0.0, 0.0]);
+
+        assert_eq!(FadeCurve::EqualPower.gain(1.0), 1.0);
+        assert_eq!(FadeCurve::SCurve.gain(0.5), 0.5);
+    }
+
+    #[test]
+    fn pcm_fill_as_test() {
+        let test_pcm = DecodedAudio::new(
+            DecodedAudioType::S16(vec![vec![i16::MIN, -1, 0, 1, i16::MAX]]),
+            44100,
+        );
+
+        let mut out_buf: [i16; 6] = [10; 6];
+        let fill_frames = test_pcm.fill_channel_as(0, 0, &mut out_buf, None);
+        assert_eq!(fill_frames, Ok(5));
+        assert_eq!(out_buf, [i16::MIN, -1, 0, 1, i16::MAX, 0]);
+
+        let mut out_buf: [i32; 2] = [10; 2];
+        let fill_frames = test_pcm.fill_channel_as(0, 3, &mut out_buf, None);
+        assert_eq!(fill_frames, Ok(2));
+        assert_eq!(out_buf, [65536, (i16::MAX as i32) << 16]);
+
+        let test_pcm = DecodedAudio::new(DecodedAudioType::F32(vec![vec![-2.0, 0.5, 2.0]]), 44100);
+
+        let mut out_buf: [i16; 3] = [10; 3];
+        test_pcm.fill_channel_as(0, 0, &mut out_buf, None).unwrap();
+        assert_eq!(out_buf, [i16::MIN, 16384, i16::MAX]);
+
+        // Dither never moves the sample by more than one step.
+        let mut dither = TpdfDither::default();
+        for _ in 0..100 {
+            test_pcm
+                .fill_channel_as(0, 0, &mut out_buf, Some(&mut dither))
+                .unwrap();
+            assert!((16383..=16385).contains(&out_buf[1]));
+        }
+    }
+
+    #[test]
+    fn pcm_mix_test() {
+        let test_pcm = DecodedAudio::new(DecodedAudioType::F32(vec![vec![1.0, 1.0, 1.0]]), 44100);
+
+        let mut out_buf: [f32; 4] = [1.0; 4];
+        let fill_frames = test_pcm.mix_channel(0, 0, GainRamp::new(0.0, 1.0), &mut out_buf);
+        assert_eq!(fill_frames, Ok(3));
+        assert_eq!(out_buf, [1.0, 1.25, 1.5, 1.0]);
+
+        let mut out_buf_l: [f32; 4] = [0.0; 4];
+        let mut out_buf_r: [f32; 4] = [0.0; 4];
+        let fill_frames =
+            test_pcm.mix_stereo(1, 0.5, 1.0, PanLaw::Linear, &mut out_buf_l, &mut out_buf_r);
+        assert_eq!(fill_frames, 2);
+        assert_eq!(out_buf_l, [0.0; 4]);
+        assert_eq!(out_buf_r, [0.5, 0.5, 0.0, 0.0]);
+
+        let (l, r) = PanLaw::ConstantPower.gains(0.0);
+        assert!((l * l + r * r - 1.0).abs() < 1e-6);
+        assert_eq!(PanLaw::Balance.gains(-0.5), (1.0, 0.5));
+
+        assert_eq!(test_pcm.mix_channel(1, 0, 1.0, &mut out_buf), Err(()));
+    }
+
+    #[test]
+    fn pcm_fill_reverse_test() {
+        let test_pcm = DecodedAudio::new(DecodedAudioType::S16(vec![vec![1, 2, 3, 4]]), 44100);
+        let s = |i: i16| i.to_f32();
+
+        let mut out_buf: [f32; 8] = [10.0; 8];
+        let fill_frames = test_pcm.fill_channel_reverse(0, 3, &mut out_buf[0..4]);
+        assert_eq!(fill_frames, Ok(4));
+        assert_eq!(&out_buf[0..4], &[4, 3, 2, 1].map(s));
+
+        out_buf = [10.0; 8];
+        let fill_frames = test_pcm.fill_channel_reverse(0, 1, &mut out_buf[0..4]);
+        assert_eq!(fill_frames, Ok(2));
+        assert_eq!(&out_buf[0..4], &[2, 1, 0, 0].map(s));
+
+        out_buf = [10.0; 8];
+        let fill_frames = test_pcm.fill_channel_reverse(0, 2, &mut out_buf[0..2]);
+        assert_eq!(fill_frames, Ok(2));
+        assert_eq!(&out_buf[0..2], &[3, 2].map(s));
+
+        out_buf = [10.0; 8];
+        let fill_frames = test_pcm.fill_channel_reverse(0, 4, &mut out_buf[0..4]);
+        assert_eq!(fill_frames, Ok(0));
+        assert_eq!(&out_buf[0..4], &[0.0, 0.0, 0.0, 0.0]);
+
+        let mut out_buf_r: [f32; 8] = [10.0; 8];
+        let fill_frames = test_pcm.fill_stereo_reverse(0, &mut out_buf, &mut out_buf_r);
+        assert_eq!(fill_frames, 1);
+        assert_eq!(out_buf, [1, 0, 0, 0, 0, 0, 0, 0].map(s));
+        assert_eq!(out_buf, out_buf_r);
+    }
+
+    #[test]
+    fn pcm_fill_interpolated_test() {
+        let test_pcm =
+            DecodedAudio::new(DecodedAudioType::F64(vec![vec![0.0, 1.0, 2.0, 3.0]]), 44100);
+
+        let mut out_buf: [f32; 8] = [10.0; 8];
+        let fill_frames =
+            test_pcm.fill_channel_interpolated(0, 0.5, 0.5, Interpolation::Linear, &mut out_buf);
+        assert_eq!(fill_frames, Ok(7));
+        assert_eq!(out_buf, [0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 1.5, 0.0]);
+
+        // Cubic interpolation reproduces a straight line away from the edges.
+        out_buf = [10.0; 8];
+        let fill_frames = test_pcm.fill_channel_interpolated(
+            0,
+            1.25,
+            0.25,
+            Interpolation::CubicHermite,
+            &mut out_buf[0..4],
+        );
+        assert_eq!(fill_frames, Ok(4));
+        assert_eq!(&out_buf[0..4], &[1.25, 1.5, 1.75, 2.0]);
+
+        // Sinc interpolation at whole frames reproduces the original samples.
+        out_buf = [10.0; 8];
+        let fill_frames =
+            test_pcm.fill_channel_interpolated(0, 3.0, -1.0, Interpolation::Sinc, &mut out_buf);
+        assert_eq!(fill_frames, Ok(4));
+        for (out_s, expected) in out_buf.iter().zip([3.0, 2.0, 1.0, 0.0, 0.0]) {
+            assert!((out_s - expected).abs() < 1e-6);
+        }
+
+        out_buf = [10.0; 8];
+        let fill_frames =
+            test_pcm.fill_channel_interpolated(0, 4.0, 1.0, Interpolation::Linear, &mut out_buf);
+        assert_eq!(fill_frames, Ok(0));
+        assert_eq!(out_buf, [0.0; 8]);
+
+        assert_eq!(
+            test_pcm.fill_channel_interpolated(1, 0.0, 1.0, Interpolation::Linear, &mut out_buf),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn pcm_fill_looped_test() {
+        let test_pcm = DecodedAudio::new(
+            DecodedAudioType::S8(vec![vec![0, 16, 32, 48, 64, 80]]),
+            44100,
+        );
+        let s = |i: i8| i.to_f32();
+
+        let mut out_buf: [f32; 8] = [10.0; 8];
+        let region = LoopRegion::new(2, 5, LoopMode::Forward);
+        let playhead = test_pcm.fill_channel_looped(0, Playhead::new(0), &region, &mut out_buf);
+        assert_eq!(playhead, Ok(Playhead::new(2)));
+        assert_eq!(out_buf, [0, 16, 32, 48, 64, 32, 48, 64].map(s),);
+
+        out_buf = [10.0; 8];
+        let region = LoopRegion::new(2, 100, LoopMode::PingPong);
+        let playhead = test_pcm.fill_channel_looped(0, Playhead::new(3), &region, &mut out_buf);
+        assert_eq!(
+            playhead,
+            Ok(Playhead {
+                frame: 5,
+                reverse: false
+            })
+        );
+        assert_eq!(out_buf, [48, 64, 80, 64, 48, 32, 48, 64].map(s));
+
+        out_buf = [10.0; 8];
+        let region = LoopRegion::new(2, 5, LoopMode::OneShot);
+        let playhead = test_pcm.fill_channel_looped(0, Playhead::new(1), &region, &mut out_buf);
+        assert_eq!(playhead, Ok(Playhead::new(5)));
+        assert_eq!(out_buf, [16, 32, 48, 64, 0, 0, 0, 0].map(s));
+
+        // The last two frames of the loop fade into the two frames before its start.
+        out_buf = [10.0; 8];
+        let region = LoopRegion {
+            crossfade: 2,
+            ..LoopRegion::new(2, 6, LoopMode::Forward)
+        };
+        test_pcm
+            .fill_channel_looped(0, Playhead::new(4), &region, &mut out_buf[0..3])
+            .unwrap();
+        let expected = [
+            s(64) * 0.75 + s(0) * 0.25,
+            s(80) * 0.25 + s(16) * 0.75,
+            s(32),
+        ];
+        for (out_s, expected) in out_buf.iter().zip(expected) {
+            assert!((out_s - expected).abs() < 1e-6);
+        }
+
+        let region = LoopRegion::new(6, 8, LoopMode::Forward);
+        assert_eq!(
+            test_pcm.fill_channel_looped(0, Playhead::new(0), &region, &mut out_buf),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn pcm_fill_mixed_test() {
+        let test_pcm = DecodedAudio::new(
+            DecodedAudioType::S16(vec![vec![i16::MAX; 3], vec![0; 3], vec![i16::MAX; 3]]),
+            44100,
+        );
+        // Treat the third channel as the center channel.
+        let from = ChannelLayout::new(vec![
+            ChannelPosition::FrontLeft,
+            ChannelPosition::FrontRight,
+            ChannelPosition::FrontCenter,
+        ]);
+        let matrix = MixMatrix::new(&from, &ChannelLayout::stereo());
+
+        let mut buf_l = [10.0; 4];
+        let mut buf_r = [10.0; 4];
+        let fill_frames = test_pcm.fill_mixed(1, &matrix, &mut [&mut buf_l, &mut buf_r]);
+        assert_eq!(fill_frames, Ok(2));
+
+        let c = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(buf_l, [1.0 + c, 1.0 + c, 0.0, 0.0]);
+        assert_eq!(buf_r, [c, c, 0.0, 0.0]);
+
+        let mono = MixMatrix::new(&ChannelLayout::mono(), &ChannelLayout::stereo());
+        assert_eq!(
+            test_pcm.fill_mixed(0, &mono, &mut [&mut buf_l, &mut buf_r]),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn collapse_identical_channels() {
+        let mut test_pcm = DecodedAudio::new(
+            DecodedAudioType::S16(vec![vec![0, 1, 2, 3], vec![0, 1, 2, 4]]),
+            44100,
+        );
+
+        test_pcm.collapse_identical_channels(0.0);
+        assert_eq!(test_pcm.stored_channels(), 2);
+
+        test_pcm.collapse_identical_channels(1.5 / f64::from(i16::MAX));
+        assert_eq!(test_pcm.channels(), 2);
+        assert_eq!(test_pcm.stored_channels(), 1);
+
+        let mut buf_l = [0.0; 4];
+        let mut buf_r = [0.0; 4];
+        assert_eq!(test_pcm.fill_stereo(0, &mut buf_l, &mut buf_r), 4);
+        assert_eq!(buf_l, buf_r);
+        assert_eq!(test_pcm.fill_channel(1, 0, &mut buf_r), Ok(4));
+        assert_eq!(buf_l, buf_r);
+        assert_eq!(test_pcm.fill_channel(2, 0, &mut buf_r), Err(()));
+
+        let matrix = MixMatrix::new(&ChannelLayout::stereo(), &ChannelLayout::mono());
+        let mut buf = [0.0; 4];
+        assert_eq!(test_pcm.fill_mixed(0, &matrix, &mut [&mut buf]), Ok(4));
+        for (&m, &s) in buf.iter().zip(&buf_l) {
+            assert!((m - s * std::f32::consts::SQRT_2).abs() < 1e-6);
+        }
+
+        // Appending a resource that is not collapsed stores every channel again.
+        let other = DecodedAudio::new(DecodedAudioType::S16(vec![vec![5], vec![6]]), 44100);
+        test_pcm.append(other).unwrap();
+        assert_eq!(test_pcm.stored_channels(), 2);
+        match test_pcm.into_raw() {
+            DecodedAudioType::S16(pcm) => {
+                assert_eq!(pcm, vec![vec![0, 1, 2, 3, 5], vec![0, 1, 2, 3, 6]])
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn visit_and_map() {
+        struct Sum;
+
+        impl ChannelVisitor for Sum {
+            type Output = f32;
+
+            fn visit<T: Sample>(self, channels: &[Vec<T>]) -> f32 {
+                channels.iter().flatten().map(|s| s.to_f32()).sum()
+            }
+        }
+
+        struct Invert;
+
+        impl ChannelMapper for Invert {
+            fn map<T: Sample>(self, channels: &[Vec<T>]) -> Vec<Vec<T>> {
+                channels
+                    .iter()
+                    .map(|ch| ch.iter().map(|s| T::from_f32(-s.to_f32())).collect())
+                    .collect()
+            }
+        }
+
+        let pcm = DecodedAudioType::U8(vec![vec![0, 128, 255]]);
+        assert_eq!(pcm.visit(Sum), 128u8.to_f32());
+
+        let inverted = pcm.map(Invert);
+        assert_eq!(inverted.format(), SampleFormat::U8);
+        match inverted {
+            DecodedAudioType::U8(pcm) => assert_eq!(pcm[0], vec![255, 127, 0]),
+            _ => unreachable!(),
+        }
+
+        let silence = vec![vec![I24::EQUILIBRIUM; 4]];
+        let pcm = DecodedAudioType::S24(silence);
+        assert_eq!(pcm.visit(Sum), 0.0);
+        assert_eq!(U24::EQUILIBRIUM.to_u32(), 1 << 23);
+        assert_eq!(I24::from_f32(-1.0).to_i32(), -8_388_607);
+        assert_eq!(<[u8; 3]>::from(I24::from([1, 2, 3])), [1, 2, 3]);
+        assert_eq!(
+            U24::from(<[u8; 3]>::from(U24::EQUILIBRIUM)),
+            U24::EQUILIBRIUM
+        );
+    }
+
+    #[cfg(feature = "f16")]
+    #[test]
+    fn f16_storage() {
+        use half::f16;
+
+        let test_pcm = DecodedAudio::new(
+            DecodedAudioType::F32(vec![
+                vec![0.0, 0.5, -0.25, 1.0, 0.1],
+                vec![-1.0, 0.0, 0.75, 0.5, -0.1],
+            ]),
+            44100,
+        );
+
+        let half = test_pcm.convert_to(SampleFormat::F16, &Default::default());
+        assert_eq!(half.format(), SampleFormat::F16);
+        match half.storage() {
+            DecodedAudioType::F16(pcm) => assert_eq!(pcm[0][1], f16::from_f32(0.5)),
+            _ => unreachable!(),
+        }
+
+        let mut buf_l = [0.0; 6];
+        let mut buf_r = [0.0; 6];
+        assert_eq!(half.fill_stereo(0, &mut buf_l, &mut buf_r), 5);
+        assert_eq!(buf_l[..4], [0.0, 0.5, -0.25, 1.0]);
+        assert_eq!(buf_r[..4], [-1.0, 0.0, 0.75, 0.5]);
+        assert_eq!(buf_l[4], f16::from_f32(0.1).to_f32());
+        assert_eq!(buf_l[5], 0.0);
+    }
 }
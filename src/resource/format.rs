@@ -0,0 +1,676 @@
+//! Conversion between sample formats.
+
+use super::{DecodedAudio, DecodedAudioType};
+use crate::dither::{ConversionOptions, Quantizer};
+use crate::sample::{Sample, I24, U24};
+
+/// The format of the samples stored in a [`DecodedAudioType`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SampleFormat {
+    U8,
+    U16,
+    U24,
+    S8,
+    S16,
+    S24,
+    F32,
+    F64,
+    #[cfg(feature = "f16")]
+    F16,
+}
+
+impl SampleFormat {
+    /// The number of bits in a sample.
+    pub fn bits(&self) -> u32 {
+        match self {
+            SampleFormat::U8 | SampleFormat::S8 => 8,
+            SampleFormat::U16 | SampleFormat::S16 => 16,
+            #[cfg(feature = "f16")]
+            SampleFormat::F16 => 16,
+            SampleFormat::U24 | SampleFormat::S24 => 24,
+            SampleFormat::F32 => 32,
+            SampleFormat::F64 => 64,
+        }
+    }
+
+    /// The number of bytes one sample takes up in memory.
+    pub fn bytes(&self) -> usize {
+        self.bits() as usize / 8
+    }
+
+    /// Whether this is a floating point format.
+    pub fn is_float(&self) -> bool {
+        match self {
+            SampleFormat::F32 | SampleFormat::F64 => true,
+            #[cfg(feature = "f16")]
+            SampleFormat::F16 => true,
+            _ => false,
+        }
+    }
+}
+
+/// The sample format a resource is stored in when it is loaded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageFormat {
+    /// Store the samples in the format of the source.
+    #[default]
+    Native,
+    /// Store the samples in the format of the source, unless it has more bits
+    /// than the given format, in which case they are converted to it.
+    AtMost(SampleFormat),
+    /// Always store the samples in the given format.
+    Exact(SampleFormat),
+}
+
+impl StorageFormat {
+    /// Returns the format that samples in the `native` format should be
+    /// converted to, or `None` if they should be stored as they are.
+    pub(crate) fn target(&self, native: SampleFormat) -> Option<SampleFormat> {
+        let format = match *self {
+            StorageFormat::Native => return None,
+            StorageFormat::AtMost(format) if native.bits() <= format.bits() => return None,
+            StorageFormat::AtMost(format) | StorageFormat::Exact(format) => format,
+        };
+
+        (format != native).then_some(format)
+    }
+}
+
+/// Whether converting from `from` to `to` loses resolution, so that it should
+/// be dithered.
+fn is_narrowing(from: SampleFormat, to: SampleFormat) -> bool {
+    !to.is_float() && (from.is_float() || from.bits() > to.bits())
+}
+
+impl DecodedAudioType {
+    /// The format of the samples.
+    pub fn format(&self) -> SampleFormat {
+        with_channels!(self, pcm => format_of(pcm))
+    }
+
+    /// Detect the number of bits that the samples actually use.
+    ///
+    /// For integer formats, this is the bit depth minus the number of low bits
+    /// that are zero in every sample. For floating point formats, this is the
+    /// smallest of 8, 16 and 24 bits where every sample is an exact `bits`-bit
+    /// value, or the bit depth of the format if there is none. A sample is an
+    /// exact `bits`-bit value if it is a multiple of `2^(1 - bits)` in the range
+    /// `[-1.0, 1.0)`, or if it is exactly what a `bits`-bit signed integer is
+    /// converted to by this crate (a multiple of `1 / (2^(bits - 1) - 1)`).
+    /// Silence is `0` bits in both signed integer and floating point formats.
+    pub fn effective_bits(&self) -> u32 {
+        with_channels!(self, pcm => effective_bits(pcm))
+    }
+
+    /// Returns the samples repacked into the smallest format that holds their
+    /// `effective_bits`, or `None` if they already are.
+    ///
+    /// Integer samples drop their unused low bits, which keeps every bit of
+    /// the samples, although each integer format reads back with its own full
+    /// scale. Floating point samples are stored as signed integers, but only
+    /// if every sample reads back as exactly the same value (or else this
+    /// returns `None`). See [`LoadOptions::detect_bit_depth`].
+    ///
+    /// [`LoadOptions::detect_bit_depth`]: crate::LoadOptions::detect_bit_depth
+    pub(crate) fn repack(&self, effective_bits: u32) -> Option<DecodedAudioType> {
+        with_channels!(self, pcm => repack(pcm, effective_bits))
+    }
+}
+
+impl DecodedAudio {
+    /// The format of the samples stored in this resource.
+    pub fn format(&self) -> SampleFormat {
+        self.resource_type.format()
+    }
+
+    /// Convert this resource to the given sample format.
+    ///
+    /// Samples are rounded to the nearest value of the new format and
+    /// clipped. When the new format has fewer bits than the current one (or
+    /// the current format is floating point and the new one is not), dither
+    /// and noise shaping are applied as configured in `options`.
+    ///
+    /// If the resource is already in the given format, then this returns a
+    /// copy that shares the same storage.
+    pub fn convert_to(&self, format: SampleFormat, options: &ConversionOptions) -> DecodedAudio {
+        let current = self.format();
+        if current == format {
+            return self.clone();
+        }
+
+        let range = self.view();
+        let mut converter = FormatConverter::new(
+            current,
+            format,
+            options,
+            self.stored_channels(),
+            self.frames,
+        );
+        with_channels!(&*self.resource_type, pcm => {
+            let pcm: Vec<&[_]> = pcm.iter().map(|ch| &ch[range.clone()]).collect();
+            converter.push(&pcm);
+        });
+
+        self.with_storage(converter.finish())
+    }
+}
+
+fn format_of<S: Sample>(_: &[Vec<S>]) -> SampleFormat {
+    S::FORMAT
+}
+
+/// The mapping from the range `[-1.0, 1.0]` to an integer format.
+struct IntFormat {
+    scale: f64,
+    offset: f64,
+    min: f64,
+    max: f64,
+}
+
+const U8_FORMAT: IntFormat = unsigned(u8::MAX as f64);
+const U16_FORMAT: IntFormat = unsigned(u16::MAX as f64);
+const U24_FORMAT: IntFormat = unsigned(16_777_215.0);
+const S8_FORMAT: IntFormat = signed(i8::MAX as f64);
+const S16_FORMAT: IntFormat = signed(i16::MAX as f64);
+const S24_FORMAT: IntFormat = signed(8_388_607.0);
+
+impl IntFormat {
+    /// The mapping for `format`, or `None` if it is a floating point format.
+    fn of(format: SampleFormat) -> Option<&'static IntFormat> {
+        match format {
+            SampleFormat::U8 => Some(&U8_FORMAT),
+            SampleFormat::U16 => Some(&U16_FORMAT),
+            SampleFormat::U24 => Some(&U24_FORMAT),
+            SampleFormat::S8 => Some(&S8_FORMAT),
+            SampleFormat::S16 => Some(&S16_FORMAT),
+            SampleFormat::S24 => Some(&S24_FORMAT),
+            _ => None,
+        }
+    }
+
+    /// The integer value of a sample of this format.
+    fn to_int<S: Sample>(&self, s: S) -> i64 {
+        (s.to_f64() * self.scale + self.offset).round() as i64
+    }
+}
+
+const fn unsigned(max: f64) -> IntFormat {
+    IntFormat {
+        scale: max / 2.0,
+        offset: max / 2.0,
+        min: 0.0,
+        max,
+    }
+}
+
+const fn signed(max: f64) -> IntFormat {
+    IntFormat {
+        scale: max,
+        offset: 0.0,
+        min: -max - 1.0,
+        max,
+    }
+}
+
+/// Converts samples to another format in chunks, keeping the dither and noise
+/// shaping state of each channel from one chunk to the next.
+pub(crate) struct FormatConverter {
+    out: DecodedAudioType,
+    quantizers: Vec<Quantizer>,
+}
+
+impl FormatConverter {
+    /// Dither and noise shaping are only applied if converting from `from`
+    /// to `to` loses resolution.
+    pub(crate) fn new(
+        from: SampleFormat,
+        to: SampleFormat,
+        options: &ConversionOptions,
+        channels: usize,
+        capacity: usize,
+    ) -> Self {
+        let options = if is_narrowing(from, to) {
+            *options
+        } else {
+            ConversionOptions::default()
+        };
+
+        let out = match to {
+            SampleFormat::U8 => DecodedAudioType::U8(empty_channels(channels, capacity)),
+            SampleFormat::U16 => DecodedAudioType::U16(empty_channels(channels, capacity)),
+            SampleFormat::U24 => DecodedAudioType::U24(empty_channels(channels, capacity)),
+            SampleFormat::S8 => DecodedAudioType::S8(empty_channels(channels, capacity)),
+            SampleFormat::S16 => DecodedAudioType::S16(empty_channels(channels, capacity)),
+            SampleFormat::S24 => DecodedAudioType::S24(empty_channels(channels, capacity)),
+            SampleFormat::F32 => DecodedAudioType::F32(empty_channels(channels, capacity)),
+            SampleFormat::F64 => DecodedAudioType::F64(empty_channels(channels, capacity)),
+            #[cfg(feature = "f16")]
+            SampleFormat::F16 => DecodedAudioType::F16(empty_channels(channels, capacity)),
+        };
+
+        Self {
+            out,
+            quantizers: (0..channels)
+                .map(|channel| Quantizer::new(&options, channel))
+                .collect(),
+        }
+    }
+
+    /// Convert the samples and append them to the end of each channel.
+    pub(crate) fn push<S: Sample>(&mut self, pcm: &[&[S]]) {
+        let q = &mut self.quantizers;
+
+        match &mut self.out {
+            DecodedAudioType::U8(out) => quantize(pcm, out, q, &U8_FORMAT, |v| v as u8),
+            DecodedAudioType::U16(out) => quantize(pcm, out, q, &U16_FORMAT, |v| v as u16),
+            DecodedAudioType::U24(out) => {
+                quantize(pcm, out, q, &U24_FORMAT, |v| U24(int_to_ne_bytes(v as i32)))
+            }
+            DecodedAudioType::S8(out) => quantize(pcm, out, q, &S8_FORMAT, |v| v as i8),
+            DecodedAudioType::S16(out) => quantize(pcm, out, q, &S16_FORMAT, |v| v as i16),
+            DecodedAudioType::S24(out) => {
+                quantize(pcm, out, q, &S24_FORMAT, |v| I24(int_to_ne_bytes(v as i32)))
+            }
+            DecodedAudioType::F32(out) => extend_float(pcm, out, |v| v as f32),
+            DecodedAudioType::F64(out) => extend_float(pcm, out, |v| v),
+            #[cfg(feature = "f16")]
+            DecodedAudioType::F16(out) => extend_float(pcm, out, half::f16::from_f64),
+        }
+    }
+
+    /// The format the samples are converted to.
+    #[cfg(feature = "resampler")]
+    pub(crate) fn format(&self) -> SampleFormat {
+        self.out.format()
+    }
+
+    /// The number of frames converted so far.
+    #[cfg(feature = "resampler")]
+    pub(crate) fn frames(&self) -> usize {
+        with_channels!(&self.out, pcm => pcm.first().map_or(0, Vec::len))
+    }
+
+    /// Drop the converted frames after `frames`.
+    #[cfg(feature = "resampler")]
+    pub(crate) fn truncate(&mut self, frames: usize) {
+        with_channels!(&mut self.out, pcm => {
+            for ch in pcm.iter_mut() {
+                ch.truncate(frames);
+            }
+        });
+    }
+
+    pub(crate) fn finish(mut self) -> DecodedAudioType {
+        with_channels!(&mut self.out, pcm => {
+            for ch in pcm.iter_mut() {
+                ch.shrink_to_fit();
+            }
+        });
+
+        self.out
+    }
+}
+
+fn empty_channels<T>(channels: usize, capacity: usize) -> Vec<Vec<T>> {
+    (0..channels)
+        .map(|_| Vec::with_capacity(capacity))
+        .collect()
+}
+
+fn quantize<S: Sample, T>(
+    pcm: &[&[S]],
+    out: &mut [Vec<T>],
+    quantizers: &mut [Quantizer],
+    int_format: &IntFormat,
+    pack: impl Fn(f64) -> T,
+) {
+    for ((ch, out_ch), quantizer) in pcm.iter().zip(out).zip(quantizers) {
+        out_ch.extend(ch.iter().map(|&s| {
+            let v = s.to_f64() * int_format.scale + int_format.offset;
+            pack(quantizer.quantize(v).clamp(int_format.min, int_format.max))
+        }));
+    }
+}
+
+fn extend_float<S: Sample, T>(pcm: &[&[S]], out: &mut [Vec<T>], from_f64: impl Fn(f64) -> T) {
+    for (ch, out_ch) in pcm.iter().zip(out) {
+        out_ch.extend(ch.iter().map(|s| from_f64(s.to_f64())));
+    }
+}
+
+/// The bit depths that samples can be repacked into.
+const PACKED_BITS: [u32; 3] = [8, 16, 24];
+
+fn effective_bits<S: Sample>(pcm: &[Vec<S>]) -> u32 {
+    match IntFormat::of(S::FORMAT) {
+        Some(int_format) => {
+            let used = pcm
+                .iter()
+                .flatten()
+                .fold(0, |used, &s| used | int_format.to_int(s));
+
+            S::BIT_DEPTH - used.trailing_zeros().min(S::BIT_DEPTH)
+        }
+        None if pcm.iter().flatten().all(|s| s.to_f64() == 0.0) => 0,
+        None => PACKED_BITS
+            .into_iter()
+            .filter(|&bits| bits < S::BIT_DEPTH)
+            .find(|&bits| {
+                pcm.iter().flatten().all(|&s| is_power_of_two_int(s, bits))
+                    || pcm.iter().flatten().all(|&s| is_scaled_int(s, bits))
+            })
+            .unwrap_or(S::BIT_DEPTH),
+    }
+}
+
+/// Whether `s` is a multiple of `2^(1 - bits)` in the range `[-1.0, 1.0)`.
+fn is_power_of_two_int<S: Sample>(s: S, bits: u32) -> bool {
+    let half_range = f64::from(1u32 << (bits - 1));
+    let v = s.to_f64() * half_range;
+
+    v.fract() == 0.0 && v >= -half_range && v < half_range
+}
+
+/// Whether `s` is exactly what a `bits`-bit signed integer is converted to.
+fn is_scaled_int<S: Sample>(s: S, bits: u32) -> bool {
+    let v = scaled_to_int(s, bits);
+
+    match bits {
+        8 => reads_back_as(v as i8, s),
+        16 => reads_back_as(v as i16, s),
+        _ => reads_back_as(I24(int_to_ne_bytes(v as i32)), s),
+    }
+}
+
+/// The nearest `bits`-bit signed integer to `s`, with the scaling used to
+/// convert signed integers to floating point.
+fn scaled_to_int<S: Sample>(s: S, bits: u32) -> i64 {
+    let max = f64::from((1u32 << (bits - 1)) - 1);
+
+    (s.to_f64() * max).round() as i64
+}
+
+/// Whether `packed` converts to exactly the same `f32` and `f64` values as
+/// `s`.
+fn reads_back_as<S: Sample, T: Sample>(packed: T, s: S) -> bool {
+    packed.to_f32().to_bits() == s.to_f32().to_bits()
+        && packed.to_f64().to_bits() == s.to_f64().to_bits()
+}
+
+fn repack<S: Sample>(pcm: &[Vec<S>], effective_bits: u32) -> Option<DecodedAudioType> {
+    let bits = PACKED_BITS
+        .into_iter()
+        .find(|&bits| bits >= effective_bits)?;
+    if bits >= S::BIT_DEPTH {
+        return None;
+    }
+
+    // Integer samples keep their signedness and drop their unused low bits,
+    // while floating point samples are stored as signed integers.
+    let int_format = IntFormat::of(S::FORMAT);
+    let to_int = |s: S| match int_format {
+        Some(int_format) => int_format.to_int(s) >> (S::BIT_DEPTH - bits),
+        None => scaled_to_int(s, bits),
+    };
+    let exact = int_format.is_none();
+
+    match (S::FORMAT, bits) {
+        (SampleFormat::U16 | SampleFormat::U24, 8) => pack(pcm, to_int, |v| v as u8, exact),
+        (SampleFormat::U24, _) => pack(pcm, to_int, |v| v as u16, exact),
+        (_, 8) => pack(pcm, to_int, |v| v as i8, exact),
+        (_, 16) => pack(pcm, to_int, |v| v as i16, exact),
+        _ => pack(pcm, to_int, |v| I24(int_to_ne_bytes(v as i32)), exact),
+    }
+}
+
+/// Pack the samples into the new format. If `exact` is `true`, then this
+/// returns `None` if any of them would not read back as exactly the same
+/// value.
+fn pack<S: Sample, T: Sample>(
+    pcm: &[Vec<S>],
+    to_int: impl Fn(S) -> i64,
+    from_int: impl Fn(i64) -> T,
+    exact: bool,
+) -> Option<DecodedAudioType> {
+    let channels = pcm
+        .iter()
+        .map(|ch| {
+            ch.iter()
+                .map(|&s| {
+                    let packed = from_int(to_int(s));
+                    (!exact || reads_back_as(packed, s)).then_some(packed)
+                })
+                .collect::<Option<Vec<T>>>()
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(T::into_audio_type(channels))
+}
+
+/// Returns the lower three bytes of `v` in native endian.
+fn int_to_ne_bytes(v: i32) -> [u8; 3] {
+    #[cfg(target_endian = "little")]
+    {
+        let b = v.to_le_bytes();
+        [b[0], b[1], b[2]]
+    }
+
+    #[cfg(target_endian = "big")]
+    {
+        let b = v.to_be_bytes();
+        [b[1], b[2], b[3]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dither::NoiseShaping;
+
+    #[test]
+    fn convert_formats() {
+        let test_pcm = DecodedAudio::new(
+            DecodedAudioType::F32(vec![vec![-2.0, -1.0, -0.5, 0.0, 0.5, 1.0, 2.0]]),
+            44100,
+        );
+
+        let s16 = test_pcm.convert_to(SampleFormat::S16, &ConversionOptions::default());
+        match s16.storage() {
+            DecodedAudioType::S16(pcm) => assert_eq!(
+                pcm[0],
+                vec![i16::MIN, -32767, -16384, 0, 16384, 32767, 32767]
+            ),
+            _ => unreachable!(),
+        }
+
+        let u8 = test_pcm.convert_to(SampleFormat::U8, &ConversionOptions::default());
+        match u8.storage() {
+            DecodedAudioType::U8(pcm) => assert_eq!(pcm[0], vec![0, 0, 64, 128, 191, 255, 255]),
+            _ => unreachable!(),
+        }
+
+        // Widening and narrowing back again is lossless.
+        for format in [
+            SampleFormat::U16,
+            SampleFormat::U24,
+            SampleFormat::S24,
+            SampleFormat::F32,
+            SampleFormat::F64,
+        ] {
+            let wide = u8.convert_to(format, &ConversionOptions::default());
+            assert_eq!(wide.format(), format);
+
+            let narrow = wide.convert_to(SampleFormat::U8, &ConversionOptions::default());
+            assert_eq!(narrow.storage().format(), SampleFormat::U8);
+            match (narrow.storage(), u8.storage()) {
+                (DecodedAudioType::U8(a), DecodedAudioType::U8(b)) => assert_eq!(a, b),
+                _ => unreachable!(),
+            }
+        }
+
+        // Dither and noise shaping only move samples by a few steps.
+        let options = ConversionOptions {
+            dither: true,
+            noise_shaping: NoiseShaping::SecondOrder,
+            seed: 1,
+        };
+        let sine: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let test_pcm = DecodedAudio::new(DecodedAudioType::F32(vec![sine.clone()]), 44100);
+        match test_pcm.convert_to(SampleFormat::S8, &options).storage() {
+            DecodedAudioType::S8(pcm) => {
+                for (&q, &s) in pcm[0].iter().zip(sine.iter()) {
+                    assert!((f32::from(q) - s * 127.0).abs() <= 6.0);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn storage_format() {
+        let storage = StorageFormat::AtMost(SampleFormat::S16);
+        assert_eq!(storage.target(SampleFormat::S24), Some(SampleFormat::S16));
+        assert_eq!(storage.target(SampleFormat::F32), Some(SampleFormat::S16));
+        assert_eq!(storage.target(SampleFormat::U8), None);
+        assert_eq!(StorageFormat::Native.target(SampleFormat::F64), None);
+        assert_eq!(
+            StorageFormat::Exact(SampleFormat::F32).target(SampleFormat::U8),
+            Some(SampleFormat::F32)
+        );
+        assert_eq!(
+            StorageFormat::Exact(SampleFormat::F32).target(SampleFormat::F32),
+            None
+        );
+
+        // Converting in chunks gives the same result as converting all at
+        // once, including the dither and noise shaping state.
+        let options = ConversionOptions {
+            dither: true,
+            noise_shaping: NoiseShaping::FirstOrder,
+            seed: 7,
+        };
+        let sine: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let whole = DecodedAudio::new(DecodedAudioType::F32(vec![sine.clone()]), 44100)
+            .convert_to(SampleFormat::S16, &options);
+
+        let mut converter =
+            FormatConverter::new(SampleFormat::F32, SampleFormat::S16, &options, 1, 0);
+        for chunk in sine.chunks(300) {
+            converter.push(&[chunk]);
+        }
+        match (converter.finish(), whole.storage()) {
+            (DecodedAudioType::S16(a), DecodedAudioType::S16(b)) => assert_eq!(&a, b),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn detect_bit_depth() {
+        let padded: Vec<I24> = [0, 1, -1, 32767, -32768]
+            .iter()
+            .map(|&v: &i32| I24(int_to_ne_bytes(v << 8)))
+            .collect();
+        let pcm = DecodedAudioType::S24(vec![padded]);
+        assert_eq!(pcm.effective_bits(), 16);
+        match pcm.repack(16) {
+            Some(DecodedAudioType::S16(pcm)) => {
+                assert_eq!(pcm[0], vec![0, 1, -1, 32767, -32768])
+            }
+            _ => unreachable!(),
+        }
+
+        let pcm = DecodedAudioType::U16(vec![vec![0x8000, 0x0100, 0xff00]]);
+        assert_eq!(pcm.effective_bits(), 8);
+        match pcm.repack(8) {
+            Some(DecodedAudioType::U8(pcm)) => assert_eq!(pcm[0], vec![0x80, 0x01, 0xff]),
+            _ => unreachable!(),
+        }
+
+        // Silence reads back the same in every signed format.
+        let pcm = DecodedAudioType::S24(vec![vec![I24([0; 3]); 4]]);
+        assert_eq!(pcm.effective_bits(), 0);
+        match pcm.repack(0) {
+            Some(DecodedAudioType::S8(pcm)) => assert_eq!(pcm[0], vec![0; 4]),
+            _ => unreachable!(),
+        }
+
+        // Floats that are exact 16-bit values with the scaling of this crate
+        // are stored as those values.
+        let scaled: Vec<f32> = [0, 16384, -32768, 32767, -1]
+            .into_iter()
+            .map(crate::convert::pcm_i16_to_f32)
+            .collect();
+        let pcm = DecodedAudioType::F32(vec![scaled]);
+        assert_eq!(pcm.effective_bits(), 16);
+        match pcm.repack(16) {
+            Some(DecodedAudioType::S16(pcm)) => {
+                assert_eq!(pcm[0], vec![0, 16384, -32768, 32767, -1])
+            }
+            _ => unreachable!(),
+        }
+
+        // Floats that are multiples of `2^-15` are detected as 16-bit, but
+        // are kept as they are since 16-bit integers read back differently.
+        let pcm = DecodedAudioType::F32(vec![vec![0.0, 0.5, -1.0, 32767.0 / 32768.0]]);
+        assert_eq!(pcm.effective_bits(), 16);
+        assert!(pcm.repack(16).is_none());
+
+        // Silence uses no bits.
+        let pcm = DecodedAudioType::F32(vec![vec![0.0; 4]]);
+        assert_eq!(pcm.effective_bits(), 0);
+        assert!(matches!(pcm.repack(0), Some(DecodedAudioType::S8(_))));
+
+        // Samples that use every bit are left as they are.
+        let pcm = DecodedAudioType::F32(vec![vec![0.0, 0.1, 1.0]]);
+        assert_eq!(pcm.effective_bits(), 32);
+        assert!(pcm.repack(32).is_none());
+        let pcm = DecodedAudioType::S16(vec![vec![0, 1, -3]]);
+        assert_eq!(pcm.effective_bits(), 16);
+        assert!(pcm.repack(16).is_none());
+    }
+
+    #[test]
+    fn repack_is_lossless() {
+        fn fill(pcm: &DecodedAudio) -> Vec<f32> {
+            let mut buf = vec![0.0; pcm.frames()];
+            pcm.fill_channel(0, 0, &mut buf).unwrap();
+            buf
+        }
+
+        // Floats are only repacked when they read back bit for bit.
+        let scaled: Vec<f32> = (-32768..=32767)
+            .step_by(7)
+            .chain([32767])
+            .map(crate::convert::pcm_i16_to_f32)
+            .collect();
+
+        for (pcm, format) in [
+            (DecodedAudioType::F32(vec![scaled]), SampleFormat::S16),
+            (DecodedAudioType::F32(vec![vec![0.0; 4]]), SampleFormat::S8),
+        ] {
+            let mut pcm = DecodedAudio::new(pcm, 44100);
+            let before: Vec<u32> = fill(&pcm).iter().map(|s| s.to_bits()).collect();
+
+            pcm.pack_to_detected_bit_depth();
+            assert_eq!(pcm.format(), format);
+            let after: Vec<u32> = fill(&pcm).iter().map(|s| s.to_bits()).collect();
+            assert_eq!(after, before);
+        }
+
+        // Padded integers keep every bit, but read back with the full scale
+        // of the narrower format.
+        let padded: Vec<I24> = [0, 1, -1, 32767, -32768]
+            .iter()
+            .map(|&v: &i32| I24(int_to_ne_bytes(v << 8)))
+            .collect();
+        let mut pcm = DecodedAudio::new(DecodedAudioType::S24(vec![padded]), 44100);
+        let before = fill(&pcm);
+
+        pcm.pack_to_detected_bit_depth();
+        assert_eq!(pcm.format(), SampleFormat::S16);
+        assert_eq!(pcm.detected_bit_depth(), Some(16));
+        for (a, b) in fill(&pcm).iter().zip(&before) {
+            assert!((a - b).abs() <= b.abs() * 4e-5);
+        }
+    }
+}
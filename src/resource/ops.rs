@@ -0,0 +1,520 @@
+//! Destructive editing operations on decoded resources.
+
+use super::{DecodedAudio, DecodedAudioF32, DecodedAudioType};
+use crate::channel::ChannelLayout;
+use crate::error::EditError;
+use crate::fade::Fade;
+use crate::sample::Sample;
+
+impl DecodedAudio {
+    /// Keep only the frames in the range `[start, end)`.
+    pub fn trim(&mut self, start: usize, end: usize) -> Result<(), EditError> {
+        check_range(start, end, self.frames)?;
+
+        with_channels!(self.storage_mut(), pcm => trim_channels(pcm, start, end));
+        self.frames = end - start;
+
+        Ok(())
+    }
+
+    /// Split this resource in two at the given frame.
+    ///
+    /// This resource keeps the frames in the range `[0, frame)`, and the
+    /// frames in the range `[frame, frames)` are returned as a new resource.
+    pub fn split_off(&mut self, frame: usize) -> Result<DecodedAudio, EditError> {
+        check_frame(frame, self.frames)?;
+
+        let tail = map_channels!(self.storage_mut(), pcm => {
+            pcm.iter_mut().map(|ch| ch.split_off(frame)).collect()
+        });
+
+        let tail = self.with_storage(tail);
+
+        self.frames = frame;
+
+        Ok(tail)
+    }
+
+    /// Append the frames of `other` to the end of this resource.
+    ///
+    /// Both resources must have the same sample rate, number of channels,
+    /// and sample format.
+    pub fn append(&mut self, mut other: DecodedAudio) -> Result<(), EditError> {
+        check_sample_rate(self.sample_rate, other.sample_rate)?;
+        if self.channels != other.channels {
+            return Err(EditError::ChannelCountMismatch {
+                expected: self.channels,
+                got: other.channels,
+            });
+        }
+        check_format(self, &other)?;
+
+        let other_frames = other.frames;
+
+        if self.stored_channels() != other.stored_channels() {
+            self.expand_channels();
+            other.expand_channels();
+        }
+
+        with_channels_pair!(self.storage_mut(), other.into_storage(), (a, b) => {
+            for (a_ch, mut b_ch) in a.iter_mut().zip(b) {
+                a_ch.append(&mut b_ch);
+            }
+        });
+        self.frames += other_frames;
+        self.detected_bit_depth = None;
+
+        Ok(())
+    }
+
+    /// Insert the given number of frames of silence before `frame`.
+    pub fn insert_silence(&mut self, frame: usize, frames: usize) -> Result<(), EditError> {
+        check_frame(frame, self.frames)?;
+
+        with_channels!(self.storage_mut(), pcm => insert_channels(pcm, frame, frames));
+        self.frames += frames;
+
+        Ok(())
+    }
+
+    /// Multiply every sample by the given gain.
+    ///
+    /// Integer samples are rounded to the nearest value and clipped.
+    pub fn apply_gain(&mut self, gain: f32) {
+        self.detected_bit_depth = None;
+
+        if let DecodedAudioType::F64(pcm) = self.storage_mut() {
+            // Keep the full precision of the samples.
+            for ch in pcm.iter_mut() {
+                for s in ch.iter_mut() {
+                    *s *= f64::from(gain);
+                }
+            }
+            return;
+        }
+
+        self.map_samples(|_, s| s * gain);
+    }
+
+    /// Fade in from silence over the first `fade.frames` frames.
+    pub fn fade_in(&mut self, fade: Fade) {
+        let frames = fade.frames.min(self.frames);
+
+        self.map_samples(|frame, s| {
+            if frame < frames {
+                s * fade.gain_at(frame)
+            } else {
+                s
+            }
+        });
+    }
+
+    /// Fade out to silence over the last `fade.frames` frames.
+    pub fn fade_out(&mut self, fade: Fade) {
+        let len = self.frames;
+        let frames = fade.frames.min(len);
+
+        self.map_samples(|frame, s| {
+            if frame >= len - frames {
+                s * fade.gain_at(len - frame - 1)
+            } else {
+                s
+            }
+        });
+    }
+
+    /// Reverse the order of the frames.
+    pub fn reverse(&mut self) {
+        with_channels!(self.storage_mut(), pcm => {
+            for ch in pcm.iter_mut() {
+                ch.reverse();
+            }
+        });
+    }
+
+    /// Copy the given channel into a new single-channel resource.
+    pub fn extract_channel(&self, channel: usize) -> Result<DecodedAudio, EditError> {
+        check_channel(channel, self.channels)?;
+
+        let range = self.view();
+        let stored = self.stored_channel(channel);
+        let resource_type =
+            map_channels!(&*self.resource_type, pcm => vec![pcm[stored][range].to_vec()]);
+        let layout = ChannelLayout::new(vec![self.layout.positions()[channel]]);
+
+        Ok(DecodedAudio::new_with_layout(
+            resource_type,
+            self.sample_rate,
+            layout,
+        ))
+    }
+
+    /// Add the channels of `other` after the channels of this resource.
+    ///
+    /// Both resources must have the same sample rate, number of frames, and
+    /// sample format.
+    pub fn merge_channels(&mut self, other: DecodedAudio) -> Result<(), EditError> {
+        check_sample_rate(self.sample_rate, other.sample_rate)?;
+        check_frame_count(self.frames, other.frames)?;
+        check_format(self, &other)?;
+
+        let other_channels = other.channels;
+        let layout = merge_layouts(&self.layout, &other.layout);
+
+        self.expand_channels();
+
+        with_channels_pair!(self.storage_mut(), other.into_raw(), (a, b) => {
+            a.extend(b)
+        });
+
+        self.channels += other_channels;
+        self.detected_bit_depth = None;
+        self.layout = layout;
+        self.source_layout = self.layout.clone();
+
+        Ok(())
+    }
+
+    /// Apply `f` to every sample as an `f32`, along with its frame, converting
+    /// back to the stored format afterwards.
+    fn map_samples(&mut self, f: impl Fn(usize, f32) -> f32) {
+        self.detected_bit_depth = None;
+        with_channels!(self.storage_mut(), pcm => map_channels(pcm, &f));
+    }
+}
+
+impl DecodedAudioF32 {
+    /// Keep only the frames in the range `[start, end)`.
+    pub fn trim(&mut self, start: usize, end: usize) -> Result<(), EditError> {
+        check_range(start, end, self.frames())?;
+
+        trim_channels(&mut self.data, start, end);
+
+        Ok(())
+    }
+
+    /// Split this resource in two at the given frame.
+    ///
+    /// This resource keeps the frames in the range `[0, frame)`, and the
+    /// frames in the range `[frame, frames)` are returned as a new resource.
+    pub fn split_off(&mut self, frame: usize) -> Result<DecodedAudioF32, EditError> {
+        check_frame(frame, self.frames())?;
+
+        let tail = self.data.iter_mut().map(|ch| ch.split_off(frame)).collect();
+
+        Ok(DecodedAudioF32 {
+            data: tail,
+            sample_rate: self.sample_rate,
+            layout: self.layout().into_owned(),
+            source_layout: self.source_layout.clone(),
+            source_order: self.source_order,
+        })
+    }
+
+    /// Append the frames of `other` to the end of this resource.
+    ///
+    /// Both resources must have the same sample rate and number of channels.
+    pub fn append(&mut self, other: DecodedAudioF32) -> Result<(), EditError> {
+        check_sample_rate(self.sample_rate, other.sample_rate)?;
+        if self.channels() != other.channels() {
+            return Err(EditError::ChannelCountMismatch {
+                expected: self.channels(),
+                got: other.channels(),
+            });
+        }
+
+        for (a_ch, mut b_ch) in self.data.iter_mut().zip(other.data) {
+            a_ch.append(&mut b_ch);
+        }
+
+        Ok(())
+    }
+
+    /// Insert the given number of frames of silence before `frame`.
+    pub fn insert_silence(&mut self, frame: usize, frames: usize) -> Result<(), EditError> {
+        check_frame(frame, self.frames())?;
+
+        insert_channels(&mut self.data, frame, frames);
+
+        Ok(())
+    }
+
+    /// Multiply every sample by the given gain.
+    pub fn apply_gain(&mut self, gain: f32) {
+        map_channels(&mut self.data, &|_, s| s * gain);
+    }
+
+    /// Fade in from silence over the first `fade.frames` frames.
+    pub fn fade_in(&mut self, fade: Fade) {
+        let frames = fade.frames.min(self.frames());
+
+        for ch in self.data.iter_mut() {
+            for (i, s) in ch[0..frames].iter_mut().enumerate() {
+                *s *= fade.gain_at(i);
+            }
+        }
+    }
+
+    /// Fade out to silence over the last `fade.frames` frames.
+    pub fn fade_out(&mut self, fade: Fade) {
+        let len = self.frames();
+        let frames = fade.frames.min(len);
+
+        for ch in self.data.iter_mut() {
+            for (i, s) in ch[len - frames..].iter_mut().rev().enumerate() {
+                *s *= fade.gain_at(i);
+            }
+        }
+    }
+
+    /// Reverse the order of the frames.
+    pub fn reverse(&mut self) {
+        for ch in self.data.iter_mut() {
+            ch.reverse();
+        }
+    }
+
+    /// Copy the given channel into a new single-channel resource.
+    pub fn extract_channel(&self, channel: usize) -> Result<DecodedAudioF32, EditError> {
+        check_channel(channel, self.channels())?;
+
+        let layout = ChannelLayout::new(vec![self.layout().positions()[channel]]);
+
+        Ok(DecodedAudioF32::new_with_layout(
+            vec![self.data[channel].clone()],
+            self.sample_rate,
+            layout,
+        ))
+    }
+
+    /// Add the channels of `other` after the channels of this resource.
+    ///
+    /// Both resources must have the same sample rate and number of frames.
+    pub fn merge_channels(&mut self, other: DecodedAudioF32) -> Result<(), EditError> {
+        check_sample_rate(self.sample_rate, other.sample_rate)?;
+        check_frame_count(self.frames(), other.frames())?;
+
+        let layout = merge_layouts(&self.layout(), &other.layout());
+
+        self.data.extend(other.data);
+        self.layout = layout;
+        self.source_layout = self.layout.clone();
+
+        Ok(())
+    }
+}
+
+fn check_frame(frame: usize, frames: usize) -> Result<(), EditError> {
+    if frame > frames {
+        Err(EditError::FrameOutOfRange { frame, frames })
+    } else {
+        Ok(())
+    }
+}
+
+fn check_range(start: usize, end: usize, frames: usize) -> Result<(), EditError> {
+    if start > end {
+        return Err(EditError::InvalidRange { start, end });
+    }
+
+    check_frame(end, frames)
+}
+
+fn check_channel(channel: usize, channels: usize) -> Result<(), EditError> {
+    if channel >= channels {
+        Err(EditError::ChannelOutOfRange { channel, channels })
+    } else {
+        Ok(())
+    }
+}
+
+fn check_sample_rate(expected: u32, got: u32) -> Result<(), EditError> {
+    if expected != got {
+        Err(EditError::SampleRateMismatch { expected, got })
+    } else {
+        Ok(())
+    }
+}
+
+/// Checked before any storage is touched, so that a failed edit leaves the
+/// resource unchanged.
+fn check_format(a: &DecodedAudio, b: &DecodedAudio) -> Result<(), EditError> {
+    if a.format() != b.format() {
+        Err(EditError::SampleFormatMismatch)
+    } else {
+        Ok(())
+    }
+}
+
+fn check_frame_count(expected: usize, got: usize) -> Result<(), EditError> {
+    if expected != got {
+        Err(EditError::FrameCountMismatch { expected, got })
+    } else {
+        Ok(())
+    }
+}
+
+fn merge_layouts(a: &ChannelLayout, b: &ChannelLayout) -> ChannelLayout {
+    ChannelLayout::new(
+        a.positions()
+            .iter()
+            .chain(b.positions().iter())
+            .copied()
+            .collect(),
+    )
+}
+
+fn trim_channels<T>(pcm: &mut [Vec<T>], start: usize, end: usize) {
+    for ch in pcm.iter_mut() {
+        ch.truncate(end);
+        ch.drain(0..start);
+    }
+}
+
+fn insert_channels<T: Sample>(pcm: &mut [Vec<T>], frame: usize, frames: usize) {
+    for ch in pcm.iter_mut() {
+        ch.splice(frame..frame, std::iter::repeat_n(T::EQUILIBRIUM, frames));
+    }
+}
+
+fn map_channels<T: Sample>(pcm: &mut [Vec<T>], f: &impl Fn(usize, f32) -> f32) {
+    for ch in pcm.iter_mut() {
+        for (frame, s) in ch.iter_mut().enumerate() {
+            *s = T::from_f32(f(frame, s.to_f32()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::ChannelPosition;
+    use crate::fade::FadeCurve;
+
+    #[test]
+    fn destructive_edits() {
+        let mut pcm = DecodedAudio::new(
+            DecodedAudioType::S8(vec![vec![1, 2, 3, 4, 5], vec![-1, -2, -3, -4, -5]]),
+            44100,
+        );
+
+        pcm.trim(1, 4).unwrap();
+        let tail = pcm.split_off(2).unwrap();
+        assert_eq!(pcm.frames(), 2);
+        assert_eq!(tail.frames(), 1);
+
+        pcm.insert_silence(1, 2).unwrap();
+        pcm.append(tail).unwrap();
+        pcm.reverse();
+        pcm.apply_gain(2.0);
+        match pcm.storage() {
+            DecodedAudioType::S8(pcm) => {
+                assert_eq!(pcm[0], vec![8, 6, 0, 0, 4]);
+                assert_eq!(pcm[1], vec![-8, -6, 0, 0, -4]);
+            }
+            _ => unreachable!(),
+        }
+
+        pcm.fade_out(Fade::new(2, FadeCurve::Linear));
+        let right = pcm.extract_channel(1).unwrap();
+        match right.storage() {
+            DecodedAudioType::S8(pcm) => assert_eq!(pcm[0], vec![-8, -6, 0, 0, 0]),
+            _ => unreachable!(),
+        }
+
+        pcm.merge_channels(right).unwrap();
+        assert_eq!(pcm.channels(), 3);
+
+        let other = DecodedAudio::new(DecodedAudioType::S16(vec![vec![0; 5]]), 44100);
+        assert_eq!(
+            pcm.merge_channels(other),
+            Err(EditError::SampleFormatMismatch)
+        );
+
+        // A failed edit leaves collapsed channels and views as they were.
+        let mut collapsed = DecodedAudio::new(DecodedAudioType::S8(vec![vec![1; 5]; 2]), 44100);
+        collapsed.collapse_identical_channels(0.0);
+        let mut view = collapsed.slice(1..3).unwrap();
+        let other = DecodedAudio::new(DecodedAudioType::S16(vec![vec![0; 2]; 2]), 44100);
+        assert_eq!(
+            view.append(other.clone()),
+            Err(EditError::SampleFormatMismatch)
+        );
+        assert_eq!(
+            view.merge_channels(other),
+            Err(EditError::SampleFormatMismatch)
+        );
+        assert_eq!(view.stored_channels(), 1);
+        assert_eq!(view.offset(), 1);
+    }
+
+    #[test]
+    fn edits_clear_detected_bit_depth() {
+        let detected = || {
+            let mut pcm = DecodedAudio::new(DecodedAudioType::S16(vec![vec![256, -512]]), 44100);
+            pcm.pack_to_detected_bit_depth();
+            assert_eq!(pcm.detected_bit_depth(), Some(8));
+            pcm
+        };
+
+        let mut pcm = detected();
+        pcm.apply_gain(0.5);
+        assert_eq!(pcm.detected_bit_depth(), None);
+
+        let mut pcm = detected();
+        pcm.fade_in(Fade::new(1, FadeCurve::Linear));
+        assert_eq!(pcm.detected_bit_depth(), None);
+
+        let mut pcm = detected();
+        pcm.fade_out(Fade::new(1, FadeCurve::Linear));
+        assert_eq!(pcm.detected_bit_depth(), None);
+
+        let mut pcm = detected();
+        pcm.append(pcm.clone()).unwrap();
+        assert_eq!(pcm.detected_bit_depth(), None);
+
+        let mut pcm = detected();
+        pcm.merge_channels(pcm.clone()).unwrap();
+        assert_eq!(pcm.detected_bit_depth(), None);
+
+        // Edits that only move samples keep it.
+        let mut pcm = detected();
+        pcm.reverse();
+        assert_eq!(pcm.detected_bit_depth(), Some(8));
+        let other = DecodedAudio::new(DecodedAudioType::S8(vec![vec![0; 5]]), 48000);
+        assert_eq!(
+            pcm.append(other),
+            Err(EditError::SampleRateMismatch {
+                expected: 44100,
+                got: 48000
+            })
+        );
+        assert_eq!(
+            pcm.trim(3, 2),
+            Err(EditError::InvalidRange { start: 3, end: 2 })
+        );
+    }
+
+    #[test]
+    fn f32_layout_follows_data() {
+        let mut pcm = DecodedAudioF32::new_with_layout(
+            vec![vec![0.0; 4]; 6],
+            44100,
+            ChannelLayout::surround_5_1(),
+        );
+
+        // Channels removed through the public `data` fall back to the default
+        // layout instead of keeping positions for channels that are gone.
+        pcm.data.truncate(2);
+        assert_eq!(*pcm.layout(), ChannelLayout::stereo());
+        let right = pcm.extract_channel(1).unwrap();
+        assert_eq!(right.layout().positions(), [ChannelPosition::FrontRight]);
+
+        pcm.data.push(vec![0.0; 4]);
+        pcm.to_canonical_order();
+        assert_eq!(*pcm.layout(), ChannelLayout::default_for_channels(3));
+
+        let pcm = DecodedAudio::from(pcm);
+        assert_eq!(pcm.layout().channels(), 3);
+    }
+}
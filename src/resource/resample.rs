@@ -0,0 +1,207 @@
+//! Resampling of resources that are already decoded.
+
+use super::{DecodedAudio, DecodedAudioF32, DecodedAudioType};
+use crate::error::LoadError;
+use crate::resample::{ChunkedResampler, ResampleQuality, ResamplerParams, ResamplerRefMut};
+use crate::sample::Sample;
+use crate::SymphoniumLoader;
+
+impl DecodedAudio {
+    /// Resample this resource to the given sample rate, using the resamplers
+    /// cached in `loader`.
+    ///
+    /// The resampled samples are stored as `f32`. The output is exactly as
+    /// long as this resource at the target sample rate, and is not delayed by
+    /// the resampler. If the sample rate already matches, then this returns a
+    /// copy of this resource.
+    pub fn resample(
+        &self,
+        loader: &mut SymphoniumLoader,
+        target_sample_rate: u32,
+        quality: ResampleQuality,
+    ) -> Result<DecodedAudio, LoadError> {
+        self.resample_inner(target_sample_rate, |params| {
+            loader.resamplers.get(
+                quality,
+                params.source_sample_rate,
+                params.target_sample_rate,
+                params.num_channels,
+            )
+        })
+    }
+
+    /// Resample this resource to the given sample rate using a custom
+    /// resampler. See [`DecodedAudio::resample`].
+    pub fn resample_with_resampler<'a>(
+        &self,
+        target_sample_rate: u32,
+        get_resampler: impl FnOnce(ResamplerParams) -> ResamplerRefMut<'a>,
+    ) -> Result<DecodedAudio, LoadError> {
+        self.resample_inner(target_sample_rate, |params| Ok(get_resampler(params)))
+    }
+
+    fn resample_inner<'a>(
+        &self,
+        target_sample_rate: u32,
+        get_resampler: impl FnOnce(ResamplerParams) -> Result<ResamplerRefMut<'a>, LoadError>,
+    ) -> Result<DecodedAudio, LoadError> {
+        if self.sample_rate == target_sample_rate {
+            return Ok(self.clone());
+        }
+
+        // Collapsed channels are all the same, so only the stored channels
+        // need to be resampled.
+        let channels = self.stored_channels();
+        let mut resampler = ChunkedResampler::new(
+            get_resampler(ResamplerParams {
+                num_channels: channels,
+                source_sample_rate: self.sample_rate,
+                target_sample_rate,
+            })?,
+            channels,
+            self.sample_rate,
+            target_sample_rate,
+        )?;
+
+        let mut out = empty_channels(channels, resampler.max_output_frames(self.frames));
+        with_channels!(self.storage(), pcm => resampler.process(
+            self.frames,
+            |ch, start, buf| {
+                let start = self.offset + start;
+                Sample::slice_to_f32(&pcm[ch][start..start + buf.len()], buf);
+            },
+            &mut out,
+        ))?;
+        resampler.finish(&mut out)?;
+
+        let mut resampled = self.with_storage(DecodedAudioType::F32(out));
+        resampled.sample_rate = target_sample_rate;
+        resampled.detected_bit_depth = None;
+
+        Ok(resampled)
+    }
+}
+
+impl DecodedAudioF32 {
+    /// Resample this resource to the given sample rate, using the resamplers
+    /// cached in `loader`.
+    ///
+    /// The output is exactly as long as this resource at the target sample
+    /// rate, and is not delayed by the resampler. If the sample rate already
+    /// matches, then this returns a copy of this resource.
+    pub fn resample(
+        &self,
+        loader: &mut SymphoniumLoader,
+        target_sample_rate: u32,
+        quality: ResampleQuality,
+    ) -> Result<DecodedAudioF32, LoadError> {
+        self.resample_inner(target_sample_rate, |params| {
+            loader.resamplers.get(
+                quality,
+                params.source_sample_rate,
+                params.target_sample_rate,
+                params.num_channels,
+            )
+        })
+    }
+
+    /// Resample this resource to the given sample rate using a custom
+    /// resampler. See [`DecodedAudioF32::resample`].
+    pub fn resample_with_resampler<'a>(
+        &self,
+        target_sample_rate: u32,
+        get_resampler: impl FnOnce(ResamplerParams) -> ResamplerRefMut<'a>,
+    ) -> Result<DecodedAudioF32, LoadError> {
+        self.resample_inner(target_sample_rate, |params| Ok(get_resampler(params)))
+    }
+
+    fn resample_inner<'a>(
+        &self,
+        target_sample_rate: u32,
+        get_resampler: impl FnOnce(ResamplerParams) -> Result<ResamplerRefMut<'a>, LoadError>,
+    ) -> Result<DecodedAudioF32, LoadError> {
+        let data = if self.sample_rate == target_sample_rate {
+            self.data.clone()
+        } else {
+            let channels = self.channels();
+            let mut resampler = ChunkedResampler::new(
+                get_resampler(ResamplerParams {
+                    num_channels: channels,
+                    source_sample_rate: self.sample_rate,
+                    target_sample_rate,
+                })?,
+                channels,
+                self.sample_rate,
+                target_sample_rate,
+            )?;
+
+            let mut out = empty_channels(channels, resampler.max_output_frames(self.frames()));
+            resampler.process(
+                self.frames(),
+                |ch, start, buf| buf.copy_from_slice(&self.data[ch][start..start + buf.len()]),
+                &mut out,
+            )?;
+            resampler.finish(&mut out)?;
+
+            out
+        };
+
+        Ok(DecodedAudioF32 {
+            data,
+            sample_rate: target_sample_rate,
+            layout: self.layout().into_owned(),
+            source_layout: self.source_layout.clone(),
+            source_order: self.source_order,
+        })
+    }
+}
+
+fn empty_channels(channels: usize, capacity: usize) -> Vec<Vec<f32>> {
+    (0..channels)
+        .map(|_| Vec::with_capacity(capacity))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_decoded() {
+        let mut loader = SymphoniumLoader::new();
+
+        let data: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let pcm = DecodedAudioF32::new(vec![data.clone(), data], 44100);
+
+        let resampled = pcm
+            .resample(&mut loader, 48000, ResampleQuality::Normal)
+            .unwrap();
+        assert_eq!(resampled.sample_rate, 48000);
+        assert_eq!(resampled.frames(), 1089);
+
+        // The resampler delay is compensated for, so the output is in phase
+        // with the input.
+        let expected = (500.0f32 * 44100.0 / 48000.0 * 0.01).sin() * 0.5;
+        assert!((resampled.data[0][500] - expected).abs() < 0.01);
+
+        // A native resource is resampled the same way, and keeps its collapsed
+        // channels.
+        let mut native = DecodedAudio::new(DecodedAudioType::F32(pcm.data.clone()), 44100);
+        native.collapse_identical_channels(0.0);
+        let resampled_native = native
+            .resample(&mut loader, 48000, ResampleQuality::Normal)
+            .unwrap();
+        assert_eq!(resampled_native.sample_rate(), 48000);
+        assert_eq!(resampled_native.channels(), 2);
+        assert_eq!(resampled_native.stored_channels(), 1);
+        let DecodedAudioType::F32(out) = resampled_native.storage() else {
+            panic!("expected f32 samples");
+        };
+        assert_eq!(out[0], resampled.data[0]);
+
+        let same = pcm
+            .resample(&mut loader, 44100, ResampleQuality::Normal)
+            .unwrap();
+        assert_eq!(same.data, pcm.data);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::convert::{self, OverflowMode};
+use crate::resource::{DecodedAudioType, SampleFormat};
+
+/// An unsigned 24-bit sample, stored as three bytes in native endian.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct U24(pub [u8; 3]);
+
+/// A signed 24-bit sample, stored as three bytes in native endian.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct I24(pub [u8; 3]);
+
+impl U24 {
+    /// Returns the sample as a `u32` in the range `[0, 2^24)`.
+    pub fn to_u32(self) -> u32 {
+        let [a, b, c] = self.0;
+
+        #[cfg(target_endian = "little")]
+        return u32::from_le_bytes([a, b, c, 0]);
+
+        #[cfg(target_endian = "big")]
+        return u32::from_be_bytes([0, a, b, c]);
+    }
+}
+
+impl I24 {
+    /// Returns the sample as an `i32` in the range `[-2^23, 2^23)`.
+    pub fn to_i32(self) -> i32 {
+        let [a, b, c] = self.0;
+
+        // Place the sample in the upper three bytes and shift it back down
+        // to sign-extend it.
+        #[cfg(target_endian = "little")]
+        return i32::from_le_bytes([0, a, b, c]) >> 8;
+
+        #[cfg(target_endian = "big")]
+        return i32::from_be_bytes([a, b, c, 0]) >> 8;
+    }
+}
+
+macro_rules! impl_bytes_conversion {
+    ($t:ident) => {
+        impl From<[u8; 3]> for $t {
+            fn from(bytes: [u8; 3]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl From<$t> for [u8; 3] {
+            fn from(s: $t) -> Self {
+                s.0
+            }
+        }
+    };
+}
+
+impl_bytes_conversion!(U24);
+impl_bytes_conversion!(I24);
+
+mod sealed {
+    use crate::resource::DecodedAudioType;
+
+    pub trait Sealed: Sized {
+        /// Wrap the channels in the matching variant of `DecodedAudioType`.
+        fn into_audio_type(channels: Vec<Vec<Self>>) -> DecodedAudioType;
+    }
+}
+
+/// A type that samples in a [`DecodedAudioType`] can be stored as.
+///
+/// This trait is sealed, and is implemented for the sample type of every
+/// variant of [`DecodedAudioType`]. Use it together with
+/// [`DecodedAudioType::visit`] and [`DecodedAudioType::map`] to write code
+/// that works with any sample format.
+pub trait Sample: Copy + Send + Sync + 'static + sealed::Sealed {
+    /// The format of this sample type.
+    const FORMAT: SampleFormat;
+    /// The number of bits in a sample.
+    const BIT_DEPTH: u32;
+    /// The number of bytes one sample takes up in memory.
+    const BYTES: usize;
+    /// The value of silence.
+    const EQUILIBRIUM: Self;
+
+    /// Convert this sample to `f32` format in the range `[-1.0, 1.0]`.
+    fn to_f32(self) -> f32;
+
+    /// Convert this sample to `f64` format in the range `[-1.0, 1.0]`.
+    ///
+    /// Unlike [`Sample::to_f32`], this is lossless for every sample type.
+    fn to_f64(self) -> f64 {
+        f64::from(self.to_f32())
+    }
+
+    /// Convert an `f32` sample in the range `[-1.0, 1.0]` to this sample
+    /// type.
+    ///
+    /// Integer samples are rounded to the nearest value, and samples outside
+    /// of the range are clipped.
+    fn from_f32(s: f32) -> Self;
+
+    /// Convert the first `src.len().min(dst.len())` samples in `src` to
+    /// `f32` format.
+    ///
+    /// This uses the SIMD slice conversions in [`convert`] where they exist.
+    fn slice_to_f32(src: &[Self], dst: &mut [f32]) {
+        for (out_s, &s) in dst.iter_mut().zip(src) {
+            *out_s = s.to_f32();
+        }
+    }
+}
+
+/// A function over the channels of a [`DecodedAudioType`] that is generic
+/// over the sample type.
+///
+/// See [`DecodedAudioType::visit`].
+pub trait ChannelVisitor {
+    type Output;
+
+    fn visit<T: Sample>(self, channels: &[Vec<T>]) -> Self::Output;
+}
+
+/// A function that transforms the channels of a [`DecodedAudioType`] without
+/// changing the sample type.
+///
+/// See [`DecodedAudioType::map`].
+pub trait ChannelMapper {
+    fn map<T: Sample>(self, channels: &[Vec<T>]) -> Vec<Vec<T>>;
+}
+
+macro_rules! impl_sample {
+    (
+        $t:ty, $variant:ident, $bits:expr, $equilibrium:expr,
+        $to_f32:expr, $from_f32:expr $(, { $($extra:item)* })?
+    ) => {
+        impl sealed::Sealed for $t {
+            fn into_audio_type(channels: Vec<Vec<Self>>) -> DecodedAudioType {
+                DecodedAudioType::$variant(channels)
+            }
+        }
+
+        impl Sample for $t {
+            const FORMAT: SampleFormat = SampleFormat::$variant;
+            const BIT_DEPTH: u32 = $bits;
+            const BYTES: usize = std::mem::size_of::<$t>();
+            const EQUILIBRIUM: Self = $equilibrium;
+
+            #[inline]
+            fn to_f32(self) -> f32 {
+                $to_f32(self)
+            }
+
+            #[inline]
+            fn from_f32(s: f32) -> Self {
+                $from_f32(s)
+            }
+
+            $($($extra)*)?
+        }
+    };
+}
+
+impl_sample!(
+    u8,
+    U8,
+    8,
+    1 << 7,
+    convert::pcm_u8_to_f32,
+    |s| convert::f32_to_pcm_u8(s, OverflowMode::Saturate),
+    {
+        fn slice_to_f32(src: &[Self], dst: &mut [f32]) {
+            convert::pcm_u8_slice_to_f32(src, dst);
+        }
+    }
+);
+
+impl_sample!(
+    u16,
+    U16,
+    16,
+    1 << 15,
+    convert::pcm_u16_to_f32,
+    |s| convert::f32_to_pcm_u16(s, OverflowMode::Saturate),
+    {
+        fn slice_to_f32(src: &[Self], dst: &mut [f32]) {
+            convert::pcm_u16_slice_to_f32(src, dst);
+        }
+    }
+);
+
+impl_sample!(
+    U24,
+    U24,
+    24,
+    U24(if cfg!(target_endian = "little") {
+        [0, 0, 0x80]
+    } else {
+        [0x80, 0, 0]
+    }),
+    |s: U24| convert::pcm_u24_to_f32_ne(s.0),
+    |s| U24(convert::f32_to_pcm_u24_ne(s, OverflowMode::Saturate)),
+    {
+        fn to_f64(self) -> f64 {
+            f64::from(self.to_u32()) * (2.0 / 16_777_215.0) - 1.0
+        }
+    }
+);
+
+impl_sample!(
+    i8,
+    S8,
+    8,
+    0,
+    convert::pcm_i8_to_f32,
+    |s| convert::f32_to_pcm_i8(s, OverflowMode::Saturate),
+    {
+        fn slice_to_f32(src: &[Self], dst: &mut [f32]) {
+            convert::pcm_i8_slice_to_f32(src, dst);
+        }
+    }
+);
+
+impl_sample!(
+    i16,
+    S16,
+    16,
+    0,
+    convert::pcm_i16_to_f32,
+    |s| convert::f32_to_pcm_i16(s, OverflowMode::Saturate),
+    {
+        fn slice_to_f32(src: &[Self], dst: &mut [f32]) {
+            convert::pcm_i16_slice_to_f32(src, dst);
+        }
+    }
+);
+
+impl_sample!(
+    I24,
+    S24,
+    24,
+    I24([0; 3]),
+    |s: I24| convert::pcm_i24_to_f32_ne(s.0),
+    |s| I24(convert::f32_to_pcm_i24_ne(s, OverflowMode::Saturate)),
+    {
+        fn to_f64(self) -> f64 {
+            f64::from(self.to_i32()) / 8_388_607.0
+        }
+    }
+);
+
+impl_sample!(f32, F32, 32, 0.0, |s| s, |s| s, {
+    fn slice_to_f32(src: &[Self], dst: &mut [f32]) {
+        let len = src.len().min(dst.len());
+        dst[..len].copy_from_slice(&src[..len]);
+    }
+});
+
+impl_sample!(f64, F64, 64, 0.0, |s| s as f32, f64::from, {
+    fn to_f64(self) -> f64 {
+        self
+    }
+});
+
+#[cfg(feature = "f16")]
+impl_sample!(
+    half::f16,
+    F16,
+    16,
+    half::f16::ZERO,
+    half::f16::to_f32,
+    half::f16::from_f32,
+    {
+        fn to_f64(self) -> f64 {
+            half::f16::to_f64(self)
+        }
+
+        fn slice_to_f32(src: &[Self], dst: &mut [f32]) {
+            use half::slice::HalfFloatSliceExt;
+
+            // This uses the F16C/FP16 instructions when the CPU supports them.
+            let len = src.len().min(dst.len());
+            src[..len].convert_to_f32_slice(&mut dst[..len]);
+        }
+    }
+);